@@ -240,14 +240,64 @@ public final class TerracottaAndroidAPI {
      * However, when initializing the EasyTier, state fetching may block for ~1 seconds.
      */
     public static void setScanning(@Nullable String room, @Nullable String player) {
+        setScanning(room, null, player);
+    }
+
+    /**
+     * <p>Set Terracotta Android into 'host-scanning' state, protecting the room with a passphrase.</p>
+     *
+     * <p>The passphrase isn't derivable from the room code. Guests must supply it to join the room.</p>
+     *
+     * @param passphrase the passphrase of the room. An unprotected room will be created if it's null or empty.
+     * @param player     the player's name. A default value will be taken if it's null.
+     * @throws IllegalStateException if Terracotta Android hasn't been initialized.
+     * @implNote Usually, this method doesn't take a long time to fetch states.
+     * However, when initializing the EasyTier, state fetching may block for ~1 seconds.
+     */
+    public static void setScanning(@Nullable String room, @Nullable String passphrase, @Nullable String player) {
         assertStarted();
-        setScanning0(room, player);
+        setScanning0(room, passphrase, player);
+    }
+
+    /**
+     * <p>Set Terracotta Android into 'host-scanning' state, sharing a Bedrock world opened to LAN on this device.</p>
+     *
+     * @param passphrase the passphrase of the room. An unprotected room will be created if it's null or empty.
+     * @param player     the player's name. A default value will be taken if it's null.
+     * @throws IllegalStateException if Terracotta Android hasn't been initialized.
+     * @implNote Usually, this method doesn't take a long time to fetch states.
+     * However, when initializing the EasyTier, state fetching may block for ~1 seconds.
+     */
+    public static void setScanningBedrock(@Nullable String room, @Nullable String passphrase, @Nullable String player) {
+        assertStarted();
+        setScanningBedrock0(room, passphrase, player);
+    }
+
+    /**
+     * <p>Set Terracotta Android into 'host-starting' state, sharing the Minecraft server at the given address,
+     * such as a dedicated server or a LAN world on another device, instead of scanning for LAN worlds.</p>
+     *
+     * @param server     the address of the Minecraft server, like {@code 192.168.1.2:25565}.
+     * @param passphrase the passphrase of the room. An unprotected room will be created if it's null or empty.
+     * @param player     the player's name. A default value will be taken if it's null.
+     * @return True if succeeded, false if server is invalid, the room cannot be reused,
+     * or Terracotta Android isn't in 'waiting' state.
+     * @throws IllegalStateException if Terracotta Android hasn't been initialized.
+     * @throws NullPointerException  if server is null.
+     * @implNote Usually, this method doesn't take a long time to fetch states.
+     * However, when initializing the EasyTier, state fetching may block for ~1 seconds.
+     */
+    public static boolean setHosting(String server, @Nullable String room, @Nullable String passphrase, @Nullable String player) {
+        Objects.requireNonNull(server, "server");
+
+        assertStarted();
+        return setHosting0(server, room, passphrase, player);
     }
 
     /**
      * <p>Set Terracotta Android into 'guest-connecting' state.</p>
      *
-     * @param room   the room code. False will be returned if it's invalid.
+     * @param room   the room code. False will be returned if it's invalid. See {@link #checkRoomCode} for the reason.
      * @param player the player's name. A default value will be taken if it's null.
      * @return True if room code is valid, false otherwise.
      * @throws IllegalStateException if Terracotta Android hasn't been initialized.
@@ -256,17 +306,230 @@ public final class TerracottaAndroidAPI {
      * However, when initializing the EasyTier, state fetching may block for ~1 seconds.
      */
     public static boolean setGuesting(String room, @Nullable String player) {
+        return setGuesting(room, null, player);
+    }
+
+    /**
+     * <p>Set Terracotta Android into 'guest-connecting' state, joining a room which may be protected by a passphrase.</p>
+     *
+     * @param room       the room code. False will be returned if it's invalid. See {@link #checkRoomCode} for the reason.
+     * @param passphrase the passphrase of the room. It's required for {@link RoomType#SCAFFOLDING_PROTECTED} rooms and ignored for others.
+     * @param player     the player's name. A default value will be taken if it's null.
+     * @return True if room code is valid, false otherwise.
+     * @throws IllegalStateException if Terracotta Android hasn't been initialized.
+     * @throws NullPointerException  if room is null.
+     * @implNote Usually, this method doesn't take a long time to fetch states.
+     * However, when initializing the EasyTier, state fetching may block for ~1 seconds.
+     */
+    public static boolean setGuesting(String room, @Nullable String passphrase, @Nullable String player) {
+        return setGuesting(room, passphrase, null, player);
+    }
+
+    /**
+     * <p>Set Terracotta Android into 'guest-connecting' state, presenting an invite minted by host.</p>
+     *
+     * @param room       the room code. False will be returned if it's invalid. See {@link #checkRoomCode} for the reason.
+     * @param passphrase the passphrase of the room. It's required for {@link RoomType#SCAFFOLDING_PROTECTED} rooms and ignored for others.
+     * @param invite     the invite minted by {@link #createInvite}. It's required if the room is invite-only.
+     * @param player     the player's name. A default value will be taken if it's null.
+     * @return True if room code is valid, false otherwise.
+     * @throws IllegalStateException if Terracotta Android hasn't been initialized.
+     * @throws NullPointerException  if room is null.
+     * @implNote Usually, this method doesn't take a long time to fetch states.
+     * However, when initializing the EasyTier, state fetching may block for ~1 seconds.
+     */
+    public static boolean setGuesting(String room, @Nullable String passphrase, @Nullable String invite, @Nullable String player) {
         Objects.requireNonNull(room, "room");
 
         assertStarted();
-        return setGuesting0(room, player);
+        return setGuesting0(room, passphrase, invite, player);
+    }
+
+    /**
+     * <p>Mint an invite for the room which is being hosted.</p>
+     *
+     * @param ttlSeconds how long the invite is valid, in seconds. It's capped at 30 days.
+     * @return A json with {@code invite}, {@code link} and {@code expiry} (milliseconds since epoch) fields,
+     * or null if Terracotta Android isn't in 'host-ok' state.
+     * @throws IllegalStateException if Terracotta Android hasn't been initialized.
+     */
+    @Nullable
+    public static String createInvite(long ttlSeconds) {
+        assertStarted();
+        return createInvite0(ttlSeconds);
+    }
+
+    /**
+     * <p>Switch invite-only mode of the room which is being hosted.
+     * Guests must present an invite from {@link #createInvite} to join an invite-only room.</p>
+     *
+     * @param enabled whether the room is invite-only.
+     * @return True if succeeded, false if Terracotta Android isn't in 'host-ok' state.
+     * @throws IllegalStateException if Terracotta Android hasn't been initialized.
+     */
+    public static boolean setInviteOnly(boolean enabled) {
+        assertStarted();
+        return setInviteOnly0(enabled);
+    }
+
+    /**
+     * <p>Switch approve-joins mode of the room which is being hosted. Guests which have already joined are kept.</p>
+     *
+     * <p>New guests wait in a queue, which is listed in the state, until {@link #approveJoin} or {@link #denyJoin} is invoked.</p>
+     *
+     * @param enabled whether joins must be approved by host.
+     * @return True if succeeded, false if Terracotta Android isn't in 'host-ok' state.
+     * @throws IllegalStateException if Terracotta Android hasn't been initialized.
+     */
+    public static boolean setApproval(boolean enabled) {
+        assertStarted();
+        return setApproval0(enabled);
+    }
+
+    /**
+     * <p>Admit a guest waiting for approval, which joins the room immediately.</p>
+     *
+     * @param machineId the machine id of the guest.
+     * @return True if succeeded, false if the guest isn't in queue, or the room is full.
+     * @throws IllegalStateException if Terracotta Android hasn't been initialized.
+     * @throws NullPointerException  if machineId is null.
+     */
+    public static boolean approveJoin(String machineId) {
+        Objects.requireNonNull(machineId, "machineId");
+
+        assertStarted();
+        return approveJoin0(machineId);
+    }
+
+    /**
+     * <p>Refuse a guest waiting for approval, which is told on its next ping.</p>
+     *
+     * @param machineId the machine id of the guest.
+     * @return True if succeeded, false if the guest isn't in queue.
+     * @throws IllegalStateException if Terracotta Android hasn't been initialized.
+     * @throws NullPointerException  if machineId is null.
+     */
+    public static boolean denyJoin(String machineId) {
+        Objects.requireNonNull(machineId, "machineId");
+
+        assertStarted();
+        return denyJoin0(machineId);
+    }
+
+    /**
+     * <p>Remove a guest from the room which is being hosted. The guest is told to leave on its next ping.</p>
+     *
+     * <p>Guests may join again, unless the room is invite-only or approves joins,
+     * in which case the invite or approval of that guest is revoked.</p>
+     *
+     * @param machineId the machine id of the guest.
+     * @return True if succeeded, false if the guest isn't in the room, it's host itself,
+     * or Terracotta Android isn't in 'host-ok' state.
+     * @throws IllegalStateException if Terracotta Android hasn't been initialized.
+     * @throws NullPointerException  if machineId is null.
+     */
+    public static boolean kick(String machineId) {
+        Objects.requireNonNull(machineId, "machineId");
+
+        assertStarted();
+        return kick0(machineId);
+    }
+
+    /**
+     * <p>Ban a player from all rooms hosted on this device, removing it from the current room if it's in.</p>
+     *
+     * <p>Bans are keyed on the machine id reported by the guest itself.
+     * A guest which reinstalls its launcher or forges another machine id is able to bypass the ban,
+     * unless the room is invite-only or approves joins.</p>
+     *
+     * @param machineId the machine id of the player.
+     * @return True if succeeded, false if machineId is empty or belongs to host itself.
+     * @throws IllegalStateException if Terracotta Android hasn't been initialized.
+     * @throws NullPointerException  if machineId is null.
+     */
+    public static boolean ban(String machineId) {
+        Objects.requireNonNull(machineId, "machineId");
+
+        assertStarted();
+        return ban0(machineId);
+    }
+
+    /**
+     * <p>Handler for scaffolding requests of a namespace owned by the launcher. See {@link #registerHandler}.</p>
+     *
+     * @implNote Handlers are invoked concurrently on threads owned by Terracotta Android.
+     */
+    public interface ScaffoldingHandler {
+        /**
+         * @param request body of the request.
+         * @return body of the response, or null for an empty one.
+         * @throws Exception if the request cannot be handled, which is reported to the peer as a failure.
+         */
+        @Nullable
+        byte[] handle(byte[] request) throws Exception;
+    }
+
+    /**
+     * <p>Register a handler served by the scaffolding server, which is advertised to peers through {@code c:protocols}.
+     * Launchers should use a namespace of their own, like {@code ourlauncher:sync}.</p>
+     *
+     * @param namespace namespace of the protocol. Namespaces of built-in handlers, such as {@code c}, are reserved.
+     * @param path      path of the protocol.
+     * @return True if succeeded, false if a handler of the same protocol has been registered.
+     * @throws IllegalArgumentException if namespace or path is invalid, or namespace is reserved.
+     * @throws IllegalStateException    if Terracotta Android hasn't been initialized.
+     */
+    public static boolean registerHandler(String namespace, String path, ScaffoldingHandler handler) {
+        assertStarted();
+        switch (registerHandler0(Objects.requireNonNull(namespace), Objects.requireNonNull(path), Objects.requireNonNull(handler))) {
+            case 0:
+                return true;
+            case 1:
+                throw new IllegalArgumentException("Invalid protocol: " + namespace + ":" + path);
+            case 2:
+                throw new IllegalArgumentException("Reserved namespace: " + namespace);
+            case 3:
+                return false;
+            default:
+                throw new AssertionError("Should NOT be here.");
+        }
+    }
+
+    /**
+     * @return True if the handler has been removed, false if it doesn't exist.
+     * @throws IllegalStateException if Terracotta Android hasn't been initialized.
+     */
+    public static boolean unregisterHandler(String namespace, String path) {
+        assertStarted();
+        return unregisterHandler0(Objects.requireNonNull(namespace), Objects.requireNonNull(path));
+    }
+
+    /**
+     * <p>Set limits of the scaffolding server, which serves Scaffolding requests from guests.
+     * It must be invoked before hosting or joining any room, as the server is started on first use.</p>
+     *
+     * <p>Limits is a json object, whose absent fields take default values: {@code max_frame_size}, {@code max_connections},
+     * {@code requests_per_second}, {@code idle_timeout_millis}, {@code max_in_flight} and {@code max_workers}.
+     * Besides, {@code allow_unauthenticated} serves guests which don't authenticate with the room secret,
+     * such as older Terracotta. It's false by default, as anyone reaching the device would be served.</p>
+     *
+     * @param limits a json describing the limits.
+     * @return True if succeeded, false if limits is invalid, has been set, or the server has started with default limits.
+     * @throws IllegalStateException if Terracotta Android hasn't been initialized.
+     * @throws NullPointerException  if limits is null.
+     */
+    public static boolean setServerLimits(String limits) {
+        Objects.requireNonNull(limits, "limits");
+
+        assertStarted();
+        return setServerLimits0(limits);
     }
 
     /**
      * Room types supported by Terracotta Android
      */
     public enum RoomType {
-        TERRACOTTA_LEGACY, PCL2CE, SCAFFOLDING
+        TERRACOTTA_LEGACY, PCL2CE, SCAFFOLDING, SCAFFOLDING_PROTECTED
     }
 
     /**
@@ -292,11 +555,33 @@ public final class TerracottaAndroidAPI {
                 return RoomType.PCL2CE;
             case 3:
                 return RoomType.SCAFFOLDING;
+            case 4:
+                return RoomType.SCAFFOLDING_PROTECTED;
             default:
                 throw new AssertionError("Should NOT be here.");
         }
     }
 
+    /**
+     * <p>Explain why the given room code is invalid.</p>
+     *
+     * <p>The returned json has an {@code error} field of {@code invalid_room_code}, a human-readable {@code message},
+     * and a {@code detail} object whose {@code reason} is one of {@code missing_prefix}, {@code invalid_length},
+     * {@code illegal_character}, {@code misplaced_dash} or {@code invalid_checksum}.
+     * Positions in {@code detail} are 1-based character indexes into {@code room}.</p>
+     *
+     * @param room A room code.
+     * @return A json describing the problem, or null if the room code is valid.
+     * @throws NullPointerException if room is null
+     */
+    @Nullable
+    public static String checkRoomCode(String room) {
+        Objects.requireNonNull(room, "room");
+
+        assertStarted();
+        return checkRoomCode0(room);
+    }
+
     /**
      * <p>Collect logs of Terracotta Android.</p>
      *
@@ -460,12 +745,38 @@ public final class TerracottaAndroidAPI {
 
     private static native void setWaiting0();
 
-    private static native void setScanning0(String room, String player);
+    private static native void setScanning0(String room, String passphrase, String player);
+
+    private static native void setScanningBedrock0(String room, String passphrase, String player);
+
+    private static native boolean setHosting0(String server, String room, String passphrase, String player);
+
+    private static native boolean setGuesting0(String room, String passphrase, String invite, String player);
 
-    private static native boolean setGuesting0(String room, String player);
+    private static native String createInvite0(long ttlSeconds);
+
+    private static native boolean setInviteOnly0(boolean enabled);
+
+    private static native boolean setApproval0(boolean enabled);
+
+    private static native boolean approveJoin0(String machineId);
+
+    private static native boolean denyJoin0(String machineId);
+
+    private static native boolean kick0(String machineId);
+
+    private static native boolean ban0(String machineId);
 
     private static native int verifyRoomCode0(String room);
 
+    private static native String checkRoomCode0(String room);
+
+    private static native int registerHandler0(String namespace, String path, Object handler);
+
+    private static native boolean unregisterHandler0(String namespace, String path);
+
+    private static native boolean setServerLimits0(String limits);
+
     private static native String getMetadata0();
 
     private static native long prepareExportLogs0();
@@ -1,15 +1,18 @@
 use std::sync::mpsc;
 use crate::controller::states::AppState;
-use crate::controller::{scaffolding, ConnectionDifficulty, ExceptionType, Room};
-use crate::scaffolding::profile::Profile;
+use crate::controller::{scaffolding, ConnectionDifficulty, ExceptionType, JoinLink, Room, RoomCodeError, RoomKind};
+use crate::scaffolding::profile::{Profile, ProfileKind};
+use crate::mc::mods;
+use crate::mc::{bedrock, fakeserver, Edition};
 use crate::mc::scanning::MinecraftScanner;
-use crate::MOTD;
 use rocket::serde::Serialize;
 use serde::ser::SerializeSeq;
 use serde::Serializer;
 use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::thread;
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use crate::easytier::publics::fetch_public_nodes;
 
 pub fn get_state() -> Value {
@@ -21,13 +24,18 @@ pub fn get_state() -> Value {
             json!({"state": "waiting", "index": index})
         }
 
-        AppState::HostScanning { .. } => {
-            json!({"state": "host-scanning", "index": index})
+        AppState::HostScanning { edition, scanner, selected } => {
+            // Worlds of Java Edition cannot be chosen while scanning for Bedrock worlds.
+            let worlds = match edition {
+                Edition::Java => worlds(scanner),
+                Edition::Bedrock => json!([]),
+            };
+            json!({"state": "host-scanning", "index": index, "edition": edition, "worlds": worlds, "selected": selected})
         }
         AppState::HostStarting { room, .. } => {
-            json!({"state": "host-starting", "index": index, "room": room.code})
+            json!({"state": "host-starting", "index": index, "room": room.code, "link": JoinLink::of(room).format()})
         }
-        AppState::HostOk { room, profiles, .. } => {
+        AppState::HostOk { room, edition, port, server, profiles, invite_only, approval, capacity, reopening, status, scanner, .. } => {
             struct Holder<'a>(&'a Vec<(SystemTime, Profile)>);
             impl<'a> Serialize for Holder<'a> {
                 fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
@@ -42,29 +50,49 @@ pub fn get_state() -> Value {
                 }
             }
 
-            json!({"state": "host-ok", "index": index, "room": room.code, "profile_index": sharing_index, "profiles": Holder(profiles)})
+            json!({
+                "state": "host-ok", "index": index, "room": room.code, "link": JoinLink::of(room).format(), "invite_only": invite_only,
+                "profile_index": sharing_index, "profiles": Holder(profiles), "capacity": capacity,
+                "approval": approval.enabled, "pending": Holder(&approval.pending),
+                "edition": edition, "port": port, "server": server.map(|server| server.to_string()), "reopening": reopening, "status": status, "worlds": worlds(scanner),
+            })
         }
 
         AppState::GuestConnecting { room, .. } => {
             json!({"state": "guest-connecting", "index": index, "room": room.code})
         }
-        AppState::GuestStarting { room, difficulty, .. } => {
+        AppState::GuestStarting { room, difficulty, awaiting_approval, .. } => {
             json!({"state": "guest-starting", "index": index, "room": room.code, "difficulty": match difficulty {
                 ConnectionDifficulty::Unknown => "UNKNOWN",
                 ConnectionDifficulty::Easiest => "EASIEST",
                 ConnectionDifficulty::Simple => "SIMPLE",
                 ConnectionDifficulty::Medium => "MEDIUM",
                 ConnectionDifficulty::Tough => "TOUGH",
-            }})
+            }, "awaiting_approval": awaiting_approval})
         }
-        AppState::GuestOk { server, profiles, .. } => {
+        AppState::GuestOk { edition, server, status, profiles, unsupported, mods, .. } => {
             let url = if server.port == 25565 {
                 "127.0.0.1".into()
             } else {
                 format!("127.0.0.1:{}", server.port)
             };
 
-            json!({"state": "guest-ok", "index": index, "url": url, "profile_index": sharing_index, "profiles": profiles})
+            let vendor = profiles.iter()
+                .find(|profile| *profile.get_kind() == ProfileKind::HOST)
+                .map(|profile| profile.get_vendor());
+            let compatibility = if unsupported.is_empty() {
+                "full"
+            } else if vendor.is_some_and(|vendor| vendor.starts_with("Terracotta ")) {
+                "older_terracotta"
+            } else {
+                "other_vendor"
+            };
+
+            json!({"state": "guest-ok", "index": index, "edition": edition, "url": url, "profile_index": sharing_index, "profiles": profiles, "host": {
+                "vendor": vendor,
+                "compatibility": compatibility,
+                "unsupported": unsupported,
+            }, "mods": mods, "status": status})
         }
         AppState::Exception { kind, .. } => json!({
             "state": "exception",
@@ -76,78 +104,245 @@ pub fn get_state() -> Value {
                 ExceptionType::HostEasytierCrash => 3,
                 ExceptionType::PingServerRst => 4,
                 ExceptionType::ScaffoldingInvalidResponse => 5,
+                ExceptionType::InviteRequired => 6,
+                ExceptionType::InviteRejected => 7,
+                ExceptionType::RoomClosed => 8,
+                ExceptionType::Kicked => 9,
+                ExceptionType::Banned => 10,
+                ExceptionType::JoinDenied => 11,
+                ExceptionType::RoomFull => 12,
             }
         }),
     }
 }
 
+fn worlds(scanner: &MinecraftScanner) -> Value {
+    json!(scanner.get_servers().into_iter().map(|server| json!({
+        "port": server.port,
+        "motd": server.motd,
+        "last_seen": server.last_seen.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0),
+    })).collect::<Vec<_>>())
+}
+
 pub fn set_waiting() {
     logging!("Core", "Setting to state WAITING.");
 
     let state = AppState::acquire();
-    if matches!(state.as_ref(), AppState::Waiting) {
-        return;
+    match state.as_ref() {
+        AppState::Waiting => {}
+        AppState::HostOk { .. } => {
+            scaffolding::events::publish(scaffolding::events::RoomEvent::Closing);
+            state.replace(|state| {
+                let AppState::HostOk { easytier, .. } = state else {
+                    unreachable!();
+                };
+                // Keep the network for a while, so that guests can receive the closing event.
+                thread::spawn(move || {
+                    thread::sleep(Duration::from_secs(1));
+                    drop(easytier);
+                });
+                AppState::Waiting
+            });
+        }
+        _ => {
+            state.set(AppState::Waiting);
+        }
     }
-    state.set(AppState::Waiting);
 }
 
-pub fn set_scanning(room: Option<String>, player: Option<String>, public_nodes: Vec<String>) {
+/// Start hosting a room. If mods is given, the manifest of mods under it is published to guests.
+/// If capacity is given, guests are refused once the room holds that many players, including host.
+pub fn set_scanning(
+    room: Option<String>,
+    passphrase: Option<String>,
+    player: Option<String>,
+    public_nodes: Vec<String>,
+    mods: Option<PathBuf>,
+    capacity: Option<usize>,
+) -> bool {
+    start_hosting(room, passphrase, player, public_nodes, mods, capacity, Source::Scan(Edition::Java))
+}
+
+/// Start hosting a room which shares a Bedrock world opened to LAN on this PC, discovered through RakNet pings.
+pub fn set_scanning_bedrock(
+    room: Option<String>,
+    passphrase: Option<String>,
+    player: Option<String>,
+    public_nodes: Vec<String>,
+    capacity: Option<usize>,
+) -> bool {
+    start_hosting(room, passphrase, player, public_nodes, None, capacity, Source::Scan(Edition::Bedrock))
+}
+
+/// Start hosting a room which shares the Minecraft server at the given address, such as a dedicated server or a
+/// LAN world on another machine, instead of scanning for LAN worlds on this PC.
+pub fn set_hosting(
+    server: SocketAddr,
+    room: Option<String>,
+    passphrase: Option<String>,
+    player: Option<String>,
+    public_nodes: Vec<String>,
+    mods: Option<PathBuf>,
+    capacity: Option<usize>,
+) -> bool {
+    start_hosting(room, passphrase, player, public_nodes, mods, capacity, Source::Address(server))
+}
+
+/// Where the Minecraft server shared by host comes from.
+enum Source {
+    /// Worlds opened to LAN on this PC, which are discovered while scanning.
+    Scan(Edition),
+    Address(SocketAddr),
+}
+
+fn start_hosting(
+    room: Option<String>,
+    passphrase: Option<String>,
+    player: Option<String>,
+    public_nodes: Vec<String>,
+    mods: Option<PathBuf>,
+    capacity: Option<usize>,
+    source: Source,
+) -> bool {
+    let passphrase = passphrase.filter(|passphrase| !passphrase.is_empty());
+    let room = match room.and_then(|room| Room::from(&room, passphrase.as_deref()).inspect_err(|e| {
+        logging!("Core", "Cannot reuse room code {:?}: {}", room, e);
+    }).ok()) {
+        // The network secret of an unprotected room is a part of its code, so a passphrase would never be used.
+        Some(Room { kind: RoomKind::Scaffolding { .. }, code, .. }) if passphrase.is_some() => {
+            logging!("Core", "Cannot protect room {} with a passphrase, as it's not a protected room.", code);
+            return false;
+        }
+        Some(room) => room,
+        None => Room::create(passphrase),
+    };
+
     let capture = {
         let state = AppState::acquire();
         if !matches!(state.as_ref(), AppState::Waiting) {
-            return;
+            return false;
         }
 
-        state.set(AppState::HostScanning {
-            scanner: MinecraftScanner::create(|m| m != MOTD),
-        })
+        match source {
+            Source::Address(server) => state.set(AppState::HostStarting { room: room.clone(), port: server.port() }),
+            Source::Scan(edition) => state.set(AppState::HostScanning {
+                edition,
+                scanner: MinecraftScanner::create(|m| !fakeserver::is_fake(m)),
+                selected: None,
+            }),
+        }
     };
-    logging!("Core", "Setting to state SCANNING.");
+    match source {
+        Source::Address(server) => {
+            logging!("Core", "Setting to state STARTING, sharing server at {}.", server);
+        }
+        Source::Scan(edition) => {
+            logging!("Core", "Setting to state SCANNING, edition={:?}.", edition);
+        }
+    }
 
     thread::spawn(move || {
-        let room = room
-            .and_then(|room| Room::from(&room))
-            .unwrap_or_else(Room::create);
-
         let (sender, receiver) = mpsc::channel();
         let room2 = room.clone();
         thread::spawn(move || {
             // EasyTier Uptime is undergoing DDOS attack, so it's crucial to perform a prefetch logic.
             let _ = sender.send(fetch_public_nodes(&room2, public_nodes));
         });
+        let (mods_sender, mods_receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let _ = mods_sender.send(mods.and_then(|directory| mods::scan(&directory).inspect_err(|e| {
+                logging!("Core", "Cannot scan mods in {:?}: {:?}", directory, e);
+            }).ok()));
+        });
 
-        let (room, port, capture) = loop {
-            thread::sleep(Duration::from_millis(200));
+        let (server, capture) = match source {
+            Source::Address(server) => (scaffolding::HostedServer::Address(server), capture),
+            Source::Scan(edition) => loop {
+                thread::sleep(Duration::from_millis(200));
 
-            let Some(state) = capture.try_capture() else {
-                return;
-            };
-            let AppState::HostScanning { scanner, .. } = state.as_ref() else {
-                unreachable!()
-            };
+                // RakNet pings are sent without locking, as they take a while if no Bedrock world is opened.
+                let pong = match edition {
+                    Edition::Java => None,
+                    Edition::Bedrock => bedrock::discover(),
+                };
 
-            if let Some(port) = scanner.get_ports().first() {
-                break (room.clone(), *port, state.set(AppState::HostStarting { room, port: *port }));
-            }
+                let Some(state) = capture.try_capture() else {
+                    return;
+                };
+                let AppState::HostScanning { scanner, selected, .. } = state.as_ref() else {
+                    unreachable!()
+                };
+
+                let server = match edition {
+                    Edition::Java => {
+                        // Host must choose a world through select_world if there are several of them.
+                        let ports = scanner.get_ports();
+                        let port = match selected {
+                            Some(selected) => ports.contains(selected).then_some(*selected),
+                            None if ports.len() == 1 => Some(ports[0]),
+                            None => None,
+                        };
+                        port.map(|port| (scaffolding::HostedServer::Lan(port), port))
+                    }
+                    Edition::Bedrock => pong.map(|pong| pong.port_v4.unwrap_or(bedrock::PORT_V4))
+                        .map(|port| (scaffolding::HostedServer::Bedrock(port), port)),
+                };
+                if let Some((server, port)) = server {
+                    break (server, state.set(AppState::HostStarting { room: room.clone(), port }));
+                }
+            },
         };
 
-        scaffolding::start_host(room, port, player, capture, receiver.recv().unwrap())
+        scaffolding::start_host(room, server, player, capture, receiver.recv().unwrap(), mods_receiver.recv().unwrap(), capacity)
     });
+
+    true
+}
+
+#[derive(Debug)]
+pub enum GuestingError {
+    InvalidRoomCode(RoomCodeError),
+    IllegalState,
+}
+
+impl GuestingError {
+    pub fn to_json(&self) -> Value {
+        match self {
+            GuestingError::InvalidRoomCode(e) => {
+                json!({"error": "invalid_room_code", "detail": e, "message": e.to_string()})
+            }
+            GuestingError::IllegalState => {
+                json!({"error": "illegal_state", "message": "Terracotta is not in waiting state."})
+            }
+        }
+    }
 }
 
-pub fn set_guesting(room: Room, player: Option<String>, public_nodes: Vec<String>) -> bool {
+/// Join a room. If motd is given, it's used as the template of the MOTD shown on the multiplayer screen, see motd::render.
+pub fn set_guesting(
+    room: &str,
+    passphrase: Option<&str>,
+    invite: Option<String>,
+    player: Option<String>,
+    public_nodes: Vec<String>,
+    mods: Option<PathBuf>,
+    motd: Option<String>,
+) -> Result<(), GuestingError> {
+    let room = Room::from(room, passphrase).map_err(GuestingError::InvalidRoomCode)?;
+
     let capture = {
         let state = AppState::acquire();
         if !matches!(state.as_ref(), AppState::Waiting { .. }) {
-            return false;
+            return Err(GuestingError::IllegalState);
         }
         state.set(AppState::GuestConnecting { room: room.clone() })
     };
     logging!("Core", "Connecting to room, code={}", room.code);
     thread::spawn(move || {
         let public_nodes = fetch_public_nodes(&room, public_nodes);
-        scaffolding::start_guest(room, player, capture, public_nodes)
+        let motd = motd.filter(|motd| !motd.is_empty());
+        scaffolding::start_guest(room, invite.filter(|invite| !invite.is_empty()), player, capture, public_nodes, mods, motd)
     });
 
-    true
+    Ok(())
 }
@@ -0,0 +1,68 @@
+use crate::controller::scaffolding::chat::{ChatLog, ChatMessage};
+use crate::controller::scaffolding::events::{self, RoomEvent};
+use crate::controller::scaffolding::MACHINE_ID;
+use crate::controller::states::AppState;
+use crate::scaffolding::PacketResponse;
+use serde_json::{json, Value};
+
+#[derive(Debug)]
+pub enum ChatError {
+    IllegalState,
+    InvalidText,
+    Unsupported,
+    Failed,
+}
+
+impl ChatError {
+    pub fn to_json(&self) -> Value {
+        match self {
+            ChatError::IllegalState => json!({"error": "illegal_state", "message": "Terracotta is not in a room."}),
+            ChatError::InvalidText => json!({"error": "invalid_text", "message": "Message is empty or too long."}),
+            ChatError::Unsupported => json!({"error": "unsupported", "message": "Host doesn't support chat."}),
+            ChatError::Failed => json!({"error": "failed", "message": "Host has refused the message, or is unreachable."}),
+        }
+    }
+}
+
+/// Chat messages of the current room from cursor on, together with the cursor of the next fetch.
+/// None is returned if Terracotta isn't in a room.
+pub fn fetch_chat(cursor: u64) -> Option<(u64, Vec<ChatMessage>)> {
+    match AppState::acquire().as_ref() {
+        AppState::HostOk { chat, .. } | AppState::GuestOk { chat, .. } => Some(chat.fetch(cursor)),
+        _ => None,
+    }
+}
+
+/// Send a message to the current room. Guests deliver it to host, and it appears locally once fetched back.
+pub fn send_chat(text: &str) -> Result<(), ChatError> {
+    let text = ChatLog::normalize(text).ok_or(ChatError::InvalidText)?;
+
+    let mut session = {
+        let mut state = AppState::acquire();
+        match state.as_mut_ref() {
+            AppState::HostOk { profiles, chat, .. } => {
+                chat.post(&MACHINE_ID, profiles[0].1.get_name(), text);
+                events::publish(RoomEvent::Chat);
+                return Ok(());
+            }
+            AppState::GuestOk { unsupported, .. } if unsupported.contains(&"c:chat_send") => return Err(ChatError::Unsupported),
+            AppState::GuestOk { session, .. } => session.clone(),
+            _ => return Err(ChatError::IllegalState),
+        }
+    };
+
+    // Lock of AppState isn't held while waiting for host.
+    match session.invoke(("c", "chat_send"), |body| {
+        serde_json::to_writer(body, &json!({
+            "machine_id": *MACHINE_ID,
+            "text": text,
+        })).unwrap();
+    }) {
+        Some(PacketResponse::Ok { .. }) => Ok(()),
+        Some(PacketResponse::Fail { status, data }) => {
+            logging!("Core", "Cannot send chat message, status={}: {}", status, String::from_utf8_lossy(&data));
+            Err(ChatError::Failed)
+        }
+        None => Err(ChatError::Failed),
+    }
+}
@@ -0,0 +1,155 @@
+use crate::controller::scaffolding::events::{self, RoomEvent};
+use crate::controller::scaffolding::invite;
+use crate::controller::scaffolding;
+use crate::controller::states::AppState;
+use crate::controller::JoinLink;
+use crate::mc::Edition;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub struct Invite {
+    pub invite: String,
+    pub link: String,
+    pub expiry: SystemTime,
+}
+
+impl Invite {
+    pub fn expiry_millis(&self) -> u64 {
+        self.expiry.duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0)
+    }
+}
+
+/// Mint an invite for the current room, which is valid for ttl. None is returned if Terracotta isn't hosting.
+pub fn create_invite(ttl: Duration) -> Option<Invite> {
+    const MAX_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+    let state = AppState::acquire();
+    let AppState::HostOk { room, .. } = state.as_ref() else {
+        return None;
+    };
+
+    let expiry = SystemTime::now() + ttl.min(MAX_TTL);
+    let invite = invite::mint(room, expiry);
+    let link = JoinLink { invite: Some(invite.clone()), ..JoinLink::of(room) }.format();
+
+    logging!("Core", "Minted an invite for room {}, expiry={:?}", room.code, expiry);
+    Some(Invite { invite, link, expiry })
+}
+
+/// Switch invite-only mode of the current room. Guests which have already joined are kept.
+pub fn set_invite_only(enabled: bool) -> bool {
+    let mut state = AppState::acquire();
+    let AppState::HostOk { profiles, invite_only, invited, .. } = state.as_mut_ref() else {
+        return false;
+    };
+
+    if *invite_only == enabled {
+        return true;
+    }
+    *invite_only = enabled;
+    if enabled {
+        for (_, profile) in profiles.iter().skip(1) {
+            if !invited.iter().any(|invited| invited == profile.get_machine_id()) {
+                invited.push(profile.get_machine_id().to_string());
+            }
+        }
+    }
+
+    logging!("Core", "Setting invite-only to {}.", enabled);
+    state.increase_shared();
+    true
+}
+
+/// Switch approve-joins mode of the current room. Guests which have already joined are kept.
+pub fn set_approval(enabled: bool) -> bool {
+    let mut state = AppState::acquire();
+    let AppState::HostOk { profiles, approval, .. } = state.as_mut_ref() else {
+        return false;
+    };
+
+    if approval.enabled == enabled {
+        return true;
+    }
+    approval.set_enabled(enabled, profiles.iter().skip(1).map(|(_, profile)| profile.get_machine_id()));
+
+    logging!("Core", "Setting approve-joins to {}.", enabled);
+    state.increase_shared();
+    true
+}
+
+/// Admit a guest waiting for approval, which joins the room immediately.
+/// Returns false if it isn't in queue, or the room is full.
+pub fn approve_join(machine_id: &str) -> bool {
+    let mut state = AppState::acquire();
+    let AppState::HostOk { profiles, approval, capacity, .. } = state.as_mut_ref() else {
+        return false;
+    };
+    if capacity.is_some_and(|capacity| profiles.len() >= capacity) {
+        return false;
+    }
+    let Some(profile) = approval.approve(machine_id) else {
+        return false;
+    };
+
+    logging!("Core", "Approving guest {}.", profile.get_name());
+    events::publish(RoomEvent::Join {
+        machine_id: machine_id.to_string(),
+        name: profile.get_name().to_string(),
+        vendor: profile.get_vendor().to_string(),
+    });
+    profiles.push((SystemTime::now(), profile));
+    state.increase_shared();
+    true
+}
+
+/// Refuse a guest waiting for approval, which is told on its next c:player_ping. Returns false if it isn't in queue.
+pub fn deny_join(machine_id: &str) -> bool {
+    let mut state = AppState::acquire();
+    let AppState::HostOk { approval, .. } = state.as_mut_ref() else {
+        return false;
+    };
+    if !approval.deny(machine_id) {
+        return false;
+    }
+
+    logging!("Core", "Denying guest {}.", machine_id);
+    state.increase_shared();
+    true
+}
+
+/// Choose the world to share among those opened to LAN. While hosting, guests are moved to the chosen world.
+/// Returns false if the world isn't advertised, cannot be exposed to guests, or host is sharing a Bedrock world.
+pub fn select_world(port: u16) -> bool {
+    // Whitelists may be updated through EasyTier CLI.
+    let mut state = AppState::acquire().into_slow();
+    match state.as_mut_ref() {
+        AppState::HostScanning { edition, scanner, selected } => {
+            if *edition != Edition::Java || !scanner.get_ports().contains(&port) {
+                return false;
+            }
+            *selected = Some(port);
+        }
+        AppState::HostOk { edition, port: current, server, relay, reopening, status, scanner, easytier, .. } => {
+            if *edition != Edition::Java || !scanner.get_ports().contains(&port) {
+                return false;
+            }
+            if *current == port && server.is_none() && !*reopening {
+                return true;
+            }
+            if *current != port && !scaffolding::expose_server(easytier, port) {
+                return false;
+            }
+            // Host switches from the server given by address to a LAN world.
+            *server = None;
+            *relay = None;
+            *current = port;
+            *reopening = false;
+            *status = None;
+            events::publish(RoomEvent::Server);
+        }
+        _ => return false,
+    }
+
+    logging!("Core", "Selecting world at {}.", port);
+    state.increase_shared();
+    true
+}
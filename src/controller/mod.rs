@@ -1,14 +1,42 @@
 mod states;
 mod api;
 mod rooms;
+mod saved;
+mod hosting;
+mod chat;
+mod moderation;
 
 pub use rooms::*;
 pub use states::*;
 pub use api::*;
+pub use saved::*;
+pub use hosting::*;
+pub use chat::*;
+pub use moderation::*;
 
+pub use crate::scaffolding::server::{HandlerRegistry, RegistryError};
+pub use crate::scaffolding::PacketResponse;
+pub use scaffolding::protocols::HANDLERS;
+
+use crate::scaffolding::limits::ServerLimits;
 use crate::scaffolding::server::start as start;
-use scaffolding::protocols::HANDLERS as HANDLERS;
+use scaffolding::protocols::room_secret;
+use std::sync::OnceLock;
+
+static SERVER_LIMITS: OnceLock<ServerLimits> = OnceLock::new();
 
 lazy_static::lazy_static! {
-    pub static ref SCAFFOLDING_PORT: u16 = start(HANDLERS, 13448).unwrap_or_else(|_| start(HANDLERS, 0).unwrap());
+    pub static ref SCAFFOLDING_PORT: u16 = {
+        // Subscriptions parked on scaffolding server are answered once room events are published.
+        scaffolding::events::watch(|| HANDLERS.notify());
+        let limits = SERVER_LIMITS.get_or_init(ServerLimits::default);
+        start(&HANDLERS, room_secret, limits.clone(), 13448)
+            .unwrap_or_else(|_| start(&HANDLERS, room_secret, limits.clone(), 0).unwrap())
+    };
+}
+
+/// Set limits of scaffolding server, which must be called before SCAFFOLDING_PORT is initialized.
+/// Returns false if limits have been set, or scaffolding server has started with default limits.
+pub fn set_server_limits(limits: ServerLimits) -> bool {
+    SERVER_LIMITS.set(limits).is_ok()
 }
@@ -0,0 +1,138 @@
+use crate::controller::scaffolding::events::{self, RoomEvent};
+use crate::controller::scaffolding::MACHINE_ID;
+use crate::controller::states::AppState;
+use crate::BANNED_PLAYERS_FILE;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A player banned by host, who is refused by c:player_ping in every room hosted later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BannedPlayer {
+    pub machine_id: String,
+    /// The name of the player when it's banned, or an empty string if it wasn't in the room.
+    pub name: String,
+    /// Milliseconds since UNIX epoch when the player was banned.
+    pub time: u64,
+}
+
+#[derive(Debug)]
+pub enum ModerationError {
+    IllegalState,
+    InvalidMachineId,
+    NotFound,
+}
+
+impl ModerationError {
+    pub fn to_json(&self) -> Value {
+        match self {
+            ModerationError::IllegalState => json!({"error": "illegal_state", "message": "Terracotta is not hosting a room."}),
+            ModerationError::InvalidMachineId => json!({"error": "invalid_machine_id", "message": "Host cannot be kicked or banned."}),
+            ModerationError::NotFound => json!({"error": "not_found", "message": "Cannot find the player."}),
+        }
+    }
+}
+
+// Loaded from disk on first use.
+static BANS: Mutex<Option<Vec<BannedPlayer>>> = Mutex::new(None);
+
+fn with_bans<R>(f: impl FnOnce(&mut Vec<BannedPlayer>) -> R) -> R {
+    let mut bans = BANS.lock();
+    f(bans.get_or_insert_with(|| {
+        let data = match fs::read(BANNED_PLAYERS_FILE.as_path()) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return vec![],
+            Err(e) => {
+                logging!("Moderation", "Cannot read banned players: {:?}", e);
+                return vec![];
+            }
+        };
+        serde_json::from_slice(&data).unwrap_or_else(|e| {
+            logging!("Moderation", "Cannot parse banned players: {:?}", e);
+            vec![]
+        })
+    }))
+}
+
+fn store(bans: &[BannedPlayer]) {
+    if let Err(e) = fs::write(BANNED_PLAYERS_FILE.as_path(), serde_json::to_vec_pretty(bans).unwrap()) {
+        logging!("Moderation", "Cannot write banned players: {:?}", e);
+    }
+}
+
+pub fn is_banned(machine_id: &str) -> bool {
+    with_bans(|bans| bans.iter().any(|ban| ban.machine_id == machine_id))
+}
+
+pub fn get_bans() -> Vec<BannedPlayer> {
+    with_bans(|bans| bans.clone())
+}
+
+/// Ban a player, who is removed from the current room if it's in. Players not in the room can be banned as well.
+/// Bans are keyed on the machine ID told by the guest itself, so a guest reinstalling its launcher or forging another
+/// machine ID bypasses the ban, unless the room is invite-only or approves joins.
+pub fn ban(machine_id: &str) -> Result<(), ModerationError> {
+    if machine_id.is_empty() || machine_id == *MACHINE_ID {
+        return Err(ModerationError::InvalidMachineId);
+    }
+    let name = match AppState::acquire().as_ref() {
+        AppState::HostOk { profiles, .. } => profiles.iter()
+            .find(|(_, profile)| profile.get_machine_id() == machine_id)
+            .map(|(_, profile)| profile.get_name().to_string()),
+        _ => None,
+    };
+
+    with_bans(|bans| {
+        if !bans.iter().any(|ban| ban.machine_id == machine_id) {
+            bans.push(BannedPlayer {
+                machine_id: machine_id.to_string(),
+                name: name.unwrap_or_default(),
+                time: SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0),
+            });
+            store(bans);
+        }
+    });
+    logging!("Moderation", "Banned {}.", machine_id);
+
+    // The ban list is updated beforehand, so that the guest is told to be banned rather than kicked.
+    let _ = kick(machine_id);
+    Ok(())
+}
+
+pub fn unban(machine_id: &str) -> Result<(), ModerationError> {
+    with_bans(|bans| {
+        let length = bans.len();
+        bans.retain(|ban| ban.machine_id != machine_id);
+        if bans.len() == length {
+            return Err(ModerationError::NotFound);
+        }
+        store(bans);
+        Ok(())
+    })
+}
+
+/// Remove a guest from the current room, which is told to leave on its next c:player_ping. Guests may join again,
+/// unless the room is invite-only or approves joins, in which case the invite or approval of that guest is revoked.
+pub fn kick(machine_id: &str) -> Result<(), ModerationError> {
+    let mut state = AppState::acquire();
+    let AppState::HostOk { profiles, invited, kicked, approval, .. } = state.as_mut_ref() else {
+        return Err(ModerationError::IllegalState);
+    };
+
+    let index = match profiles.iter().position(|(_, profile)| profile.get_machine_id() == machine_id) {
+        Some(0) => return Err(ModerationError::InvalidMachineId),
+        Some(index) => index,
+        None => return Err(ModerationError::NotFound),
+    };
+    let (_, profile) = profiles.remove(index);
+    invited.retain(|invited| invited != machine_id);
+    approval.revoke(machine_id);
+    kicked.push((SystemTime::now(), machine_id.to_string()));
+
+    logging!("Moderation", "Removing guest {}.", profile.get_name());
+    events::publish(RoomEvent::Leave { machine_id: machine_id.to_string() });
+    state.increase_shared();
+    Ok(())
+}
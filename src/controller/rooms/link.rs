@@ -0,0 +1,212 @@
+use crate::controller::{Room, RoomCodeError};
+use std::fmt::Write;
+
+static PREFIX: &str = "terracotta://join/";
+
+/// A request to join a room, either from a deep-link such as
+/// 'terracotta://join/U/XXXX-XXXX-XXXX-XXXX?player=Steve' or from a plain room code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JoinLink {
+    pub room: String,
+    pub passphrase: Option<String>,
+    pub invite: Option<String>,
+    pub player: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum JoinLinkError {
+    InvalidEncoding,
+    InvalidRoomCode(RoomCodeError),
+}
+
+impl JoinLink {
+    /// Create a link for sharing the room. Passphrase of a protected room is never included.
+    pub fn of(room: &Room) -> JoinLink {
+        JoinLink { room: room.code.clone(), passphrase: None, invite: None, player: None }
+    }
+
+    pub fn parse(link: &str) -> Result<JoinLink, JoinLinkError> {
+        let link = link.trim();
+
+        let (room, query) = match link.get(..PREFIX.len()) {
+            Some(prefix) if prefix.eq_ignore_ascii_case(PREFIX) => {
+                let link = &link[PREFIX.len()..];
+                match link.split_once('?') {
+                    Some((room, query)) => (decode(room.trim_end_matches('/'))?, query),
+                    None => (decode(link.trim_end_matches('/'))?, ""),
+                }
+            }
+            _ => (link.to_string(), ""),
+        };
+
+        let mut passphrase: Option<String> = None;
+        let mut invite: Option<String> = None;
+        let mut player: Option<String> = None;
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = decode(value)?;
+            match key {
+                "player" if !value.is_empty() => player = Some(value),
+                "passphrase" if !value.is_empty() => passphrase = Some(value),
+                "invite" if !value.is_empty() => invite = Some(value),
+                _ => {}
+            }
+        }
+
+        // Normalize the room code, so that typos are reported before reaching the running instance.
+        let room = match Room::from(&room, passphrase.as_deref()) {
+            Ok(parsed) => parsed.code,
+            // The passphrase of a protected room may be supplied by user later.
+            Err(RoomCodeError::MissingPassphrase) => room,
+            Err(e) => return Err(JoinLinkError::InvalidRoomCode(e)),
+        };
+
+        Ok(JoinLink { room, passphrase, invite, player })
+    }
+
+    pub fn format(&self) -> String {
+        let mut link = String::from(PREFIX);
+        link.push_str(&self.room);
+
+        let mut separator = '?';
+        for (key, value) in [("player", &self.player), ("passphrase", &self.passphrase), ("invite", &self.invite)] {
+            if let Some(value) = value {
+                link.push(separator);
+                link.push_str(key);
+                link.push('=');
+                encode(&mut link, value);
+                separator = '&';
+            }
+        }
+        link
+    }
+}
+
+fn decode(value: &str) -> Result<String, JoinLinkError> {
+    let bytes = value.as_bytes();
+    let mut output: Vec<u8> = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                // from_str_radix accepts a leading sign, which isn't a hex digit.
+                let byte = value.get(i + 1..i + 3)
+                    .filter(|hex| hex.bytes().all(|byte| byte.is_ascii_hexdigit()))
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .ok_or(JoinLinkError::InvalidEncoding)?;
+                output.push(byte);
+                i += 3;
+            }
+            b'+' => {
+                output.push(b' ');
+                i += 1;
+            }
+            byte => {
+                output.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8(output).map_err(|_| JoinLinkError::InvalidEncoding)
+}
+
+fn encode(output: &mut String, value: &str) {
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~' | b'/') {
+            output.push(byte as char);
+        } else {
+            write!(output, "%{:02X}", byte).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(link: &JoinLink) {
+        let formatted = link.format();
+        assert_eq!(JoinLink::parse(&formatted).as_ref(), Ok(link), "{}", formatted);
+        assert_eq!(JoinLink::parse(&formatted).unwrap().format(), formatted);
+    }
+
+    #[test]
+    fn unprotected_room() {
+        let room = Room::create(None);
+        let link = JoinLink { player: Some("Steve".to_string()), ..JoinLink::of(&room) };
+        assert!(link.format().starts_with("terracotta://join/U/"));
+        round_trip(&link);
+        round_trip(&JoinLink { invite: Some("0123456789abcdef".to_string()), ..link });
+    }
+
+    #[test]
+    fn protected_room() {
+        let room = Room::create(Some("secret".to_string()));
+        assert!(JoinLink::of(&room).format().starts_with("terracotta://join/P/"));
+        assert!(!JoinLink::of(&room).format().contains("secret"));
+
+        round_trip(&JoinLink::of(&room));
+        round_trip(&JoinLink { passphrase: Some("secret".to_string()), player: Some("Alex".to_string()), ..JoinLink::of(&room) });
+    }
+
+    #[test]
+    fn plain_code() {
+        let room = Room::create(None);
+        let link = JoinLink::parse(&format!("  {}\n", room.code)).unwrap();
+        assert_eq!(link, JoinLink::of(&room));
+
+        let link = JoinLink::parse(&format!("TERRACOTTA://JOIN/{}/", room.code)).unwrap();
+        assert_eq!(link, JoinLink::of(&room));
+    }
+
+    #[test]
+    fn percent_encoding() {
+        let room = Room::create(Some("p@ss word&=?".to_string()));
+        let link = JoinLink {
+            passphrase: Some("p@ss word&=?".to_string()),
+            player: Some("史蒂夫 & Co.".to_string()),
+            ..JoinLink::of(&room)
+        };
+        let formatted = link.format();
+        assert!(formatted.contains("passphrase=p%40ss%20word%26%3D%3F"), "{}", formatted);
+        round_trip(&link);
+
+        let parsed = JoinLink::parse(&format!("{}{}?player=Steve+Jobs&passphrase=p%40ss+word%26%3d%3f", PREFIX, room.code)).unwrap();
+        assert_eq!(parsed.player.as_deref(), Some("Steve Jobs"));
+        assert_eq!(parsed.passphrase.as_deref(), Some("p@ss word&=?"));
+    }
+
+    #[test]
+    fn missing_passphrase() {
+        let room = Room::create(Some("secret".to_string()));
+        let link = JoinLink::parse(&format!("{}{}?player=Steve", PREFIX, room.code)).unwrap();
+        assert_eq!(link.room, room.code);
+        assert_eq!(link.passphrase, None);
+    }
+
+    #[test]
+    fn malformed_links() {
+        let room = Room::create(None);
+        let code = &room.code;
+
+        assert_eq!(JoinLink::parse(&format!("{}{}?player=%E4%B8", PREFIX, code)), Err(JoinLinkError::InvalidEncoding));
+        assert_eq!(JoinLink::parse(&format!("{}{}?player=%G0", PREFIX, code)), Err(JoinLinkError::InvalidEncoding));
+        assert_eq!(JoinLink::parse(&format!("{}{}?player=%4", PREFIX, code)), Err(JoinLinkError::InvalidEncoding));
+        assert_eq!(JoinLink::parse(&format!("{}{}?player=%+1", PREFIX, code)), Err(JoinLinkError::InvalidEncoding));
+        assert_eq!(JoinLink::parse(&format!("{}{}?player=%-1", PREFIX, code)), Err(JoinLinkError::InvalidEncoding));
+        assert_eq!(JoinLink::parse(&format!("{}{}%", PREFIX, code)), Err(JoinLinkError::InvalidEncoding));
+
+        assert!(matches!(JoinLink::parse(PREFIX), Err(JoinLinkError::InvalidRoomCode(_))));
+        assert_eq!(
+            JoinLink::parse(&format!("{}{}", PREFIX, &code[2..])),
+            Err(JoinLinkError::InvalidRoomCode(RoomCodeError::MissingPrefix))
+        );
+        assert!(matches!(
+            JoinLink::parse(&format!("{}{}", PREFIX, &code[..code.len() - 1])),
+            Err(JoinLinkError::InvalidRoomCode(RoomCodeError::InvalidLength { .. }))
+        ));
+        assert!(matches!(JoinLink::parse("https://example.com/join/U/0000"), Err(JoinLinkError::InvalidRoomCode(_))));
+    }
+}
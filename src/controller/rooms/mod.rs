@@ -1,4 +1,10 @@
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+
 pub mod scaffolding;
+mod link;
+
+pub use link::*;
 
 #[derive(Debug, Clone)]
 pub struct Room {
@@ -12,7 +18,38 @@ pub struct Room {
 
 #[derive(Debug, Clone)]
 pub enum RoomKind {
-    Scaffolding { #[allow(dead_code)] seed: u128 }
+    Scaffolding { seed: u128 },
+    /// A room whose network secret is a passphrase chosen by host, rather than a part of the room code.
+    Protected { seed: u128 },
+}
+
+/// Reasons for rejecting a room code. Positions are 1-based character indexes into the user's input.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum RoomCodeError {
+    MissingPrefix,
+    InvalidLength { expected: usize, found: usize },
+    IllegalCharacter { position: usize, character: char },
+    MisplacedDash { position: usize },
+    InvalidChecksum,
+    MissingPassphrase,
+}
+
+impl Display for RoomCodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RoomCodeError::MissingPrefix => write!(f, "Room code must start with 'U/' or 'P/'."),
+            RoomCodeError::InvalidLength { expected, found } => {
+                write!(f, "Room code must be {} characters long, but {} characters are found.", expected, found)
+            }
+            RoomCodeError::IllegalCharacter { position, character } => {
+                write!(f, "Illegal character '{}' at position {}.", character, position)
+            }
+            RoomCodeError::MisplacedDash { position } => write!(f, "Misplaced dash at position {}.", position),
+            RoomCodeError::InvalidChecksum => write!(f, "Room code checksum mismatches, some characters may be mistyped."),
+            RoomCodeError::MissingPassphrase => write!(f, "Room is protected by a passphrase, which is required to join."),
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -21,11 +58,20 @@ pub enum ConnectionDifficulty {
 }
 
 impl Room {
-    pub fn create() -> Room {
-        scaffolding::create_room()
+    pub fn create(passphrase: Option<String>) -> Room {
+        scaffolding::create_room(passphrase)
     }
 
-    pub fn from(code: &str) -> Option<Room> {
-        scaffolding::parse(code)
+    /// Parse a room code. A passphrase is required for protected rooms, and is ignored for others.
+    pub fn from(code: &str, passphrase: Option<&str>) -> Result<Room, RoomCodeError> {
+        scaffolding::parse(code, passphrase)
+    }
+}
+
+impl RoomKind {
+    pub fn seed(&self) -> u128 {
+        match self {
+            RoomKind::Scaffolding { seed } | RoomKind::Protected { seed } => *seed,
+        }
     }
 }
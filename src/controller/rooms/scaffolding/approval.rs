@@ -0,0 +1,106 @@
+use crate::scaffolding::profile::Profile;
+use std::time::{Duration, SystemTime};
+
+// Denied guests are told within this duration, after which they may ask again.
+const DENIAL_NOTICE: Duration = Duration::from_secs(30);
+// Pending guests are dropped after being silent for this duration, like guests in the room.
+const PENDING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Joins awaiting approval of host, while approve-joins mode is enabled.
+#[derive(Debug, Default)]
+pub struct ApprovalQueue {
+    pub enabled: bool,
+    /// Guests waiting for approval, with the time of their latest c:player_ping.
+    pub pending: Vec<(SystemTime, Profile)>,
+    /// Machine IDs which have been approved, or had joined before approve-joins mode is enabled.
+    approved: Vec<String>,
+    /// Machine IDs denied by host, which are told on their next c:player_ping.
+    denied: Vec<(SystemTime, String)>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Admission {
+    Approved,
+    /// The guest is waiting in queue. Whether it has just been added is carried.
+    Pending(bool),
+    Denied,
+}
+
+impl ApprovalQueue {
+    pub fn is_approved(&self, machine_id: &str) -> bool {
+        !self.enabled || self.approved.iter().any(|approved| approved == machine_id)
+    }
+
+    /// Handle c:player_ping of a guest which isn't in the room.
+    pub fn request(&mut self, machine_id: &str, profile: impl FnOnce() -> Profile) -> Admission {
+        if self.is_approved(machine_id) {
+            return Admission::Approved;
+        }
+
+        let now = SystemTime::now();
+        self.denied.retain(|(time, _)| now.duration_since(*time).is_ok_and(|d| d < DENIAL_NOTICE));
+        if let Some(index) = self.denied.iter().position(|(_, denied)| denied == machine_id) {
+            self.denied.remove(index);
+            return Admission::Denied;
+        }
+
+        match self.pending.iter_mut().find(|(_, profile)| profile.get_machine_id() == machine_id) {
+            Some((time, _)) => {
+                *time = now;
+                Admission::Pending(false)
+            }
+            None => {
+                self.pending.push((now, profile()));
+                Admission::Pending(true)
+            }
+        }
+    }
+
+    /// Switch approve-joins mode. Guests which have already joined are approved.
+    pub fn set_enabled<'a>(&mut self, enabled: bool, members: impl Iterator<Item = &'a str>) {
+        self.enabled = enabled;
+        if enabled {
+            for member in members {
+                self.approve_id(member);
+            }
+        } else {
+            // Pending guests are admitted on their next c:player_ping.
+            self.pending.clear();
+        }
+    }
+
+    /// Approve a pending guest, returning its profile.
+    pub fn approve(&mut self, machine_id: &str) -> Option<Profile> {
+        let index = self.pending.iter().position(|(_, profile)| profile.get_machine_id() == machine_id)?;
+        self.approve_id(machine_id);
+        Some(self.pending.remove(index).1)
+    }
+
+    /// Deny a pending guest. Returns false if it isn't in queue.
+    pub fn deny(&mut self, machine_id: &str) -> bool {
+        let Some(index) = self.pending.iter().position(|(_, profile)| profile.get_machine_id() == machine_id) else {
+            return false;
+        };
+        self.pending.remove(index);
+        self.denied.push((SystemTime::now(), machine_id.to_string()));
+        true
+    }
+
+    /// Forget the approval of a guest, which has been removed by host.
+    pub fn revoke(&mut self, machine_id: &str) {
+        self.approved.retain(|approved| approved != machine_id);
+    }
+
+    /// Drop pending guests which have stopped asking. Returns whether the queue is changed.
+    pub fn expire(&mut self, now: SystemTime) -> bool {
+        let length = self.pending.len();
+        self.pending.retain(|(time, _)| !now.duration_since(*time).is_ok_and(|d| d >= PENDING_TIMEOUT));
+        self.pending.len() != length
+    }
+
+    fn approve_id(&mut self, machine_id: &str) {
+        if !self.approved.iter().any(|approved| approved == machine_id) {
+            self.approved.push(machine_id.to_string());
+        }
+    }
+}
@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Older messages are dropped, so guests joining later only receive the recent history.
+const CAPACITY: usize = 200;
+/// Maximum length of a message in characters.
+pub const MAX_LENGTH: usize = 512;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChatMessage {
+    /// Assigned by host in ascending order, which is also the cursor of c:chat_fetch.
+    pub id: u64,
+    pub machine_id: String,
+    /// Name of the sender when the message is sent.
+    pub name: String,
+    pub text: String,
+    /// Unix timestamp in milliseconds when host receives the message.
+    pub time: u64,
+}
+
+/// Lobby chat of a room, which lives as long as the room. Host keeps the history, which is mirrored by guests.
+#[derive(Debug, Default)]
+pub struct ChatLog {
+    /// The ID of the next message.
+    next: u64,
+    messages: VecDeque<ChatMessage>,
+}
+
+impl ChatLog {
+    /// Trim the text of a message, returning None if it's empty or too long.
+    pub fn normalize(text: &str) -> Option<String> {
+        let text = text.trim();
+        if text.is_empty() || text.chars().count() > MAX_LENGTH {
+            return None;
+        }
+        Some(text.chars().map(|c| if c.is_control() && c != '\n' { ' ' } else { c }).collect())
+    }
+
+    /// Append a message on host. Text must have been normalized.
+    pub fn post(&mut self, machine_id: &str, name: &str, text: String) -> &ChatMessage {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+        self.push(ChatMessage { id: self.next, machine_id: machine_id.to_string(), name: name.to_string(), text, time });
+        self.messages.back().unwrap()
+    }
+
+    /// Messages from cursor on, together with the cursor of the next fetch.
+    /// All retained messages are returned if cursor is older than them.
+    pub fn fetch(&self, cursor: u64) -> (u64, Vec<ChatMessage>) {
+        (self.next, self.messages.iter().filter(|message| message.id >= cursor).cloned().collect())
+    }
+
+    pub fn cursor(&self) -> u64 {
+        self.next
+    }
+
+    /// Mirror messages fetched from host on guest. Returns whether any message is added.
+    pub fn merge(&mut self, messages: Vec<ChatMessage>) -> bool {
+        let mut changed = false;
+        for message in messages {
+            if message.id >= self.next {
+                self.push(message);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    fn push(&mut self, message: ChatMessage) {
+        if self.messages.len() == CAPACITY {
+            self.messages.pop_front();
+        }
+        self.next = message.id + 1;
+        self.messages.push_back(message);
+    }
+}
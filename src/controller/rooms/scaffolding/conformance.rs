@@ -0,0 +1,333 @@
+//! Conformance suite of Terracotta's own Scaffolding implementation, run by `terracotta scaffolding conformance`
+//! and by `cargo test`.
+//!
+//! Besides the wire suite in [crate::scaffolding::conformance], which is run against an in-process server, every entry
+//! of [HANDLERS] is invoked in each state which can be entered without EasyTier, and guest-side profile reconciliation
+//! chat history and join approval are checked against fixtures. HostOk is covered by running the wire suite against a live room instead.
+
+use crate::controller::scaffolding::approval::{Admission, ApprovalQueue};
+use crate::controller::scaffolding::chat::{ChatLog, ChatMessage, MAX_LENGTH};
+use crate::controller::scaffolding::events::RoomEvent;
+use crate::controller::scaffolding::protocols::{room_secret, HANDLERS};
+use crate::controller::scaffolding::reconcile::{self, Applied};
+use crate::controller::states::{AppState, ExceptionType};
+use crate::controller::Room;
+use crate::scaffolding::conformance::{self, Check, Suite};
+use crate::scaffolding::limits::ServerLimits;
+use crate::scaffolding::profile::{Profile, ProfileKind, ProfileSnapshot};
+use crate::scaffolding::server;
+use serde_json::{json, Value};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::{Duration, SystemTime};
+
+const SECRET: &str = "CONFORMANCE";
+const LOCAL: &str = "00000000000000000000000000000001";
+const HOST: &str = "00000000000000000000000000000002";
+const GUEST: &str = "00000000000000000000000000000003";
+const OTHER: &str = "00000000000000000000000000000004";
+
+/// Run the whole suite. Returns whether all checks have passed.
+pub fn run() -> bool {
+    let mut suite = Suite::new();
+
+    match server::start(&HANDLERS, || Some(SECRET.to_string()), ServerLimits::default(), 0) {
+        Ok(port) => conformance::run(&mut suite, SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port), Some(SECRET), false),
+        Err(e) => suite.check("Scaffolding server starts", || Err(e.to_string())),
+    }
+
+    check_handlers(&mut suite);
+    check_reconciliation(&mut suite);
+    check_chat(&mut suite);
+    check_approval(&mut suite);
+
+    AppState::acquire().set(AppState::Waiting);
+    suite.finish()
+}
+
+fn ensure(condition: bool, message: impl FnOnce() -> String) -> Check {
+    if condition { Ok(()) } else { Err(message()) }
+}
+
+fn check_handlers(suite: &mut Suite) {
+    type Factory = fn() -> AppState;
+    let states: [(&str, Factory); 4] = [
+        ("Waiting", || AppState::Waiting),
+        ("HostStarting", || AppState::HostStarting { room: Room::create(None), port: 25565 }),
+        ("GuestConnecting", || AppState::GuestConnecting { room: Room::create(None) }),
+        ("Exception", || AppState::Exception { kind: ExceptionType::PingHostFail }),
+    ];
+
+    for (name, state) in states {
+        suite.group(&format!("Handlers in {}", name));
+        AppState::acquire().set(state());
+
+        suite.check("Room secret is unavailable", || ensure(room_secret().is_none(), || "Secret is available.".to_string()));
+        for protocol in HANDLERS.protocols() {
+            suite.check(&protocol, || check_handler(&protocol));
+        }
+    }
+}
+
+/// Invoke a handler outside of HostOk, where protocols depending on the room must fail without side effects.
+fn check_handler(protocol: &str) -> Check {
+    let profile = json!({ "machine_id": GUEST, "name": "Conformance", "vendor": "Terracotta Conformance" });
+    let (body, expected) = match protocol {
+        "c:ping" => (b"conformance".to_vec(), 0),
+        "c:protocols" | "c:auth" | "c:pipeline" => (vec![], 0),
+        "c:server_port" | "c:server_edition" => (vec![], 32),
+        "c:player_ping" => (profile.to_string().into_bytes(), 255),
+        "c:player_invite" => (json!({ "machine_id": GUEST, "invite": "conformance" }).to_string().into_bytes(), 255),
+        "c:subscribe" => (json!({ "machine_id": GUEST, "cursor": null }).to_string().into_bytes(), 0),
+        "c:player_profiles_list" => (vec![], 255),
+        "c:chat_send" => (json!({ "machine_id": GUEST, "text": "Conformance" }).to_string().into_bytes(), 255),
+        "c:chat_fetch" => (json!({ "cursor": null }).to_string().into_bytes(), 255),
+        "c:mods_manifest" => (vec![], 255),
+        _ => return Err("No expectation is defined for this protocol.".to_string()),
+    };
+
+    let response = server::dispatch(protocol, &body, &HANDLERS);
+    let (status, data) = (response[0], &response[5..]);
+    ensure(status == expected, || format!("Status is {}, expecting {}: {}", status, expected, String::from_utf8_lossy(data)))?;
+
+    match protocol {
+        "c:ping" => ensure(data == body, || "Response doesn't match the request body.".to_string()),
+        "c:protocols" => {
+            let listed = String::from_utf8_lossy(data).split('\0').map(str::to_string).collect::<Vec<_>>();
+            ensure(listed == HANDLERS.protocols(), || format!("Listed protocols are {:?}.", listed))
+        }
+        // Guests are told to stop, as no room is available.
+        "c:subscribe" => {
+            let value = serde_json::from_slice::<Value>(data).map_err(|e| e.to_string())?;
+            let events = value.get("events").and_then(Value::as_array).ok_or("Events are missing.")?;
+            ensure(
+                events.iter().any(|event| serde_json::from_value::<RoomEvent>(event.clone()).is_ok_and(|event| matches!(event, RoomEvent::Closing))),
+                || format!("Closing event is missing: {}", value),
+            )
+        }
+        _ => Ok(()),
+    }
+}
+
+fn profile(machine_id: &str, name: &str, kind: ProfileKind) -> Profile {
+    ProfileSnapshot { machine_id: machine_id.to_string(), name: name.to_string(), vendor: "Conformance".to_string(), kind }.into_profile()
+}
+
+fn list(profiles: &[(&str, &str, &str)]) -> Vec<u8> {
+    serde_json::to_vec(&profiles.iter()
+        .map(|(machine_id, name, kind)| json!({ "machine_id": machine_id, "name": name, "vendor": "Conformance", "kind": kind }))
+        .collect::<Vec<_>>()).unwrap()
+}
+
+/// Summarize profiles as (machine_id, name, kind), sorted by machine ID.
+fn summary(profiles: &[Profile]) -> Vec<(String, String, ProfileKind)> {
+    let mut summary = profiles.iter()
+        .map(|profile| (profile.get_machine_id().to_string(), profile.get_name().to_string(), *profile.get_kind()))
+        .collect::<Vec<_>>();
+    summary.sort_by(|a, b| a.0.cmp(&b.0));
+    summary
+}
+
+fn expect_summary(profiles: &[Profile], expected: &[(&str, &str, ProfileKind)]) -> Check {
+    let expected = expected.iter().map(|(m, n, k)| (m.to_string(), n.to_string(), *k)).collect::<Vec<_>>();
+    let actual = summary(profiles);
+    ensure(actual == expected, || format!("Profiles are {:?}, expecting {:?}.", actual, expected))
+}
+
+fn check_reconciliation(suite: &mut Suite) {
+    let local = profile(LOCAL, "Local", ProfileKind::LOCAL);
+
+    suite.group("Profile list parsing");
+    suite.check("Local player is added if host hasn't listed it", || {
+        let profiles = reconcile::parse_profiles(&list(&[(HOST, "Host", "HOST"), (GUEST, "Guest", "GUEST")]), &local).ok_or("List is refused.")?;
+        expect_summary(&profiles, &[(LOCAL, "Local", ProfileKind::LOCAL), (HOST, "Host", ProfileKind::HOST), (GUEST, "Guest", ProfileKind::GUEST)])
+    });
+    suite.check("Local player listed by host is marked as LOCAL", || {
+        let profiles = reconcile::parse_profiles(&list(&[(HOST, "Host", "HOST"), (LOCAL, "Renamed", "GUEST")]), &local).ok_or("List is refused.")?;
+        expect_summary(&profiles, &[(LOCAL, "Renamed", ProfileKind::LOCAL), (HOST, "Host", ProfileKind::HOST)])
+    });
+    for (name, data) in [
+        ("List without host is refused", list(&[(GUEST, "Guest", "GUEST")])),
+        ("List with two hosts is refused", list(&[(HOST, "Host", "HOST"), (GUEST, "Guest", "HOST")])),
+        ("List with duplicated machine IDs is refused", list(&[(HOST, "Host", "HOST"), (GUEST, "A", "GUEST"), (GUEST, "B", "GUEST")])),
+        ("List with local player twice is refused", list(&[(HOST, "Host", "HOST"), (LOCAL, "A", "GUEST"), (LOCAL, "B", "GUEST")])),
+        ("List with unknown kind is refused", list(&[(HOST, "Host", "HOST"), (GUEST, "Guest", "OWNER")])),
+        ("Malformed list is refused", b"{\"machine_id\":\"\"}".to_vec()),
+    ] {
+        suite.check(name, || ensure(reconcile::parse_profiles(&data, &local).is_none(), || "List is accepted.".to_string()));
+    }
+
+    suite.group("Profile reconciliation");
+    let server_profiles = |profiles: &[(&str, &str, &str)]| reconcile::parse_profiles(&list(profiles), &local).unwrap();
+    suite.check("First list adds host and guests", || {
+        let mut profiles = vec![local.clone()];
+        let changed = reconcile::reconcile(&mut profiles, server_profiles(&[(HOST, "Host", "HOST"), (GUEST, "Guest", "GUEST")]));
+        ensure(changed == Some(true), || format!("Result is {:?}.", changed))?;
+        expect_summary(&profiles, &[(LOCAL, "Local", ProfileKind::LOCAL), (HOST, "Host", ProfileKind::HOST), (GUEST, "Guest", ProfileKind::GUEST)])
+    });
+    suite.check("Identical list changes nothing", || {
+        let mut profiles = vec![local.clone(), profile(HOST, "Host", ProfileKind::HOST), profile(GUEST, "Guest", ProfileKind::GUEST)];
+        let changed = reconcile::reconcile(&mut profiles, server_profiles(&[(HOST, "Host", "HOST"), (GUEST, "Guest", "GUEST")]));
+        ensure(changed == Some(false), || format!("Result is {:?}.", changed))
+    });
+    suite.check("Names are updated, and departed guests are removed", || {
+        let mut profiles = vec![local.clone(), profile(HOST, "Host", ProfileKind::HOST), profile(GUEST, "Guest", ProfileKind::GUEST)];
+        let changed = reconcile::reconcile(&mut profiles, server_profiles(&[(HOST, "New Host", "HOST"), (OTHER, "Other", "GUEST")]));
+        ensure(changed == Some(true), || format!("Result is {:?}.", changed))?;
+        expect_summary(&profiles, &[(LOCAL, "Local", ProfileKind::LOCAL), (HOST, "New Host", ProfileKind::HOST), (OTHER, "Other", ProfileKind::GUEST)])
+    });
+    suite.check("Local player keeps its own name", || {
+        let mut profiles = vec![local.clone(), profile(HOST, "Host", ProfileKind::HOST)];
+        reconcile::reconcile(&mut profiles, server_profiles(&[(HOST, "Host", "HOST"), (LOCAL, "Renamed", "GUEST")])).ok_or("List is refused.")?;
+        expect_summary(&profiles, &[(LOCAL, "Local", ProfileKind::LOCAL), (HOST, "Host", ProfileKind::HOST)])
+    });
+    suite.check("Replaced host is refused", || {
+        let mut profiles = vec![local.clone(), profile(HOST, "Host", ProfileKind::HOST)];
+        let changed = reconcile::reconcile(&mut profiles, server_profiles(&[(OTHER, "Other", "HOST")]));
+        ensure(changed.is_none(), || format!("Result is {:?}.", changed))
+    });
+    suite.check("Guest turning into host is refused", || {
+        let mut profiles = vec![local.clone(), profile(HOST, "Host", ProfileKind::HOST), profile(GUEST, "Guest", ProfileKind::GUEST)];
+        let changed = reconcile::reconcile(&mut profiles, server_profiles(&[(GUEST, "Guest", "HOST"), (HOST, "Host", "GUEST")]));
+        ensure(changed.is_none(), || format!("Result is {:?}.", changed))
+    });
+
+    suite.group("Room events");
+    let initial = || vec![local.clone(), profile(HOST, "Host", ProfileKind::HOST), profile(GUEST, "Guest", ProfileKind::GUEST)];
+    let apply = |events: Vec<RoomEvent>, expected_applied: Applied, expected: &[(&str, &str, ProfileKind)]| -> Check {
+        let mut profiles = initial();
+        let applied = reconcile::apply_events(&mut profiles, events, LOCAL);
+        ensure(applied == expected_applied, || format!("Result is {:?}, expecting {:?}.", applied, expected_applied))?;
+        expect_summary(&profiles, expected)
+    };
+    let unchanged = [(LOCAL, "Local", ProfileKind::LOCAL), (HOST, "Host", ProfileKind::HOST), (GUEST, "Guest", ProfileKind::GUEST)];
+    let join = |machine_id: &str| RoomEvent::Join { machine_id: machine_id.to_string(), name: "Other".to_string(), vendor: "Conformance".to_string() };
+    let leave = |machine_id: &str| RoomEvent::Leave { machine_id: machine_id.to_string() };
+    let rename = |machine_id: &str| RoomEvent::Rename { machine_id: machine_id.to_string(), name: "Renamed".to_string() };
+
+    suite.check("Join adds a guest", || apply(
+        vec![join(OTHER)],
+        Applied { changed: true, ..Applied::default() },
+        &[(LOCAL, "Local", ProfileKind::LOCAL), (HOST, "Host", ProfileKind::HOST), (GUEST, "Guest", ProfileKind::GUEST), (OTHER, "Other", ProfileKind::GUEST)],
+    ));
+    suite.check("Join of a known player or local player is ignored", || apply(vec![join(GUEST), join(HOST), join(LOCAL)], Applied::default(), &unchanged));
+    suite.check("Leave removes a guest", || apply(
+        vec![leave(GUEST)],
+        Applied { changed: true, ..Applied::default() },
+        &[(LOCAL, "Local", ProfileKind::LOCAL), (HOST, "Host", ProfileKind::HOST)],
+    ));
+    suite.check("Leave of host is ignored", || apply(vec![leave(HOST)], Applied::default(), &unchanged));
+    suite.check("Leave of local player requires a resync", || apply(vec![leave(LOCAL)], Applied { resync: true, ..Applied::default() }, &unchanged));
+    suite.check("Rename updates host and guests", || apply(
+        vec![rename(HOST), rename(GUEST)],
+        Applied { changed: true, ..Applied::default() },
+        &[(LOCAL, "Local", ProfileKind::LOCAL), (HOST, "Renamed", ProfileKind::HOST), (GUEST, "Renamed", ProfileKind::GUEST)],
+    ));
+    suite.check("Rename of local player is ignored", || apply(vec![rename(LOCAL)], Applied::default(), &unchanged));
+    suite.check("Chat requests a fetch", || apply(vec![RoomEvent::Chat], Applied { chat: true, ..Applied::default() }, &unchanged));
+    suite.check("Server requests a port query", || apply(vec![RoomEvent::Server], Applied { server: true, ..Applied::default() }, &unchanged));
+    suite.check("Events after Closing aren't applied", || apply(vec![RoomEvent::Closing, join(OTHER)], Applied { closing: true, ..Applied::default() }, &unchanged));
+}
+
+fn check_chat(suite: &mut Suite) {
+    suite.group("Chat history");
+    let ids = |messages: &[ChatMessage]| messages.iter().map(|message| message.id).collect::<Vec<_>>();
+
+    suite.check("Text is normalized", || {
+        ensure(ChatLog::normalize("  Hello\tworld \n").as_deref() == Some("Hello world"), || "Text isn't trimmed.".to_string())?;
+        ensure(ChatLog::normalize(" \n ").is_none(), || "Blank text is accepted.".to_string())?;
+        ensure(ChatLog::normalize(&"a".repeat(MAX_LENGTH)).is_some(), || "Text of maximum length is refused.".to_string())?;
+        ensure(ChatLog::normalize(&"a".repeat(MAX_LENGTH + 1)).is_none(), || "Text exceeding maximum length is accepted.".to_string())
+    });
+    suite.check("Fetch returns messages from cursor on", || {
+        let mut chat = ChatLog::default();
+        for text in ["First", "Second", "Third"] {
+            chat.post(GUEST, "Guest", text.to_string());
+        }
+        let (cursor, messages) = chat.fetch(0);
+        ensure(cursor == 3 && ids(&messages) == [0, 1, 2], || format!("Fetched {:?} with cursor {}.", ids(&messages), cursor))?;
+        let (cursor, messages) = chat.fetch(2);
+        ensure(cursor == 3 && ids(&messages) == [2], || format!("Fetched {:?} with cursor {}.", ids(&messages), cursor))?;
+        let (cursor, messages) = chat.fetch(3);
+        ensure(cursor == 3 && messages.is_empty(), || format!("Fetched {:?} with cursor {}.", ids(&messages), cursor))
+    });
+    suite.check("History is bounded", || {
+        let mut chat = ChatLog::default();
+        for i in 0..1000 {
+            chat.post(GUEST, "Guest", i.to_string());
+        }
+        let (cursor, messages) = chat.fetch(0);
+        ensure(cursor == 1000 && messages.len() < 1000 && messages.last().is_some_and(|message| message.id == 999), || {
+            format!("Fetched {} messages with cursor {}.", messages.len(), cursor)
+        })
+    });
+    suite.check("Guest mirrors host without duplicates", || {
+        let mut host = ChatLog::default();
+        let mut guest = ChatLog::default();
+        host.post(HOST, "Host", "First".to_string());
+        ensure(guest.merge(host.fetch(guest.cursor()).1), || "First message isn't merged.".to_string())?;
+        host.post(GUEST, "Guest", "Second".to_string());
+        // A stale fetch may overlap with the messages known by guest.
+        ensure(guest.merge(host.fetch(0).1), || "Second message isn't merged.".to_string())?;
+        ensure(!guest.merge(host.fetch(0).1), || "Known messages are merged again.".to_string())?;
+        ensure(guest.fetch(0) == host.fetch(0), || format!("Guest has {:?}, host has {:?}.", guest.fetch(0), host.fetch(0)))
+    });
+}
+
+fn check_approval(suite: &mut Suite) {
+    suite.group("Join approval");
+    let guest = || profile(GUEST, "Guest", ProfileKind::GUEST);
+    let enabled = || {
+        let mut approval = ApprovalQueue::default();
+        approval.set_enabled(true, [HOST].into_iter());
+        approval
+    };
+    let expect = |admission: Admission, expected: Admission| ensure(admission == expected, || format!("Result is {:?}, expecting {:?}.", admission, expected));
+
+    suite.check("Joins are admitted while disabled", || {
+        let mut approval = ApprovalQueue::default();
+        expect(approval.request(GUEST, guest), Admission::Approved)
+    });
+    suite.check("Unknown guests wait in queue once", || {
+        let mut approval = enabled();
+        expect(approval.request(GUEST, guest), Admission::Pending(true))?;
+        expect(approval.request(GUEST, guest), Admission::Pending(false))?;
+        ensure(approval.pending.len() == 1 && !approval.is_approved(GUEST), || format!("Queue is {:?}.", approval.pending))
+    });
+    suite.check("Members are approved when enabling", || {
+        let mut approval = enabled();
+        expect(approval.request(HOST, guest), Admission::Approved)
+    });
+    suite.check("Approved guests are admitted", || {
+        let mut approval = enabled();
+        approval.request(GUEST, guest);
+        let approved = approval.approve(GUEST).ok_or("Guest isn't in queue.")?;
+        ensure(approved.get_machine_id() == GUEST && approval.pending.is_empty(), || format!("Approved {:?}.", approved))?;
+        expect(approval.request(GUEST, guest), Admission::Approved)
+    });
+    suite.check("Denied guests are told once", || {
+        let mut approval = enabled();
+        approval.request(GUEST, guest);
+        ensure(approval.deny(GUEST) && !approval.deny(GUEST), || "Guest is denied twice.".to_string())?;
+        expect(approval.request(GUEST, guest), Admission::Denied)?;
+        expect(approval.request(GUEST, guest), Admission::Pending(true))
+    });
+    suite.check("Revoked guests wait in queue again", || {
+        let mut approval = enabled();
+        approval.revoke(HOST);
+        expect(approval.request(HOST, guest), Admission::Pending(true))
+    });
+    suite.check("Silent guests leave the queue", || {
+        let mut approval = enabled();
+        approval.request(GUEST, guest);
+        ensure(!approval.expire(SystemTime::now()), || "Guest expires immediately.".to_string())?;
+        ensure(approval.expire(SystemTime::now() + Duration::from_secs(60)) && approval.pending.is_empty(), || "Guest doesn't expire.".to_string())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn conformance() {
+        assert!(super::run(), "Conformance suite has failed, see the output above.");
+    }
+}
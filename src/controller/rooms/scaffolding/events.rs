@@ -0,0 +1,79 @@
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+// Subscribers falling behind by more than this many events are asked to resync the whole player list.
+const CAPACITY: usize = 256;
+
+/// Changes of the room streamed to guests through c:subscribe.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RoomEvent {
+    Join { machine_id: String, name: String, vendor: String },
+    Leave { machine_id: String },
+    Rename { machine_id: String, name: String },
+    /// New chat messages are available through c:chat_fetch.
+    Chat,
+    /// Minecraft server of host has stopped or reopened, whose port should be queried again through c:server_port.
+    Server,
+    Closing,
+}
+
+pub struct Update {
+    /// The cursor to be sent by subscriber in its next request.
+    pub cursor: u64,
+    pub events: Vec<RoomEvent>,
+    /// Whether some events have been dropped, so that subscriber must fetch the whole player list again.
+    pub lost: bool,
+}
+
+struct EventLog {
+    /// The cursor of the next event, which is also the number of events published.
+    next: u64,
+    events: VecDeque<RoomEvent>,
+}
+
+static LOG: Mutex<EventLog> = Mutex::new(EventLog { next: 0, events: VecDeque::new() });
+static WATCHERS: Mutex<Vec<fn()>> = Mutex::new(vec![]);
+
+pub fn publish(event: RoomEvent) {
+    let mut log = LOG.lock();
+    if log.events.len() == CAPACITY {
+        log.events.pop_front();
+    }
+    log.events.push_back(event);
+    log.next += 1;
+    drop(log);
+
+    for watcher in WATCHERS.lock().iter() {
+        watcher();
+    }
+}
+
+/// Call watcher whenever an event is published, which wakes subscriptions waiting for events.
+pub fn watch(watcher: fn()) {
+    WATCHERS.lock().push(watcher);
+}
+
+pub fn latest() -> u64 {
+    LOG.lock().next
+}
+
+/// Events published after cursor, or None if there isn't any yet.
+pub fn since(cursor: u64) -> Option<Update> {
+    let log = LOG.lock();
+    if log.next == cursor {
+        return None;
+    }
+
+    let first = log.next - log.events.len() as u64;
+    if cursor < first || cursor > log.next {
+        return Some(Update { cursor: log.next, events: vec![], lost: true });
+    }
+
+    Some(Update {
+        cursor: log.next,
+        events: log.events.iter().skip((cursor - first) as usize).cloned().collect(),
+        lost: false,
+    })
+}
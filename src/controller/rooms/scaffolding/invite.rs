@@ -0,0 +1,78 @@
+use crate::controller::Room;
+use crate::INVITE_KEY_FILE;
+use hmac::{Hmac, Mac};
+use rand_core::{OsRng, TryRngCore};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// Signatures are truncated to keep invites short enough to be pasted into chats.
+const SIGNATURE_LENGTH: usize = 16;
+
+lazy_static::lazy_static! {
+    static ref INVITE_KEY: [u8; 32] = get_invite_key();
+}
+
+#[derive(Debug, PartialEq)]
+pub enum InviteError {
+    Malformed,
+    Expired,
+    InvalidSignature,
+}
+
+fn get_invite_key() -> [u8; 32] {
+    let mut key = [0u8; 32];
+    if let Ok(bytes) = fs::read(INVITE_KEY_FILE.as_path())
+        && bytes.len() == key.len()
+    {
+        key.copy_from_slice(&bytes);
+        return key;
+    }
+
+    OsRng.try_fill_bytes(&mut key).unwrap();
+    if let Err(e) = fs::write(INVITE_KEY_FILE.as_path(), key) {
+        logging!("Invite", "Cannot save invite key, invites will be invalid after restarting: {:?}", e);
+    }
+    key
+}
+
+// Room seeds are derivable from room codes, which guests already know.
+// Mixing a secret only known by host into the key prevents guests from minting invites by themselves.
+fn create_mac(room: &Room, expiry: u64) -> Hmac<Sha256> {
+    let key = Sha256::new()
+        .chain_update(*INVITE_KEY)
+        .chain_update(room.kind.seed().to_be_bytes())
+        .finalize();
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(&key).unwrap();
+    mac.update(room.code.as_bytes());
+    mac.update(b":");
+    mac.update(expiry.to_string().as_bytes());
+    mac
+}
+
+/// Mint an invite for the room, which is valid until expiry. The format is '<expiry in UNIX seconds>-<signature>'.
+pub fn mint(room: &Room, expiry: SystemTime) -> String {
+    let expiry = expiry.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_secs();
+    let signature = create_mac(room, expiry).finalize().into_bytes();
+
+    format!("{}-{}", expiry, hex::encode_upper(&signature[..SIGNATURE_LENGTH]))
+}
+
+pub fn verify(room: &Room, invite: &str) -> Result<(), InviteError> {
+    let (expiry, signature) = invite.trim().split_once('-').ok_or(InviteError::Malformed)?;
+    let expiry = expiry.parse::<u64>().map_err(|_| InviteError::Malformed)?;
+    let signature = hex::decode(signature).map_err(|_| InviteError::Malformed)?;
+    if signature.len() != SIGNATURE_LENGTH {
+        return Err(InviteError::Malformed);
+    }
+
+    create_mac(room, expiry)
+        .verify_truncated_left(&signature)
+        .map_err(|_| InviteError::InvalidSignature)?;
+
+    if SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).as_secs() >= expiry {
+        return Err(InviteError::Expired);
+    }
+    Ok(())
+}
@@ -1,5 +1,13 @@
 mod room;
 pub mod protocols;
+pub mod invite;
+pub mod events;
+pub mod chat;
+pub mod approval;
+pub mod reconcile;
+pub mod motd;
+#[cfg(not(target_os = "android"))]
+pub mod conformance;
 
 use crate::MACHINE_ID_FILE;
 use rand_core::{OsRng, TryRngCore};
@@ -0,0 +1,57 @@
+use crate::controller::Room;
+use crate::mc::status::ServerStatus;
+use crate::scaffolding::profile::{Profile, ProfileKind};
+
+/// Template of the MOTD advertised to Minecraft on guests, used unless another one is given by UI.
+pub const DEFAULT_TEMPLATE: &str = "§6§l{host} 的世界§r§7（{code}，{players} 人在房间中，请保持陶瓦运行）";
+
+/// Render the MOTD advertised to Minecraft on guests, which tells whose world it is. Placeholders in template are:
+/// - `{host}`: name of host.
+/// - `{code}`: the first segment of room code, which is a part of network name. The last segments are never shown, as
+///   they are the network secret of U/ rooms.
+/// - `{players}`: number of players in the room, including host.
+/// - `{motd}`: description of the server of host, or empty if it hasn't been pinged.
+///
+/// Placeholders are substituted in a single pass, so that values containing placeholders aren't expanded again.
+/// Unknown placeholders are kept as is. The fixed MOTD is used until host is known from the player list.
+pub fn render(template: &str, room: &Room, profiles: &[Profile], status: Option<&ServerStatus>) -> String {
+    let Some(host) = profiles.iter().find(|profile| *profile.get_kind() == ProfileKind::HOST) else {
+        return crate::MOTD.to_string();
+    };
+    let value = |placeholder: &str| match placeholder {
+        "host" => Some(sanitize(host.get_name())),
+        "code" => Some(sanitize(room.code.split(['-', '/']).nth(1).unwrap_or_default())),
+        "players" => Some(profiles.len().to_string()),
+        "motd" => Some(status.map(|status| sanitize(&status.description)).unwrap_or_default()),
+        _ => None,
+    };
+
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match rest.find('}').and_then(|end| Some((end, value(&rest[1..end])?))) {
+            Some((end, value)) => {
+                rendered.push_str(&value);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                rendered.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+// Values given by others must not break the advertisement nor the formatting codes of template.
+fn sanitize(value: &str) -> String {
+    value.chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .filter(|c| !matches!(c, '§' | '[' | ']'))
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
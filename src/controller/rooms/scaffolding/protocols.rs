@@ -1,12 +1,17 @@
+use crate::controller::scaffolding::approval::Admission;
+use crate::controller::scaffolding::chat::ChatLog;
+use crate::controller::scaffolding::events::{self, RoomEvent};
+use crate::controller::scaffolding::invite;
 use crate::controller::states::AppState;
+use crate::controller::is_banned;
 use crate::scaffolding::profile::{ProfileKind, ProfileSnapshot};
-use crate::scaffolding::server::Handlers;
+use crate::scaffolding::server::{HandlerRegistry, Handlers, Polls};
 use crate::scaffolding::PacketResponse;
 use serde::ser::SerializeSeq;
 use serde::Serializer as _;
 use serde_json::{json, Serializer, Value};
 use std::io;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 fn parse<F, R>(f: F) -> io::Result<R>
 where
@@ -30,33 +35,163 @@ macro_rules! define_handle {
     };
 }
 
-pub static HANDLERS: Handlers = &[
+/// Returned by c:player_ping, c:server_port, c:player_profiles_list, c:chat_fetch and c:subscribe when the room is
+/// invite-only and the guest hasn't presented an invite.
+pub const STATUS_INVITE_REQUIRED: u8 = 64;
+/// Returned by c:player_invite when the invite is malformed, expired or not signed by host.
+pub const STATUS_INVITE_REJECTED: u8 = 65;
+/// Returned by c:player_ping, c:server_port, c:player_profiles_list, c:chat_fetch and c:subscribe when host has
+/// removed the guest, which must leave the room.
+pub const STATUS_KICKED: u8 = 70;
+/// Returned by c:player_ping, c:server_port, c:player_profiles_list, c:chat_fetch and c:subscribe when the guest has
+/// been banned by host.
+pub const STATUS_BANNED: u8 = 71;
+/// Returned by c:player_ping, c:server_port, c:player_profiles_list, c:chat_fetch and c:subscribe when the room
+/// approves joins and host hasn't approved the guest yet.
+pub const STATUS_APPROVAL_PENDING: u8 = 72;
+/// Returned by c:player_ping when host has denied the guest from joining.
+pub const STATUS_JOIN_DENIED: u8 = 73;
+/// Returned by c:player_ping when the room has reached the capacity configured by host.
+pub const STATUS_ROOM_FULL: u8 = 74;
+
+// Guests are removed after being silent for 10 seconds, so subscriptions must be answered before that.
+const SUBSCRIBE_TIMEOUT: Duration = Duration::from_secs(8);
+// Kicked guests are told to leave within this duration, after which they are allowed to join again.
+const KICK_NOTICE: Duration = Duration::from_secs(30);
+
+/// Refresh the last seen time of a guest, returning whether the guest is in the room.
+/// None is returned if the room has been closed.
+fn touch(machine_id: &str) -> Option<bool> {
+    let mut container = AppState::acquire();
+    let AppState::HostOk { profiles, .. } = container.as_mut_ref() else {
+        return None;
+    };
+    match profiles.iter_mut().skip(1).find(|(_, profile)| profile.get_machine_id() == machine_id) {
+        Some((time, _)) => {
+            *time = SystemTime::now();
+            Some(true)
+        }
+        None => Some(false),
+    }
+}
+
+/// Machine ID of the guest sending the request, which is carried as {"machine_id"} by newer Terracotta.
+fn requester(request: &[u8]) -> Option<String> {
+    serde_json::from_slice::<Value>(request).ok()
+        .and_then(|value| Some(value.as_object()?.get("machine_id")?.as_str()?.to_string()))
+}
+
+/// Refuse guests which aren't allowed to learn about the room, returning the status and message to respond with.
+/// Having a profile in the room isn't a proof of membership, as machine IDs are told by guests themselves. Guests who
+/// have joined before the room became invite-only are invited by then.
+/// Bans and kicks are keyed on the told machine ID as well, so they can be bypassed by telling another one.
+fn refusal(state: &AppState, machine_id: Option<&str>) -> Option<(u8, &'static str)> {
+    let AppState::HostOk { invite_only, invited, kicked, approval, .. } = state else {
+        return None;
+    };
+    if machine_id.is_some_and(is_banned) {
+        return Some((STATUS_BANNED, "You have been banned from this room."));
+    }
+    // Kicked guests are told so by c:player_ping, which is left to clear the record.
+    let now = SystemTime::now();
+    if machine_id.is_some_and(|machine_id| kicked.iter().any(|(time, kicked)| {
+        kicked == machine_id && now.duration_since(*time).is_ok_and(|d| d < KICK_NOTICE)
+    })) {
+        return Some((STATUS_KICKED, "You have been removed from this room."));
+    }
+    if *invite_only && !machine_id.is_some_and(|machine_id| invited.iter().any(|invited| invited == machine_id)) {
+        return Some((STATUS_INVITE_REQUIRED, "This room is invite-only."));
+    }
+    if !approval.is_approved(machine_id.unwrap_or_default()) {
+        return Some((STATUS_APPROVAL_PENDING, "Waiting for host to approve the join."));
+    }
+    None
+}
+
+/// Secret used to authenticate scaffolding connections, which is only available while hosting a room.
+pub fn room_secret() -> Option<String> {
+    match AppState::acquire().as_ref() {
+        AppState::HostOk { room, .. } => Some(room.network_secret.clone()),
+        _ => None,
+    }
+}
+
+/// Handlers served by scaffolding server. Embedders may register handlers for their own namespaces here.
+pub static HANDLERS: HandlerRegistry = HandlerRegistry::with_polls(BUILTIN_HANDLERS, POLL_HANDLERS);
+
+static POLL_HANDLERS: Polls = &[
+    ("c", "subscribe", subscribe, SUBSCRIBE_TIMEOUT),
+];
+
+// Long polling for room events. Request carries the cursor returned by previous response, or null for the first one.
+// Responses are sent once events are available or SUBSCRIBE_TIMEOUT elapses, which also keep the guest alive. Until
+// then, the request is parked on scaffolding server, which invokes this again once events are published.
+fn subscribe(request: &[u8], mut response: Vec<u8>, expired: bool) -> io::Result<Option<PacketResponse>> {
+    let value: Value = serde_json::from_str(&String::from_utf8_lossy(request))?;
+
+    let machine_id = parse(|| value.as_object()?.get("machine_id")?.as_str())?;
+    let cursor = value.as_object().and_then(|value| value.get("cursor")?.as_u64());
+
+    if let Some((status, message)) = refusal(AppState::acquire().as_ref(), Some(machine_id)) {
+        response.extend_from_slice(message.as_bytes());
+        return PacketResponse::fail(status, response).map(Some);
+    }
+    let joined = touch(machine_id);
+    let mut update = match (joined, cursor) {
+        (Some(true), Some(cursor)) => match events::since(cursor) {
+            Some(update) => update,
+            None if expired => events::Update { cursor, events: vec![], lost: false },
+            None => return Ok(None),
+        },
+        _ => events::Update { cursor: events::latest(), events: vec![], lost: true },
+    };
+    match joined {
+        None if !update.events.iter().any(|event| matches!(event, RoomEvent::Closing)) => update.events.push(RoomEvent::Closing),
+        Some(false) => update.lost = true,
+        _ => {}
+    }
+
+    serde_json::to_writer(&mut response, &json!({
+        "cursor": update.cursor,
+        "events": update.events,
+        "resync": update.lost,
+    }))?;
+    PacketResponse::ok(response).map(Some)
+}
+
+static BUILTIN_HANDLERS: Handlers = &[
     define_handle! { c:ping[request => response]
         response.extend_from_slice(request);
     },
     define_handle! { c:protocols[request => response]
-        for (i, handler) in HANDLERS.iter().enumerate() {
-            response.extend_from_slice(handler.0.as_bytes());
-            response.push(b':');
-            response.extend_from_slice(handler.1.as_bytes());
-
-            if i != HANDLERS.len() - 1 {
-                response.push(b'\0');
-            }
-        }
+        response.extend_from_slice(HANDLERS.protocols().join("\0").as_bytes());
     },
+    // Handshake is performed by scaffolding server itself. It's listed here to be advertised through c:protocols.
+    define_handle! { c:auth[request => response] },
+    // Connections are switched to the pipelined format by scaffolding server once this protocol succeeds.
+    define_handle! { c:pipeline[request => response] },
+    // Request may carry {"machine_id"}, which is required once the room is invite-only or approves joins.
     define_handle! { c:server_port[request => response]
-        if let Some(port) = {
-            let state = AppState::acquire();
-            match state.as_ref() {
-                AppState::HostOk { port, .. } => Some(*port),
-                _ => None,
-            }
-        } {
-            response.extend_from_slice(&port.to_be_bytes());
-        } else {
+        let machine_id = requester(request);
+
+        let state = AppState::acquire();
+        // Guests are told the new port through RoomEvent::Server once the world is reopened.
+        let AppState::HostOk { port, reopening: false, .. } = state.as_ref() else {
             return PacketResponse::fail(32, response);
+        };
+        if let Some((status, message)) = refusal(state.as_ref(), machine_id.as_deref()) {
+            response.extend_from_slice(message.as_bytes());
+            return PacketResponse::fail(status, response);
         }
+        response.extend_from_slice(&port.to_be_bytes());
+    },
+    // Guests not asking this are assumed to play Java Edition, which is the only one supported by older Terracotta.
+    define_handle! { c:server_edition[request => response]
+        let state = AppState::acquire();
+        let AppState::HostOk { edition, .. } = state.as_ref() else {
+            return PacketResponse::fail(32, response);
+        };
+        serde_json::to_writer(&mut response, &json!({ "edition": edition }))?;
     },
     define_handle! { c:player_ping[request => response]
         let value: Value = serde_json::from_str(&String::from_utf8_lossy(request))?;
@@ -65,38 +200,156 @@ pub static HANDLERS: Handlers = &[
         let machine_id = parse(|| value.as_object()?.get("machine_id")?.as_str())?;
         let vendor = parse(|| value.as_object()?.get("vendor")?.as_str())?;
 
+        if is_banned(machine_id) {
+            response.extend_from_slice("You have been banned from this room.".as_bytes());
+            return PacketResponse::fail(STATUS_BANNED, response);
+        }
+
         let mut container = AppState::acquire();
-        let AppState::HostOk { profiles, .. } = container.as_mut_ref() else {
+        let AppState::HostOk { profiles, invite_only, invited, kicked, approval, capacity, .. } = container.as_mut_ref() else {
             return Err(io::Error::other("IllegalStateException: Expecting HostOk."));
         };
+        let now = SystemTime::now();
+        kicked.retain(|(time, _)| now.duration_since(*time).is_ok_and(|d| d < KICK_NOTICE));
+        if let Some(index) = kicked.iter().position(|(_, kicked)| kicked == machine_id) {
+            kicked.remove(index);
+            response.extend_from_slice("You have been removed from this room.".as_bytes());
+            return PacketResponse::fail(STATUS_KICKED, response);
+        }
+        // Members are invited once the room becomes invite-only, so that others cannot rename them.
+        if *invite_only && !invited.iter().any(|invited| invited == machine_id) {
+            response.extend_from_slice("This room is invite-only.".as_bytes());
+            return PacketResponse::fail(STATUS_INVITE_REQUIRED, response);
+        }
         match profiles.iter().position(|profile| profile.1.get_machine_id() == machine_id) {
             Some(i) if i >= 1 => {
                 profiles[i].0 = SystemTime::now();
 
                 if profiles[i].1.get_name() != name {
                     profiles[i].1.set_name(name.to_string());
+                    events::publish(RoomEvent::Rename { machine_id: machine_id.to_string(), name: name.to_string() });
                     container.increase_shared();
                 }
             }
             Some(_) => return Err(io::Error::other("IllegalStateException: Cannot modify host, machine_id may conflict.")),
+            None if capacity.is_some_and(|capacity| profiles.len() >= capacity) => {
+                response.extend_from_slice("This room is full.".as_bytes());
+                return PacketResponse::fail(STATUS_ROOM_FULL, response);
+            }
             None => {
-                profiles.push((SystemTime::now(), ProfileSnapshot {
+                let profile = || ProfileSnapshot {
                     machine_id: machine_id.to_string(),
                     name: name.to_string(),
                     vendor: vendor.to_string(),
                     kind: ProfileKind::GUEST
-                }.into_profile()));
+                }.into_profile();
+                match approval.request(machine_id, profile) {
+                    Admission::Approved => {}
+                    Admission::Pending(new) => {
+                        if new {
+                            container.increase_shared();
+                        }
+                        response.extend_from_slice("Waiting for host to approve the join.".as_bytes());
+                        return PacketResponse::fail(STATUS_APPROVAL_PENDING, response);
+                    }
+                    Admission::Denied => {
+                        response.extend_from_slice("Host has denied the join.".as_bytes());
+                        return PacketResponse::fail(STATUS_JOIN_DENIED, response);
+                    }
+                }
+
+                profiles.push((SystemTime::now(), profile()));
+                events::publish(RoomEvent::Join {
+                    machine_id: machine_id.to_string(),
+                    name: name.to_string(),
+                    vendor: vendor.to_string(),
+                });
                 container.increase_shared();
             }
         }
     },
-    define_handle! { c:player_profiles_list[request => response]
-        let mut value = Serializer::new(&mut response);
+    define_handle! { c:player_invite[request => response]
+        let value: Value = serde_json::from_str(&String::from_utf8_lossy(request))?;
+
+        let machine_id = parse(|| value.as_object()?.get("machine_id")?.as_str())?;
+        let token = parse(|| value.as_object()?.get("invite")?.as_str())?;
+
+        let mut container = AppState::acquire();
+        let AppState::HostOk { room, invited, .. } = container.as_mut_ref() else {
+            return Err(io::Error::other("IllegalStateException: Expecting HostOk."));
+        };
+        if let Err(e) = invite::verify(room, token) {
+            logging!("Invite", "Rejecting invite from {}: {:?}", machine_id, e);
+            response.extend_from_slice(format!("{:?}", e).as_bytes());
+            return PacketResponse::fail(STATUS_INVITE_REJECTED, response);
+        }
+        if !invited.iter().any(|invited| invited == machine_id) {
+            invited.push(machine_id.to_string());
+        }
+    },
+    // Messages are attributed to the profile of sender, which must have joined the room through c:player_ping.
+    define_handle! { c:chat_send[request => response]
+        let value: Value = serde_json::from_str(&String::from_utf8_lossy(request))?;
+
+        let machine_id = parse(|| value.as_object()?.get("machine_id")?.as_str())?;
+        let text = parse(|| ChatLog::normalize(value.as_object()?.get("text")?.as_str()?))?;
+
+        let mut container = AppState::acquire();
+        let AppState::HostOk { profiles, chat, .. } = container.as_mut_ref() else {
+            return Err(io::Error::other("IllegalStateException: Expecting HostOk."));
+        };
+        let Some((time, profile)) = profiles.iter_mut().skip(1).find(|(_, profile)| profile.get_machine_id() == machine_id) else {
+            return Err(io::Error::other("IllegalStateException: Sender hasn't joined the room."));
+        };
+        *time = SystemTime::now();
+
+        let message = chat.post(machine_id, profile.get_name(), text);
+        serde_json::to_writer(&mut response, &json!({ "id": message.id }))?;
+        events::publish(RoomEvent::Chat);
+    },
+    // Request carries the cursor returned by previous response, or null to fetch the whole history kept by host.
+    // It also carries {"machine_id"}, as messages tell machine IDs, which are withheld like c:player_profiles_list.
+    define_handle! { c:chat_fetch[request => response]
+        let value: Value = serde_json::from_str(&String::from_utf8_lossy(request))?;
+        let cursor = value.as_object().and_then(|value| value.get("cursor")?.as_u64()).unwrap_or(0);
 
+        let container = AppState::acquire();
+        let AppState::HostOk { chat, .. } = container.as_ref() else {
+            return Err(io::Error::other("IllegalStateException: Expecting HostOk."));
+        };
+        if let Some((status, message)) = refusal(container.as_ref(), requester(request).as_deref()) {
+            response.extend_from_slice(message.as_bytes());
+            return PacketResponse::fail(status, response);
+        }
+        let (cursor, messages) = chat.fetch(cursor);
+        serde_json::to_writer(&mut response, &json!({
+            "cursor": cursor,
+            "messages": messages,
+        }))?;
+    },
+    // Responds null if host hasn't chosen a mods directory.
+    define_handle! { c:mods_manifest[request => response]
+        let container = AppState::acquire();
+        let AppState::HostOk { mods, .. } = container.as_ref() else {
+            return Err(io::Error::other("IllegalStateException: Expecting HostOk."));
+        };
+        serde_json::to_writer(&mut response, mods)?;
+    },
+    // Invites and join approval trust the machine ID told by guests, as it isn't bound to the authenticated connection.
+    // Hence a guest knowing the machine ID of a member can impersonate it, and machine IDs of players are withheld from
+    // guests who haven't been invited or approved. Request carries {"machine_id"}, which is required once the room is
+    // invite-only or approves joins.
+    define_handle! { c:player_profiles_list[request => response]
         let container = AppState::acquire();
         let AppState::HostOk { profiles, .. } = container.as_ref() else {
             return Err(io::Error::other("IllegalStateException: Expecting HostOk."));
         };
+        if let Some((status, message)) = refusal(container.as_ref(), requester(request).as_deref()) {
+            response.extend_from_slice(message.as_bytes());
+            return PacketResponse::fail(status, response);
+        }
+
+        let mut value = Serializer::new(&mut response);
 
         let mut sequence = value.serialize_seq(Some(profiles.len()))?;
         for (_, profile) in profiles {
@@ -0,0 +1,169 @@
+use crate::controller::scaffolding::events::RoomEvent;
+use crate::scaffolding::profile::{Profile, ProfileKind, ProfileSnapshot};
+use serde_json::Value;
+
+/// Parse the response of c:player_profiles_list into profiles sorted by machine ID, where local player is marked as LOCAL
+/// and added if host hasn't listed it yet. None is returned if the list is malformed, lacks host or has conflicted machine IDs.
+pub fn parse_profiles(data: &[u8], local_profile: &Profile) -> Option<Vec<Profile>> {
+    let mut host = false;
+    let mut local = false;
+
+    let mut server_players: Vec<Profile> = vec![];
+    for item in serde_json::from_slice::<Value>(data).ok()?.as_array()? {
+        let name = item.as_object()?.get("name")?.as_str()?;
+        let machine_id = item.as_object()?.get("machine_id")?.as_str()?;
+        let vendor = item.as_object()?.get("vendor")?.as_str()?;
+
+        let kind = if machine_id == local_profile.get_machine_id() {
+            if local {
+                logging!("RoomExperiment", "API c:player_profiles_list invocation failed: Multiple local player, machine_id may have conflicted.");
+                return None;
+            }
+            local = true;
+
+            ProfileKind::LOCAL
+        } else {
+            match item.as_object()?.get("kind")?.as_str()? {
+                "HOST" if !host => {
+                    host = true;
+                    ProfileKind::HOST
+                }
+                "GUEST" => ProfileKind::GUEST,
+                _ => return None,
+            }
+        };
+
+        server_players.push(ProfileSnapshot {
+            machine_id: machine_id.to_string(),
+            name: name.to_string(),
+            vendor: vendor.to_string(),
+            kind,
+        }.into_profile())
+    }
+    if !host {
+        logging!("RoomExperiment", "API c:player_profiles_list invocation failed: No host detected.");
+        return None;
+    }
+    if !local {
+        server_players.push(local_profile.clone());
+    }
+
+    server_players.sort_by_cached_key(|profile| profile.get_machine_id().to_string());
+    for profile in server_players.windows(2) {
+        if profile[0].get_machine_id() == profile[1].get_machine_id() {
+            logging!("RoomExperiment", "API c:player_profiles_list invocation failed: machine_id conflict.");
+            return None;
+        }
+    }
+    Some(server_players)
+}
+
+/// Update profiles known by guest to match the ones from [parse_profiles]. Returns whether profiles are changed,
+/// or None if host or a guest has changed its kind, in which case machine IDs may have conflicted.
+pub fn reconcile(profiles: &mut Vec<Profile>, server_profiles: Vec<Profile>) -> Option<bool> {
+    let mut used = vec![false; server_profiles.len()];
+    let mut changed = false;
+    for i in (0..profiles.len()).rev() {
+        let profile = &mut profiles[i];
+        match profile.get_kind() {
+            ProfileKind::HOST => match server_profiles.binary_search_by_key(&profile.get_machine_id(), |p| p.get_machine_id()) {
+                Ok(index) if !used[index] && *server_profiles[index].get_kind() == ProfileKind::HOST => {
+                    used[index] = true;
+                    if profile.get_name() != server_profiles[index].get_name() {
+                        profile.set_name(server_profiles[index].get_name().to_string());
+                        changed = true;
+                    }
+                }
+                _ => {
+                    logging!("RoomExperiment", "API c:player_profiles_list invocation failed: Host Profile is consumed or invalid, machine_id may have conflict.");
+                    return None;
+                }
+            },
+            ProfileKind::LOCAL => {}
+            ProfileKind::GUEST => match server_profiles.binary_search_by_key(&profile.get_machine_id(), |p| p.get_machine_id()) {
+                Ok(index) if used[index] && *server_profiles[index].get_kind() == ProfileKind::GUEST => {
+                    profiles.remove(i);
+                    changed = true;
+                }
+                Ok(index) if *server_profiles[index].get_kind() == ProfileKind::GUEST => {
+                    used[index] = true;
+                    if profile.get_name() != server_profiles[index].get_name() {
+                        profile.set_name(server_profiles[index].get_name().to_string());
+                        changed = true;
+                    }
+                }
+                Ok(_) => {
+                    logging!("RoomExperiment", "API c:player_profiles_list invocation failed: Guest Profile type is changed, machine_id may have conflict.");
+                    return None;
+                }
+                Err(_) => {
+                    profiles.remove(i);
+                    changed = true;
+                }
+            },
+        }
+    }
+
+    let mut server_profiles = server_profiles;
+    for i in (0..server_profiles.len()).rev() {
+        let profile = server_profiles.pop().unwrap();
+        if !used[i] && *profile.get_kind() != ProfileKind::LOCAL {
+            profiles.push(profile);
+            changed = true;
+        }
+    }
+    Some(changed)
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Applied {
+    pub changed: bool,
+    /// Whether the whole player list must be fetched again, as local player has been removed by host.
+    pub resync: bool,
+    /// Whether host is closing the room. Events following it aren't applied.
+    pub closing: bool,
+    /// Whether new chat messages should be fetched.
+    pub chat: bool,
+    /// Whether the port of Minecraft server should be queried again.
+    pub server: bool,
+}
+
+/// Apply room events from c:subscribe to profiles known by guest.
+pub fn apply_events(profiles: &mut Vec<Profile>, events: Vec<RoomEvent>, local_machine_id: &str) -> Applied {
+    let mut applied = Applied::default();
+    for event in events {
+        match event {
+            RoomEvent::Join { machine_id, name, vendor } => {
+                if machine_id != local_machine_id && !profiles.iter().any(|profile| profile.get_machine_id() == machine_id) {
+                    profiles.push(ProfileSnapshot { machine_id, name, vendor, kind: ProfileKind::GUEST }.into_profile());
+                    applied.changed = true;
+                }
+            }
+            // Host has removed local player, which must join again.
+            RoomEvent::Leave { machine_id } if machine_id == local_machine_id => applied.resync = true,
+            RoomEvent::Leave { machine_id } => {
+                if let Some(i) = profiles.iter().position(|profile| {
+                    profile.get_machine_id() == machine_id && *profile.get_kind() == ProfileKind::GUEST
+                }) {
+                    profiles.remove(i);
+                    applied.changed = true;
+                }
+            }
+            RoomEvent::Rename { machine_id, name } => {
+                if let Some(profile) = profiles.iter_mut().find(|profile| {
+                    profile.get_machine_id() == machine_id && *profile.get_kind() != ProfileKind::LOCAL
+                }) && profile.get_name() != name {
+                    profile.set_name(name);
+                    applied.changed = true;
+                }
+            }
+            RoomEvent::Chat => applied.chat = true,
+            RoomEvent::Server => applied.server = true,
+            RoomEvent::Closing => {
+                applied.closing = true;
+                break;
+            }
+        }
+    }
+    applied
+}
@@ -1,21 +1,36 @@
+use crate::controller::scaffolding::approval::ApprovalQueue;
+use crate::controller::scaffolding::chat::{ChatLog, ChatMessage};
+use crate::controller::scaffolding::events::{self, RoomEvent};
+use crate::controller::scaffolding::reconcile;
+use crate::controller::scaffolding::motd;
+use crate::controller::scaffolding::protocols::{
+    STATUS_APPROVAL_PENDING, STATUS_BANNED, STATUS_INVITE_REJECTED, STATUS_INVITE_REQUIRED, STATUS_JOIN_DENIED, STATUS_KICKED,
+    STATUS_ROOM_FULL,
+};
 use crate::controller::scaffolding::{MACHINE_ID, VENDOR};
 use crate::controller::states::{AppState, AppStateCapture};
-use crate::controller::{ConnectionDifficulty, ExceptionType, Room, RoomKind, SCAFFOLDING_PORT};
+use crate::controller::{ConnectionDifficulty, ExceptionType, Room, RoomCodeError, RoomKind, SCAFFOLDING_PORT};
 use crate::easytier;
 use crate::easytier::argument::{Argument, PortForward, Proto};
 use crate::easytier::publics::PublicServers;
-use crate::easytier::EasyTierMember;
-use crate::mc::fakeserver::FakeServer;
+use crate::easytier::{EasyTier, EasyTierMember};
+use crate::mc::fakeserver::{self, FakeServer};
+use crate::mc::mods::{self, ModDiff, ModEntry};
+use crate::mc::relay::Relay;
+use crate::mc::scanning::MinecraftScanner;
+use crate::mc::status::{self, ServerStatus};
+use crate::mc::{bedrock, Edition};
 use crate::ports::PortRequest;
 use crate::scaffolding::client::ClientSession;
 use crate::scaffolding::profile::{Profile, ProfileKind, ProfileSnapshot};
 use crate::scaffolding::PacketResponse;
 use rand_core::{OsRng, TryRngCore};
 use serde_json::{json, Value};
-use socket2::{Domain, SockAddr, Socket, Type};
 use std::borrow::Cow;
+use std::io;
 use std::mem::{transmute, MaybeUninit};
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::thread;
 use std::time::{Duration, SystemTime};
@@ -38,7 +53,7 @@ fn lookup_char(char: char) -> Option<u8> {
     None
 }
 
-pub fn create_room() -> Room {
+pub fn create_room(passphrase: Option<String>) -> Room {
     let value = {
         let mut bytes = [0u8; 16];
         OsRng.try_fill_bytes(&mut bytes).unwrap();
@@ -46,57 +61,105 @@ pub fn create_room() -> Room {
     } % 34u128.pow(16);
     let value = value - value % 7;
 
-    let (code, network_name, network_secret) = from_value(value);
-
-    Room {
-        code,
-        network_name,
-        network_secret,
-        kind: RoomKind::Scaffolding { seed: value },
+    match passphrase {
+        Some(passphrase) => from_protected_value(value, passphrase),
+        None => from_scaffolding_value(value),
     }
 }
 
-pub fn parse(code: &str) -> Option<Room> {
+pub fn parse(code: &str, passphrase: Option<&str>) -> Result<Room, RoomCodeError> {
     let code: Vec<char> = code.to_ascii_uppercase().chars().collect();
-    if code.len() < "U/XXXX-XXXX-XXXX-XXXX".len() {
-        return None;
-    }
 
-    let value: u128 = 'value: {
-        'parse_segment: for code in code.windows("U/XXXX-XXXX-XXXX-XXXX".len()) {
-            if code[0] != 'U' || code[1] != '/' {
-                continue 'parse_segment;
+    let (protected, value) = 'value: {
+        let mut error: Option<RoomCodeError> = None;
+        for begin in 0..code.len() {
+            if (code[begin] != 'U' && code[begin] != 'P') || code.get(begin + 1) != Some(&'/') {
+                continue;
             }
 
-            let code = &code[2..];
-            let mut value: u128 = 0;
-            for i in (0.."XXXX-XXXX-XXXX-XXXX".len()).rev() {
-                if i == 4 || i == 9 || i == 14 {
-                    if code[i] != '-' {
-                        continue 'parse_segment;
-                    }
-                } else {
-                    match lookup_char(code[i]) {
-                        Some(v) => value = value * 34 + v as u128,
-                        None => continue 'parse_segment,
-                    }
+            match parse_segment(&code[begin..], begin) {
+                Ok(value) => break 'value (code[begin] == 'P', value),
+                Err(e) => {
+                    error.get_or_insert(e);
                 }
             }
-            if value.is_multiple_of(7) {
-                break 'value value;
-            }
         }
-        return None;
+        return Err(error.unwrap_or(RoomCodeError::MissingPrefix));
     };
 
+    if !protected {
+        return Ok(from_scaffolding_value(value));
+    }
+    match passphrase {
+        Some(passphrase) if !passphrase.is_empty() => Ok(from_protected_value(value, passphrase.to_string())),
+        _ => Err(RoomCodeError::MissingPassphrase),
+    }
+}
+
+fn from_scaffolding_value(value: u128) -> Room {
     let (code, network_name, network_secret) = from_value(value);
 
-    Some(Room {
+    Room {
         code,
         network_name,
         network_secret,
         kind: RoomKind::Scaffolding { seed: value },
-    })
+    }
+}
+
+// Protected rooms share the encoding of scaffolding rooms, but the whole code only identifies the network.
+// The secret is the passphrase chosen by host, which cannot be derived from the code.
+fn from_protected_value(value: u128, passphrase: String) -> Room {
+    let (code, _, _) = from_value(value);
+    let code = code.replacen("U/", "P/", 1);
+    let network_name = format!("scaffolding-mc-p-{}", &code["P/".len()..]);
+
+    Room {
+        code,
+        network_name,
+        network_secret: passphrase,
+        kind: RoomKind::Protected { seed: value },
+    }
+}
+
+// Segments of U/ and P/ codes share the same encoding, so 'code' starts with either prefix, which isn't checked here.
+// Reported positions are 1-based indexes into the whole input, where 'offset' is the index of the prefix.
+fn parse_segment(code: &[char], offset: usize) -> Result<u128, RoomCodeError> {
+    if code.len() < "U/XXXX-XXXX-XXXX-XXXX".len() {
+        return Err(RoomCodeError::InvalidLength {
+            expected: "U/XXXX-XXXX-XXXX-XXXX".len(),
+            found: code.len(),
+        });
+    }
+
+    let code = &code[2.."U/XXXX-XXXX-XXXX-XXXX".len()];
+    let mut digits = [0u8; 16];
+    let mut j = 0;
+    for (i, char) in code.iter().enumerate() {
+        let position = offset + "U/".len() + i + 1;
+        if i == 4 || i == 9 || i == 14 {
+            if *char != '-' {
+                return Err(RoomCodeError::MisplacedDash { position });
+            }
+        } else if *char == '-' {
+            return Err(RoomCodeError::MisplacedDash { position });
+        } else {
+            match lookup_char(*char) {
+                Some(v) => digits[j] = v,
+                None => return Err(RoomCodeError::IllegalCharacter { position, character: *char }),
+            }
+            j += 1;
+        }
+    }
+
+    let mut value: u128 = 0;
+    for digit in digits.iter().rev() {
+        value = value * 34 + *digit as u128;
+    }
+    if !value.is_multiple_of(7) {
+        return Err(RoomCodeError::InvalidChecksum);
+    }
+    Ok(value)
 }
 
 fn from_value(value: u128) -> (String, String, String) {
@@ -137,14 +200,56 @@ fn from_value(value: u128) -> (String, String, String) {
     (code, network_name, network_secret)
 }
 
-pub fn start_host(room: Room, port: u16, player: Option<String>, capture: AppStateCapture, public_servers: PublicServers) {
+/// The Minecraft server shared by host.
+#[derive(Debug, Clone, Copy)]
+pub enum HostedServer {
+    /// A world opened to LAN by a client on this PC, at the given port.
+    Lan(u16),
+    /// A Bedrock world opened to LAN on this PC, which is reached through RakNet at the given UDP port.
+    Bedrock(u16),
+    /// A server at the address given by host, such as a dedicated server. Servers on other machines are relayed,
+    /// while those on this PC are shared directly like LAN worlds.
+    Address(SocketAddr),
+}
+
+pub fn start_host(
+    room: Room,
+    server: HostedServer,
+    player: Option<String>,
+    capture: AppStateCapture,
+    public_servers: PublicServers,
+    mods: Option<Vec<ModEntry>>,
+    capacity: Option<usize>,
+) {
     let scaffolding = *SCAFFOLDING_PORT;
 
+    let edition = match server {
+        HostedServer::Bedrock(_) => Edition::Bedrock,
+        _ => Edition::Java,
+    };
+    let (port, address, relay) = match server {
+        HostedServer::Lan(port) | HostedServer::Bedrock(port) => (port, None, None),
+        HostedServer::Address(address) if address.ip().is_loopback() => (address.port(), Some(address), None),
+        HostedServer::Address(address) => match Relay::create(address) {
+            Ok(relay) => (relay.port, Some(address), Some(relay)),
+            Err(e) => {
+                logging!("RoomExperiment", "Cannot relay MC server at {}: {:?}", address, e);
+                let Some(state) = capture.try_capture() else {
+                    return;
+                };
+                state.set(AppState::Exception { kind: ExceptionType::PingServerRst });
+                return;
+            }
+        },
+    };
+
     let mut args = compute_arguments(&room, public_servers);
     args.push(Argument::HostName(Cow::Owned(format!("scaffolding-mc-server-{}", scaffolding))));
     args.push(Argument::IPv4(Ipv4Addr::new(10, 144, 144, 1)));
     args.push(Argument::TcpWhitelist(scaffolding));
-    args.push(Argument::TcpWhitelist(port));
+    if edition == Edition::Java {
+        args.push(Argument::TcpWhitelist(port));
+    }
     args.push(Argument::UdpWhitelist(port));
 
     let easytier = easytier::create(args);
@@ -154,7 +259,13 @@ pub fn start_host(room: Room, port: u16, player: Option<String>, capture: AppSta
         };
         state.set(AppState::HostOk {
             room,
+            edition,
             port,
+            server: address,
+            relay,
+            reopening: false,
+            status: None,
+            scanner: MinecraftScanner::create(|m| !fakeserver::is_fake(m)),
             easytier,
             profiles: vec![(
                 SystemTime::now(),
@@ -165,31 +276,88 @@ pub fn start_host(room: Room, port: u16, player: Option<String>, capture: AppSta
                     kind: ProfileKind::HOST
                 }.into_profile()
             )],
+            invite_only: false,
+            invited: vec![],
+            kicked: vec![],
+            approval: ApprovalQueue::default(),
+            capacity,
+            chat: ChatLog::default(),
+            mods,
         })
     };
 
     thread::spawn(move || {
         let mut counter = 0;
+        // Since when Minecraft server has stopped, while host is waiting for the world to be reopened.
+        let mut stopped_since: Option<SystemTime> = None;
+        // Other worlds opened to LAN when Minecraft server has stopped, which aren't taken as the reopened one.
+        let mut others: Vec<u16> = vec![];
         loop {
             thread::sleep(Duration::from_secs(5));
 
-            if check_mc_conn(port) {
-                counter = 0;
-            } else {
-                counter += 1;
-                if counter >= 3 {
+            // Host may switch to another world meanwhile, so that the port is read every time.
+            let (port, server, reopening, ports) = {
+                let Some(state) = capture.try_capture() else {
+                    return;
+                };
+                let AppState::HostOk { port, server, reopening, scanner, .. } = state.as_ref() else {
+                    unreachable!();
+                };
+                if !*reopening {
+                    stopped_since = None;
+                }
+                (*port, *server, *reopening, scanner.get_ports())
+            };
+            let address = server.unwrap_or(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port).into());
+            // Servers given by host and Bedrock worlds are waited at the same address, while LAN worlds of Java Edition
+            // may be reopened at another port. Like choosing the world to share initially, host must choose through
+            // select_world if it cannot be told which of the worlds is reopened.
+            let reopened = match reopening {
+                false => None,
+                true if server.is_some() || edition == Edition::Bedrock => check_mc_conn(edition, address).map(|_| port),
+                true if ports.contains(&port) => Some(port),
+                true => match ports.iter().filter(|port| !others.contains(port)).collect::<Vec<_>>()[..] {
+                    [reopened] => Some(*reopened),
+                    _ => None,
+                },
+            };
+
+            let mut stopped = false;
+            let mut pinged = None;
+            match stopped_since {
+                Some(since) if reopened.is_none() && since.elapsed().is_ok_and(|d| d >= REOPEN_TIMEOUT) => {
+                    logging!("RoomExperiment", "World hasn't been reopened in {}s, closing the room.", REOPEN_TIMEOUT.as_secs());
                     let Some(state) = capture.try_capture() else {
                         return;
                     };
                     state.set(AppState::Exception { kind: ExceptionType::PingServerRst });
                     return;
                 }
+                Some(_) => {}
+                None => match check_mc_conn(edition, address) {
+                    Some(status) => {
+                        counter = 0;
+                        pinged = Some(status);
+                    }
+                    None => {
+                        counter += 1;
+                        if counter >= 3 {
+                            logging!("RoomExperiment", "MC server at {} has stopped, waiting for the world to be reopened.", address);
+                            counter = 0;
+                            stopped_since = Some(SystemTime::now());
+                            stopped = true;
+                            others = ports.iter().copied().filter(|other| *other != port).collect();
+                        }
+                    }
+                },
             }
 
-            let Some(mut state) = capture.try_capture() else {
+            let Some(state) = capture.try_capture() else {
                 return;
             };
-            let AppState::HostOk { easytier, profiles, .. } = state.as_mut_ref() else {
+            // Whitelists may be updated through EasyTier CLI.
+            let mut state = if reopened.is_some() { state.into_slow() } else { state };
+            let AppState::HostOk { easytier, profiles, approval, port: shared_port, reopening, status, .. } = state.as_mut_ref() else {
                 unreachable!();
             };
 
@@ -198,12 +366,36 @@ pub fn start_host(room: Room, port: u16, player: Option<String>, capture: AppSta
                 return;
             }
 
-            let mut changed = false;
             let now = SystemTime::now();
+            let mut changed = approval.expire(now);
+            // Status of another world, which host has chosen meanwhile, is left to the next ping.
+            if let Some(pinged) = pinged && *shared_port == port && status.as_ref() != Some(&pinged) {
+                *status = Some(pinged);
+                changed = true;
+            }
+            if stopped && *shared_port == port {
+                *reopening = true;
+                *status = None;
+                events::publish(RoomEvent::Server);
+                changed = true;
+            }
+            // Host may have chosen a world meanwhile, which is kept.
+            if let Some(reopened) = reopened && *reopening {
+                if reopened != port && !expose_server(easytier, reopened) {
+                    state.set(AppState::Exception { kind: ExceptionType::PingServerRst });
+                    return;
+                }
+                logging!("RoomExperiment", "World has been reopened, MC server is at {}", reopened);
+                *shared_port = reopened;
+                *reopening = false;
+                events::publish(RoomEvent::Server);
+                changed = true;
+            }
             for i in (1..profiles.len()).rev() {
                 let (time, profile) = &profiles[i];
                 if i != 0 && now.duration_since(*time).is_ok_and(|d| d >= Duration::from_secs(10)) {
                     logging!("RoomExperiment", "Removing guest {}: timeout.", profile.get_name());
+                    events::publish(RoomEvent::Leave { machine_id: profile.get_machine_id().to_string() });
                     profiles.remove(i);
                     changed = true;
                 }
@@ -215,7 +407,38 @@ pub fn start_host(room: Room, port: u16, player: Option<String>, capture: AppSta
     });
 }
 
-pub fn start_guest(room: Room, player: Option<String>, capture: AppStateCapture, public_servers: PublicServers) {
+/// Let guests reach MC server at another port. Callers should publish RoomEvent::Server once the port is updated.
+/// Guests can only reach ports in the whitelists, which are given when EasyTier is created.
+pub fn expose_server(easytier: &mut EasyTier, port: u16) -> bool {
+    if !easytier.set_whitelist(&[*SCAFFOLDING_PORT, port], &[port]) {
+        logging!("RoomExperiment", "Cannot expose MC server at {} to guests.", port);
+        return false;
+    }
+    true
+}
+
+// Rooms are closed if the world isn't reopened within this duration after Minecraft server has stopped.
+const REOPEN_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+// Interval between status pings sent by guests through the forwarded port.
+const STATUS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Protocols used by guests, besides c:ping and c:protocols which are required to verify host.
+static GUEST_PROTOCOLS: [&str; 10] = [
+    "c:server_port", "c:player_ping", "c:player_profiles_list", "c:player_invite", "c:subscribe", "c:auth", "c:chat_send", "c:chat_fetch",
+    "c:mods_manifest", "c:server_edition",
+];
+static BASELINE_PROTOCOLS: [&str; 3] = ["c:server_port", "c:player_ping", "c:player_profiles_list"];
+
+pub fn start_guest(
+    room: Room,
+    invite: Option<String>,
+    player: Option<String>,
+    capture: AppStateCapture,
+    public_servers: PublicServers,
+    mods: Option<PathBuf>,
+    motd: Option<String>,
+) {
+    let secret = room.network_secret.clone();
     let mut args = compute_arguments(&room, public_servers);
     args.push(Argument::DHCP);
     args.push(Argument::TcpWhitelist(0));
@@ -226,7 +449,7 @@ pub fn start_guest(room: Room, player: Option<String>, capture: AppStateCapture,
             return;
         };
 
-        state.set(AppState::GuestStarting { room, easytier, difficulty: ConnectionDifficulty::Unknown })
+        state.set(AppState::GuestStarting { room, easytier, difficulty: ConnectionDifficulty::Unknown, awaiting_approval: false })
     };
 
     let (scaffolding_port, host_ip) = 'local_port: {
@@ -344,23 +567,140 @@ pub fn start_guest(room: Room, player: Option<String>, capture: AppStateCapture,
         return;
     };
 
-    let Some(response) = session.send_sync(("c", "server_port"), |_| {}) else {
-        fail(capture);
-        return;
+    match session.negotiate(&secret) {
+        Ok(true) => {
+            logging!("RoomExperiment", "Scaffolding session is pipelined.");
+        }
+        Ok(false) => {}
+        Err(e) => {
+            logging!("RoomExperiment", "Cannot negotiate with scaffolding server: {:?}", e);
+            fail(capture);
+            return;
+        }
+    }
+    let unsupported = match session.protocols() {
+        Some(_) => GUEST_PROTOCOLS.iter().copied().filter(|protocol| !session.supports(protocol)).collect::<Vec<_>>(),
+        // Hosts not answering c:protocols are assumed to implement the protocols used since the first version.
+        None => GUEST_PROTOCOLS.iter().copied().filter(|protocol| !BASELINE_PROTOCOLS.contains(protocol)).collect::<Vec<_>>(),
+    };
+    if !unsupported.is_empty() {
+        logging!("RoomExperiment", "Host is running an older Terracotta or other vendor, unsupported protocols: {:?}", unsupported);
+    }
+    let supports = {
+        let unsupported = unsupported.clone();
+        move |protocol: &str| !unsupported.contains(&protocol)
     };
 
-    let port = if let PacketResponse::Ok { data } = response
-        && data.len() == 2
-    {
-        let mut p = [0u8; 2];
-        p.copy_from_slice(data.as_slice());
-        u16::from_be_bytes(p)
-    } else {
-        fail(capture);
-        return;
+    if let Some(invite) = invite.filter(|_| {
+        // Hosts without invites are never invite-only.
+        supports("c:player_invite") || {
+            logging!("RoomExperiment", "Host doesn't support invites, skipping.");
+            false
+        }
+    }) {
+        match session.invoke(("c", "player_invite"), |body| {
+            serde_json::to_writer(body, &json!({
+                "machine_id": *MACHINE_ID,
+                "invite": invite,
+            })).unwrap();
+        }) {
+            Some(PacketResponse::Ok { .. }) => {
+                logging!("RoomExperiment", "Invite has been accepted.");
+            }
+            Some(PacketResponse::Fail { status: STATUS_INVITE_REJECTED, data }) => {
+                logging!("RoomExperiment", "Invite has been rejected: {}", String::from_utf8_lossy(&data));
+                let Some(state) = capture.try_capture() else {
+                    return;
+                };
+                state.set(AppState::Exception { kind: ExceptionType::InviteRejected });
+                return;
+            }
+            Some(PacketResponse::Fail { status, data }) => {
+                logging!("RoomExperiment", "Cannot present invite, status={}: {}", status, String::from_utf8_lossy(&data));
+            }
+            None => {
+                fail(capture);
+                return;
+            }
+        }
+    }
+
+    let local_profile = ProfileSnapshot {
+        machine_id: MACHINE_ID.to_string(),
+        name: player.unwrap_or("Terracotta Anonymous Guest".to_string()),
+        vendor: VENDOR.to_string(),
+        kind: ProfileKind::LOCAL,
+    }.into_profile();
+
+    // Hosts without join approval ignore the machine ID.
+    let port = loop {
+        let Some(response) = session.send_sync(("c", "server_port"), |body| {
+            serde_json::to_writer(body, &json!({ "machine_id": local_profile.get_machine_id() })).unwrap();
+        }) else {
+            fail(capture);
+            return;
+        };
+
+        match response {
+            PacketResponse::Ok { data } if data.len() == 2 => {
+                let mut p = [0u8; 2];
+                p.copy_from_slice(data.as_slice());
+                break u16::from_be_bytes(p);
+            }
+            PacketResponse::Fail { status: STATUS_APPROVAL_PENDING, .. } => {
+                if !wait_for_approval(&mut session, &capture, &local_profile) {
+                    return;
+                }
+            }
+            PacketResponse::Fail { status, data } if refused(status).is_some() => {
+                logging!("RoomExperiment", "Refused by host, status={}: {}", status, String::from_utf8_lossy(&data));
+                if let Some(state) = capture.try_capture() {
+                    state.set(AppState::Exception { kind: refused(status).unwrap() });
+                }
+                return;
+            }
+            _ => {
+                fail(capture);
+                return;
+            }
+        }
     };
     logging!("RoomExperiment", "MC server is at {}", port);
 
+    let edition = if supports("c:server_edition") {
+        match session.invoke(("c", "server_edition"), |_| {}) {
+            Some(PacketResponse::Ok { data }) => {
+                match serde_json::from_slice::<Value>(&data).ok().as_ref().and_then(|value| value.get("edition")?.as_str()) {
+                    Some("bedrock") => Edition::Bedrock,
+                    Some("java") => Edition::Java,
+                    _ => {
+                        logging!("RoomExperiment", "API c:server_edition invocation failed: Invalid response.");
+                        fail(capture);
+                        return;
+                    }
+                }
+            }
+            Some(PacketResponse::Fail { status, data }) => {
+                logging!("RoomExperiment", "API c:server_edition invocation failed with status {}: {}", status, String::from_utf8_lossy(&data));
+                fail(capture);
+                return;
+            }
+            None => {
+                fail(capture);
+                return;
+            }
+        }
+    } else {
+        Edition::Java
+    };
+    logging!("RoomExperiment", "MC server is of {:?} Edition", edition);
+
+    // Mismatched mods are only reported by Minecraft after connecting, so they are compared beforehand.
+    let mods = match mods {
+        Some(directory) if supports("c:mods_manifest") => compare_mods(&mut session, &directory),
+        _ => None,
+    };
+
     let local_port = {
         let Some(state) = capture.try_capture() else {
             return;
@@ -370,57 +710,31 @@ pub fn start_guest(room: Room, player: Option<String>, capture: AppStateCapture,
             unreachable!();
         };
 
-        // To maximum compatibility, try to request the identical port.
-        // If failed, use a dynamic free port instead.
-        let local_port = PortRequest::request_specific(port).unwrap_or_else(|e| {
-            logging!("RoomExperiment", "Unable to request shadow port {} on client: {:?}. Mods requiring UDP socket like SimpleVoiceChat may go wrong.", port, e);
-            PortRequest::Minecraft.request()
-        });
-
-        if !easytier.add_port_forward(&{
-            let locals = [
-                SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, local_port).into(),
-                SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, local_port, 0, 0).into(),
-            ];
-            let protos = [Proto::TCP, Proto::UDP];
-
-            // TODO: Compute SIZE automatically.
-            const SIZE: usize = 4;
-            assert_eq!(locals.len() * protos.len(), SIZE);
-            let mut forwards: [MaybeUninit<PortForward>; SIZE] = [const { MaybeUninit::uninit() }; _];
-            for (i, local) in locals.into_iter().enumerate() {
-                for (j, proto) in protos.iter().enumerate() {
-                    forwards[i * 2 + j].write(PortForward {
-                        remote: SocketAddrV4::new(host_ip, port).into(),
-                        local,
-                        proto: proto.clone(),
-                    });
-                }
-            }
-            // SAFETY: These two types are of the same size and all elements have been properly initialized.
-            unsafe { transmute::<[MaybeUninit<PortForward>; SIZE], [PortForward; SIZE]>(forwards) }
-        }) {
-            logging!("RoomExperiment", "Cannot create a port-forward {} -> {} for MC Connection.", local_port, port);
+        let Some(local_port) = forward_server(easytier, host_ip, port, edition) else {
             state.set(AppState::Exception { kind: ExceptionType::GuestEasytierCrash });
             return;
-        } else {}
-
+        };
         local_port
     };
 
+    let mut status = None;
     for _ in 0..8 {
-        if check_mc_conn(local_port) {
+        status = check_mc_conn(edition, SocketAddrV4::new(Ipv4Addr::LOCALHOST, local_port).into());
+        if status.is_some() {
             break;
         }
     }
-    logging!("RoomExperiment", "MC connection is OK.");
-
-    let local_profile = ProfileSnapshot {
-        machine_id: MACHINE_ID.to_string(),
-        name: player.unwrap_or("Terracotta Anonymous Guest".to_string()),
-        vendor: VENDOR.to_string(),
-        kind: ProfileKind::LOCAL,
-    }.into_profile();
+    logging!("RoomExperiment", "MC connection is OK: {:?}", status.as_ref().map(|status| (&status.version, status.online, status.max)));
+
+    // Host is told by the player list, so that the configured MOTD is advertised since the first LAN broadcast.
+    let mut profiles = vec![local_profile.clone()];
+    if supports("c:player_profiles_list") && let Some(PacketResponse::Ok { data }) = session.send_sync(("c", "player_profiles_list"), |body| {
+        serde_json::to_writer(body, &json!({ "machine_id": local_profile.get_machine_id() })).unwrap();
+    }) && let Some(server_profiles) = reconcile::parse_profiles(&data, &local_profile) {
+        // Profiles are reconciled again by the first refresh, which reports conflicts.
+        reconcile::reconcile(&mut profiles, server_profiles);
+    }
+    let motd = motd.unwrap_or_else(|| motd::DEFAULT_TEMPLATE.to_string());
 
     let capture = {
         let Some(state) = capture.try_capture() else {
@@ -431,91 +745,301 @@ pub fn start_guest(room: Room, player: Option<String>, capture: AppStateCapture,
                 unreachable!();
             };
 
+            let server = FakeServer::create(edition, local_port, motd::render(&motd, &room, &profiles, status.as_ref()));
             AppState::GuestOk {
                 room,
+                edition,
                 easytier,
-                server: FakeServer::create(local_port, crate::MOTD),
-                profiles: vec![local_profile.clone()],
+                server,
+                motd,
+                status,
+                profiles,
+                unsupported,
+                chat: ChatLog::default(),
+                mods,
+                session: session.clone(),
             }
         })
     };
 
     thread::spawn(move || {
+        let mut port = port;
+        let mut pinged_at = SystemTime::now();
+        // The cursor of room events, or None if host doesn't support c:subscribe and player list must be polled.
+        let mut subscription: Option<Option<u64>> = supports("c:subscribe").then_some(None);
         loop {
-            thread::sleep(Duration::from_secs(5));
-
-            {
-                let Some(_) = session.send_sync(("c", "player_ping"), |body| {
+            let mut chat = false;
+            let mut server = false;
+            let refresh = match subscription {
+                None => {
+                    thread::sleep(Duration::from_secs(5));
+                    true
+                }
+                Some(cursor) => match session.invoke(("c", "subscribe"), |body| {
                     serde_json::to_writer(body, &json!({
                         "machine_id": local_profile.get_machine_id(),
-                        "name": local_profile.get_name(),
-                        "vendor": local_profile.get_vendor()
+                        "cursor": cursor,
                     })).unwrap();
-                }) else {
-                    fail(capture);
-                    return;
+                }) {
+                    Some(PacketResponse::Ok { data }) => {
+                        let Some((cursor, events, resync)) = serde_json::from_slice::<Value>(&data).ok().and_then(|value| {
+                            let value = value.as_object()?;
+                            let events = value.get("events")?.as_array()?.iter()
+                                // Events unknown by this version are ignored.
+                                .filter_map(|event| serde_json::from_value::<RoomEvent>(event.clone()).ok())
+                                .collect::<Vec<_>>();
+
+                            Some((value.get("cursor")?.as_u64()?, events, value.get("resync")?.as_bool()?))
+                        }) else {
+                            logging!("RoomExperiment", "API c:subscribe invocation failed: Invalid response.");
+                            let Some(state) = capture.try_capture() else {
+                                return;
+                            };
+                            state.set(AppState::Exception { kind: ExceptionType::ScaffoldingInvalidResponse });
+                            return;
+                        };
+                        subscription = Some(Some(cursor));
+
+                        let Some(mut state) = capture.try_capture() else {
+                            return;
+                        };
+                        let AppState::GuestOk { profiles, .. } = state.as_mut_ref() else {
+                            unreachable!();
+                        };
+
+                        let applied = reconcile::apply_events(profiles, events, &MACHINE_ID);
+                        if applied.closing {
+                            logging!("RoomExperiment", "Room has been closed by host.");
+                            state.set(AppState::Exception { kind: ExceptionType::RoomClosed });
+                            return;
+                        }
+                        if applied.changed {
+                            state.increase_shared();
+                        }
+                        chat = applied.chat;
+                        server = applied.server;
+
+                        resync || applied.resync
+                    }
+                    Some(PacketResponse::Fail { status: 255, .. }) => {
+                        logging!("RoomExperiment", "Host doesn't support c:subscribe, polling player list instead.");
+                        subscription = None;
+                        true
+                    }
+                    // Host has enabled approve-joins mode after this guest timed out, which is told by c:player_ping.
+                    Some(PacketResponse::Fail { status: STATUS_APPROVAL_PENDING, .. }) => {
+                        thread::sleep(Duration::from_secs(5));
+                        continue;
+                    }
+                    Some(PacketResponse::Fail { status, data }) if refused(status).is_some() => {
+                        logging!("RoomExperiment", "Refused by host, status={}: {}", status, String::from_utf8_lossy(&data));
+                        let Some(state) = capture.try_capture() else {
+                            return;
+                        };
+                        state.set(AppState::Exception { kind: refused(status).unwrap() });
+                        return;
+                    }
+                    Some(PacketResponse::Fail { status, data }) => {
+                        logging!("RoomExperiment", "API c:subscribe invocation failed with status {}: {}", status, String::from_utf8_lossy(&data));
+                        fail(capture);
+                        return;
+                    }
+                    None => {
+                        fail(capture);
+                        return;
+                    }
+                },
+            };
+            // Chat history is fetched along with the whole player list, which also happens right after joining.
+            if (refresh || chat) && supports("c:chat_fetch") {
+                let cursor = {
+                    let Some(state) = capture.try_capture() else {
+                        return;
+                    };
+                    let AppState::GuestOk { chat, .. } = state.as_ref() else {
+                        unreachable!();
+                    };
+                    chat.cursor()
+                };
+                // Chat isn't essential to play, so that failures are only logged and retried with the next refresh.
+                let messages = match session.invoke(("c", "chat_fetch"), |body| {
+                    serde_json::to_writer(body, &json!({ "cursor": cursor, "machine_id": local_profile.get_machine_id() })).unwrap();
+                }) {
+                    Some(PacketResponse::Ok { data }) => serde_json::from_slice::<Value>(&data).ok()
+                        .and_then(|value| serde_json::from_value::<Vec<ChatMessage>>(value.get("messages")?.clone()).ok())
+                        .or_else(|| {
+                            logging!("RoomExperiment", "API c:chat_fetch invocation failed: Invalid response.");
+                            None
+                        }),
+                    Some(PacketResponse::Fail { status, data }) => {
+                        logging!("RoomExperiment", "API c:chat_fetch invocation failed with status {}: {}", status, String::from_utf8_lossy(&data));
+                        None
+                    }
+                    None => {
+                        fail(capture);
+                        return;
+                    }
                 };
-            }
 
-            {
-                let Some(server_profiles) = session.send_sync(("c", "player_profiles_list"), |_| {}).map(|response| {
-                    let PacketResponse::Ok { data } = response else {
+                if let Some(messages) = messages {
+                    let Some(mut state) = capture.try_capture() else {
+                        return;
+                    };
+                    let AppState::GuestOk { chat, .. } = state.as_mut_ref() else {
                         unreachable!();
                     };
-                    data
-                }).and_then(|data| {
-                    let mut host = false;
-                    let mut local = false;
-
-                    let mut server_players: Vec<Profile> = vec![];
-                    for item in serde_json::from_slice::<Value>(data.as_slice()).ok()?.as_array()? {
-                        let name = item.as_object()?.get("name")?.as_str()?;
-                        let machine_id = item.as_object()?.get("machine_id")?.as_str()?;
-                        let vendor = item.as_object()?.get("vendor")?.as_str()?;
-
-                        let kind = if machine_id == *MACHINE_ID {
-                            if local {
-                                logging!("RoomExperiment", "API c:player_profiles_list invocation failed: Multiple local player, machine_id may have conflicted.");
-                                return None;
-                            }
-                            local = true;
-
-                            ProfileKind::LOCAL
-                        } else {
-                            match item.as_object()?.get("kind")?.as_str()? {
-                                "HOST" if !host => {
-                                    host = true;
-                                    ProfileKind::HOST
+                    chat.merge(messages);
+                }
+            }
+            // Host has stopped or reopened the world, which may be served at another port now.
+            // It's also queried after resync, as RoomEvent::Server may have been dropped.
+            if server || (refresh && subscription.is_some()) {
+                match session.invoke(("c", "server_port"), |body| {
+                    serde_json::to_writer(body, &json!({ "machine_id": local_profile.get_machine_id() })).unwrap();
+                }) {
+                    Some(PacketResponse::Ok { data }) if data.len() == 2 => {
+                        let reopened = u16::from_be_bytes([data[0], data[1]]);
+                        if reopened != port {
+                            logging!("RoomExperiment", "MC server has moved from {} to {}", port, reopened);
+                            let Some(state) = capture.try_capture() else {
+                                return;
+                            };
+                            let mut state = state.into_slow();
+                            let AppState::GuestOk { room, easytier, server, motd, status, profiles, .. } = state.as_mut_ref() else {
+                                unreachable!();
+                            };
+                            match move_forward(easytier, host_ip, server.port, port, reopened) {
+                                Some(true) => {}
+                                // Previous port-forwards are left behind then, and the world is advertised at another port.
+                                Some(false) => {
+                                    let Some(local_port) = forward_server(easytier, host_ip, reopened, edition) else {
+                                        state.set(AppState::Exception { kind: ExceptionType::GuestEasytierCrash });
+                                        return;
+                                    };
+                                    *server = FakeServer::create(edition, local_port, motd::render(motd, room, profiles, status.as_ref()));
+                                }
+                                None => {
+                                    state.set(AppState::Exception { kind: ExceptionType::GuestEasytierCrash });
+                                    return;
                                 }
-                                "GUEST" => ProfileKind::GUEST,
-                                _ => return None,
                             }
-                        };
-
-                        server_players.push(ProfileSnapshot {
-                            machine_id: machine_id.to_string(),
-                            name: name.to_string(),
-                            vendor: vendor.to_string(),
-                            kind,
-                        }.into_profile())
+                            state.increase_shared();
+                            port = reopened;
+                        }
                     }
-                    if !host {
-                        logging!("RoomExperiment", "API c:player_profiles_list invocation failed: No host detected.");
-                        return None;
+                    Some(PacketResponse::Fail { status: 32, .. }) => {
+                        logging!("RoomExperiment", "MC server of host has stopped, waiting for the world to be reopened.");
                     }
-                    if !local {
-                        server_players.push(local_profile.clone());
+                    Some(PacketResponse::Ok { .. }) => {
+                        logging!("RoomExperiment", "API c:server_port invocation failed: Invalid response.");
                     }
+                    Some(PacketResponse::Fail { status, data }) => {
+                        logging!("RoomExperiment", "API c:server_port invocation failed with status {}: {}", status, String::from_utf8_lossy(&data));
+                    }
+                    None => {
+                        fail(capture);
+                        return;
+                    }
+                }
+            }
+            // Guests may see the version and players of the world before launching the game.
+            if pinged_at.elapsed().is_ok_and(|d| d >= STATUS_INTERVAL) {
+                pinged_at = SystemTime::now();
+                let local_port = {
+                    let Some(state) = capture.try_capture() else {
+                        return;
+                    };
+                    let AppState::GuestOk { server, .. } = state.as_ref() else {
+                        unreachable!();
+                    };
+                    server.port
+                };
+                let pinged = query_status(edition, SocketAddrV4::new(Ipv4Addr::LOCALHOST, local_port).into(), Duration::from_secs(5)).ok();
 
-                    server_players.sort_by_cached_key(|profile| profile.get_machine_id().to_string());
-                    for profile in server_players.windows(2) {
-                        if profile[0].get_machine_id() == profile[1].get_machine_id() {
-                            logging!("RoomExperiment", "API c:player_profiles_list invocation failed: machine_id conflict.");
-                            return None;
-                        }
+                let Some(mut state) = capture.try_capture() else {
+                    return;
+                };
+                let AppState::GuestOk { status, .. } = state.as_mut_ref() else {
+                    unreachable!();
+                };
+                if *status != pinged {
+                    *status = pinged;
+                    state.increase_shared();
+                }
+            }
+            {
+                let Some(state) = capture.try_capture() else {
+                    return;
+                };
+                let AppState::GuestOk { room, server, motd, status, profiles, .. } = state.as_ref() else {
+                    unreachable!();
+                };
+                // Players may have joined or left the room, which is told on the multiplayer screen.
+                server.set_motd(motd::render(motd, room, profiles, status.as_ref()));
+            }
+            if !refresh {
+                continue;
+            }
+
+            // Both requests are in flight simultaneously if session is pipelined.
+            let ping = if supports("c:player_ping") {
+                session.send(("c", "player_ping"), |body| {
+                    serde_json::to_writer(body, &json!({
+                        "machine_id": local_profile.get_machine_id(),
+                        "name": local_profile.get_name(),
+                        "vendor": local_profile.get_vendor()
+                    })).unwrap();
+                })
+            } else {
+                // Still check whether host is reachable.
+                session.send(("c", "ping"), |_| {})
+            };
+            let profiles_list = supports("c:player_profiles_list").then(|| session.send(("c", "player_profiles_list"), |body| {
+                serde_json::to_writer(body, &json!({ "machine_id": local_profile.get_machine_id() })).unwrap();
+            }));
+
+            match ping.wait() {
+                Some(PacketResponse::Ok { .. }) => {}
+                // Host has enabled approve-joins mode after this guest timed out, which is told by the next ping.
+                Some(PacketResponse::Fail { status: STATUS_APPROVAL_PENDING, .. }) => {
+                    logging!("RoomExperiment", "Waiting for host to approve the join again.");
+                }
+                Some(PacketResponse::Fail { status, data }) => {
+                    let Some(kind) = refused(status) else {
+                        logging!("RoomExperiment", "API c:player_ping invocation failed with status {}: {}", status, String::from_utf8_lossy(&data));
+                        fail(capture);
+                        return;
+                    };
+                    logging!("RoomExperiment", "Refused by host, status={}: {}", status, String::from_utf8_lossy(&data));
+                    let Some(state) = capture.try_capture() else {
+                        return;
+                    };
+                    state.set(AppState::Exception { kind });
+                    return;
+                }
+                None => {
+                    fail(capture);
+                    return;
+                }
+            }
+
+            let Some(profiles_list) = profiles_list else {
+                continue;
+            };
+
+            {
+                let response = profiles_list.wait();
+                // Player list is withheld until host approves this guest again.
+                if matches!(response, Some(PacketResponse::Fail { status: STATUS_APPROVAL_PENDING, .. })) {
+                    continue;
+                }
+                let Some(server_profiles) = response.and_then(|response| match response {
+                    PacketResponse::Ok { data } => Some(data),
+                    PacketResponse::Fail { status, data } => {
+                        logging!("RoomExperiment", "API c:player_profiles_list invocation failed with status {}: {}", status, String::from_utf8_lossy(&data));
+                        None
                     }
-                    Some(server_players)
-                }) else {
+                }).and_then(|data| reconcile::parse_profiles(&data, &local_profile)) else {
                     fail(capture);
                     return;
                 };
@@ -531,67 +1055,168 @@ pub fn start_guest(room: Room, player: Option<String>, capture: AppStateCapture,
                     return;
                 }
 
-                let mut used = vec![false; server_profiles.len()];
-                let mut changed = false;
-                for i in (0..profiles.len()).rev() {
-                    let profile = &mut profiles[i];
-                    match profile.get_kind() {
-                        ProfileKind::HOST => match server_profiles.binary_search_by_key(&profile.get_machine_id(), |p| p.get_machine_id()) {
-                            Ok(index) if !used[index] && *server_profiles[index].get_kind() == ProfileKind::HOST => {
-                                used[index] = true;
-                                if profile.get_name() != server_profiles[index].get_name() {
-                                    profile.set_name(server_profiles[index].get_name().to_string());
-                                    changed = true;
-                                }
-                            }
-                            _ => {
-                                logging!("RoomExperiment", "API c:player_profiles_list invocation failed: Host Profile is consumed or invalid, machine_id may have conflict.");
-                                state.set(AppState::Exception { kind: ExceptionType::ScaffoldingInvalidResponse });
-                                return;
-                            }
-                        },
-                        ProfileKind::LOCAL => {}
-                        ProfileKind::GUEST => match server_profiles.binary_search_by_key(&profile.get_machine_id(), |p| p.get_machine_id()) {
-                            Ok(index) if used[index] && *server_profiles[index].get_kind() == ProfileKind::GUEST => {
-                                profiles.remove(i);
-                                changed = true;
-                            }
-                            Ok(index) if *server_profiles[index].get_kind() == ProfileKind::GUEST => {
-                                used[index] = true;
-                                if profile.get_name() != server_profiles[index].get_name() {
-                                    profile.set_name(server_profiles[index].get_name().to_string());
-                                    changed = true;
-                                }
-                            }
-                            Ok(_) => {
-                                logging!("RoomExperiment", "API c:player_profiles_list invocation failed: Guest Profile type is changed, machine_id may have conflict.");
-                                state.set(AppState::Exception { kind: ExceptionType::ScaffoldingInvalidResponse });
-                                return;
-                            }
-                            Err(_) => {
-                                profiles.remove(i);
-                                changed = true;
-                            }
-                        },
+                match reconcile::reconcile(profiles, server_profiles) {
+                    Some(true) => {
+                        state.increase_shared();
                     }
-                }
-
-                let mut server_profiles = server_profiles;
-                for i in (0..server_profiles.len()).rev() {
-                    let profile = server_profiles.pop().unwrap();
-                    if !used[i] && *profile.get_kind() != ProfileKind::LOCAL {
-                        profiles.push(profile);
-                        changed = true;
+                    Some(false) => {}
+                    None => {
+                        state.set(AppState::Exception { kind: ExceptionType::ScaffoldingInvalidResponse });
+                        return;
                     }
                 }
-                if changed {
-                    state.increase_shared();
-                }
             }
         }
     });
 }
 
+/// Forward a local port to MC server of host through TCP and UDP, returning the local port.
+fn forward_server(easytier: &mut EasyTier, host_ip: Ipv4Addr, port: u16, edition: Edition) -> Option<u16> {
+    // To maximum compatibility, try to request the identical port.
+    // If failed, use a dynamic free port instead.
+    // Default ports of Bedrock Edition are left to FakeServer, which answers RakNet pings with the local port.
+    let local_port = match edition {
+        Edition::Java => PortRequest::request_specific(port).unwrap_or_else(|e| {
+            logging!("RoomExperiment", "Unable to request shadow port {} on client: {:?}. Mods requiring UDP socket like SimpleVoiceChat may go wrong.", port, e);
+            PortRequest::Minecraft.request()
+        }),
+        Edition::Bedrock => PortRequest::Minecraft.request(),
+    };
+
+    if !easytier.add_port_forward(&port_forwards(local_port, host_ip, port)) {
+        logging!("RoomExperiment", "Cannot create a port-forward {} -> {} for MC Connection.", local_port, port);
+        return None;
+    }
+    Some(local_port)
+}
+
+/// Point the port-forwards at local_port to another port of host, so that Minecraft keeps reaching the world at the
+/// same local port. Returns Some(false) if previous port-forwards cannot be removed, which are kept untouched then, or
+/// None if new ones cannot be created.
+fn move_forward(easytier: &mut EasyTier, host_ip: Ipv4Addr, local_port: u16, from: u16, to: u16) -> Option<bool> {
+    if !easytier.remove_port_forward(&port_forwards(local_port, host_ip, from)) {
+        logging!("RoomExperiment", "Cannot remove the port-forward {} -> {} for MC Connection.", local_port, from);
+        return Some(false);
+    }
+    if !easytier.add_port_forward(&port_forwards(local_port, host_ip, to)) {
+        logging!("RoomExperiment", "Cannot create a port-forward {} -> {} for MC Connection.", local_port, to);
+        return None;
+    }
+    Some(true)
+}
+
+fn port_forwards(local_port: u16, host_ip: Ipv4Addr, port: u16) -> [PortForward; 4] {
+    let locals = [
+        SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, local_port).into(),
+        SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, local_port, 0, 0).into(),
+    ];
+    let protos = [Proto::TCP, Proto::UDP];
+
+    // TODO: Compute SIZE automatically.
+    const SIZE: usize = 4;
+    assert_eq!(locals.len() * protos.len(), SIZE);
+    let mut forwards: [MaybeUninit<PortForward>; SIZE] = [const { MaybeUninit::uninit() }; _];
+    for (i, local) in locals.into_iter().enumerate() {
+        for (j, proto) in protos.iter().enumerate() {
+            forwards[i * 2 + j].write(PortForward {
+                remote: SocketAddrV4::new(host_ip, port).into(),
+                local,
+                proto: proto.clone(),
+            });
+        }
+    }
+    // SAFETY: These two types are of the same size and all elements have been properly initialized.
+    unsafe { transmute::<[MaybeUninit<PortForward>; SIZE], [PortForward; SIZE]>(forwards) }
+}
+
+/// Map statuses of c:player_ping and c:server_port, with which host refuses this guest, to exceptions.
+fn refused(status: u8) -> Option<ExceptionType> {
+    match status {
+        STATUS_INVITE_REQUIRED => Some(ExceptionType::InviteRequired),
+        STATUS_KICKED => Some(ExceptionType::Kicked),
+        STATUS_BANNED => Some(ExceptionType::Banned),
+        STATUS_JOIN_DENIED => Some(ExceptionType::JoinDenied),
+        STATUS_ROOM_FULL => Some(ExceptionType::RoomFull),
+        _ => None,
+    }
+}
+
+/// Keep pinging host, which approves joins, until this guest is approved. Returns false if the join has been given up,
+/// in which case AppState has been updated if it's still captured.
+fn wait_for_approval(session: &mut ClientSession, capture: &AppStateCapture, local_profile: &Profile) -> bool {
+    logging!("RoomExperiment", "Waiting for host to approve the join.");
+    loop {
+        // Pending guests are kept in the queue of host as long as they keep pinging.
+        let kind = match session.send_sync(("c", "player_ping"), |body| {
+            serde_json::to_writer(body, &json!({
+                "machine_id": local_profile.get_machine_id(),
+                "name": local_profile.get_name(),
+                "vendor": local_profile.get_vendor()
+            })).unwrap();
+        }) {
+            Some(PacketResponse::Ok { .. }) => {
+                logging!("RoomExperiment", "Join has been approved.");
+                return true;
+            }
+            Some(PacketResponse::Fail { status: STATUS_APPROVAL_PENDING, .. }) => None,
+            Some(PacketResponse::Fail { status, data }) => {
+                logging!("RoomExperiment", "Refused by host, status={}: {}", status, String::from_utf8_lossy(&data));
+                Some(refused(status).unwrap_or(ExceptionType::PingHostFail))
+            }
+            None => Some(ExceptionType::PingHostFail),
+        };
+
+        {
+            let Some(mut state) = capture.try_capture() else {
+                return false;
+            };
+            if let Some(kind) = kind {
+                state.set(AppState::Exception { kind });
+                return false;
+            }
+            let AppState::GuestStarting { easytier, awaiting_approval, .. } = state.as_mut_ref() else {
+                unreachable!();
+            };
+            if !easytier.is_alive() {
+                state.set(AppState::Exception { kind: ExceptionType::GuestEasytierCrash });
+                return false;
+            }
+            if !*awaiting_approval {
+                *awaiting_approval = true;
+                state.increase_shared();
+            }
+        }
+        thread::sleep(Duration::from_secs(2));
+    }
+}
+
+/// Compare mods under directory with the manifest published by host. None is returned if host hasn't published one.
+fn compare_mods(session: &mut ClientSession, directory: &Path) -> Option<ModDiff> {
+    let host = match session.invoke(("c", "mods_manifest"), |_| {})? {
+        PacketResponse::Ok { data } => match serde_json::from_slice::<Option<Vec<ModEntry>>>(&data) {
+            Ok(host) => host?,
+            Err(e) => {
+                logging!("RoomExperiment", "API c:mods_manifest invocation failed: Invalid response, {:?}", e);
+                return None;
+            }
+        },
+        PacketResponse::Fail { status, data } => {
+            logging!("RoomExperiment", "API c:mods_manifest invocation failed with status {}: {}", status, String::from_utf8_lossy(&data));
+            return None;
+        }
+    };
+    let local = mods::scan(directory).inspect_err(|e| {
+        logging!("RoomExperiment", "Cannot scan mods in {:?}: {:?}", directory, e);
+    }).ok()?;
+
+    let diff = mods::diff(&host, &local);
+    logging!(
+        "RoomExperiment", "Compared {} mods of host with {} local mods: {} missing, {} extra, {} different.",
+        host.len(), local.len(), diff.missing.len(), diff.extra.len(), diff.different.len()
+    );
+    Some(diff)
+}
+
 fn compute_arguments(room: &Room, public_servers: PublicServers) -> Vec<Argument> {
     static DEFAULT_ARGUMENTS: [Argument; 8] = [
         Argument::NoTun,
@@ -624,23 +1249,25 @@ fn compute_arguments(room: &Room, public_servers: PublicServers) -> Vec<Argument
     args
 }
 
-fn check_mc_conn(port: u16) -> bool {
+fn query_status(edition: Edition, address: SocketAddr, timeout: Duration) -> io::Result<ServerStatus> {
+    match edition {
+        Edition::Java => status::query(address, timeout),
+        Edition::Bedrock => bedrock::ping(address, timeout).map(|pong| pong.to_status()),
+    }
+}
+
+fn check_mc_conn(edition: Edition, address: SocketAddr) -> Option<ServerStatus> {
     let start = SystemTime::now();
 
-    let socket = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
-    socket.set_read_timeout(Some(Duration::from_secs(64))).unwrap();
-    socket.set_write_timeout(Some(Duration::from_secs(64))).unwrap();
-    if let Ok(_) = socket.connect_timeout(
-        &SockAddr::from(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), port)),
-        Duration::from_secs(64),
-    ) && let Ok(_) = socket.send(&[0xFE]) {
-        let mut buf: [MaybeUninit<u8>; _] = [MaybeUninit::uninit(); 1];
-
-        if let Ok(size) = socket.recv(&mut buf) && size >= 1
-            // SAFETY: The first byte has been initialized by recv, as size >= 1
-            && unsafe { buf[0].assume_init() } == 0xFF
-        {
-            return true;
+    // RakNet pings are sent through UDP, which may be lost.
+    let timeout = match edition {
+        Edition::Java => Duration::from_secs(64),
+        Edition::Bedrock => Duration::from_secs(5),
+    };
+    match query_status(edition, address, timeout) {
+        Ok(status) => return Some(status),
+        Err(e) => {
+            logging!("RoomExperiment", "Cannot query status of MC server at {}: {:?}", address, e);
         }
     }
 
@@ -649,5 +1276,5 @@ fn check_mc_conn(port: u16) -> bool {
             .duration_since(SystemTime::now())
             .unwrap_or(Duration::ZERO),
     );
-    false
+    None
 }
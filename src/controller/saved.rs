@@ -0,0 +1,230 @@
+use crate::controller::{set_scanning, Room, RoomCodeError, RoomKind};
+use crate::SAVED_ROOMS_FILE;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A room profile persisted on disk, so that the host can reopen a room with the same code after restarting.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedRoom {
+    pub name: String,
+    pub code: String,
+    /// The seed of the room code. It's stored as a decimal string, as JSON numbers cannot hold an u128.
+    pub seed: String,
+    /// The passphrase of a protected room. It's kept so that the room can be reopened without asking host again.
+    #[serde(default)]
+    pub passphrase: Option<String>,
+    pub player: Option<String>,
+    pub public_nodes: Vec<String>,
+    /// The mods directory announced to guests. Rooms saved by older versions have none.
+    #[serde(default)]
+    pub mods: Option<PathBuf>,
+    /// The maximum number of players, including host. Rooms saved by older versions have no limit.
+    #[serde(default)]
+    pub capacity: Option<usize>,
+    /// Milliseconds since UNIX epoch when this room was opened most recently, or 0 if it has never been opened.
+    pub last_used: u64,
+}
+
+impl SavedRoom {
+    /// Describe the room to UI. Passphrase is never sent back, while UI is told whether the room has one.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "name": self.name,
+            "code": self.code,
+            "seed": self.seed,
+            "has_passphrase": self.passphrase.is_some(),
+            "player": self.player,
+            "public_nodes": self.public_nodes,
+            "mods": self.mods,
+            "capacity": self.capacity,
+            "last_used": self.last_used,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub enum SavedRoomError {
+    InvalidName,
+    NameConflict,
+    InvalidRoomCode(RoomCodeError),
+    /// A passphrase is given for a room code which isn't a protected one.
+    UnexpectedPassphrase,
+    InvalidCapacity,
+    NotFound,
+    IllegalState,
+}
+
+impl SavedRoomError {
+    pub fn to_json(&self) -> Value {
+        match self {
+            SavedRoomError::InvalidName => json!({"error": "invalid_name", "message": "Room name must not be empty."}),
+            SavedRoomError::NameConflict => json!({"error": "name_conflict", "message": "A saved room with the same name exists."}),
+            SavedRoomError::InvalidRoomCode(e) => {
+                json!({"error": "invalid_room_code", "detail": e, "message": e.to_string()})
+            }
+            SavedRoomError::UnexpectedPassphrase => json!({
+                "error": "unexpected_passphrase",
+                "message": "Passphrase can only be set for protected rooms, whose code starts with P/.",
+            }),
+            SavedRoomError::InvalidCapacity => {
+                json!({"error": "invalid_capacity", "message": "Room capacity must be at least 2, including host."})
+            }
+            SavedRoomError::NotFound => json!({"error": "not_found", "message": "Cannot find the saved room."}),
+            SavedRoomError::IllegalState => {
+                json!({"error": "illegal_state", "message": "Terracotta is not in waiting state."})
+            }
+        }
+    }
+}
+
+static LOCK: Mutex<()> = Mutex::new(());
+
+fn load() -> Vec<SavedRoom> {
+    let data = match fs::read(SAVED_ROOMS_FILE.as_path()) {
+        Ok(data) => data,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return vec![],
+        Err(e) => {
+            logging!("SavedRooms", "Cannot read saved rooms: {:?}", e);
+            return vec![];
+        }
+    };
+
+    match serde_json::from_slice::<Vec<SavedRoom>>(&data) {
+        Ok(rooms) => rooms
+            .into_iter()
+            .filter(|room| match Room::from(&room.code, room.passphrase.as_deref()) {
+                Ok(Room { kind, .. }) if kind.seed().to_string() == room.seed => true,
+                _ => {
+                    logging!("SavedRooms", "Dropping corrupted saved room {:?}.", room.name);
+                    false
+                }
+            })
+            .collect(),
+        Err(e) => {
+            logging!("SavedRooms", "Cannot parse saved rooms: {:?}", e);
+            vec![]
+        }
+    }
+}
+
+fn store(rooms: &[SavedRoom]) {
+    let file = SAVED_ROOMS_FILE.as_path();
+    let temp = file.with_extension("json.tmp");
+
+    if let Err(e) = fs::write(&temp, serde_json::to_vec(rooms).unwrap()).and_then(|_| fs::rename(&temp, file)) {
+        logging!("SavedRooms", "Cannot save rooms: {:?}", e);
+    }
+}
+
+pub fn list_saved_rooms() -> Vec<SavedRoom> {
+    let _lock = LOCK.lock();
+    load()
+}
+
+pub fn create_saved_room(
+    name: String,
+    room: Option<String>,
+    passphrase: Option<String>,
+    player: Option<String>,
+    public_nodes: Vec<String>,
+    mods: Option<PathBuf>,
+    capacity: Option<usize>,
+) -> Result<SavedRoom, SavedRoomError> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err(SavedRoomError::InvalidName);
+    }
+    if capacity.is_some_and(|capacity| capacity < 2) {
+        return Err(SavedRoomError::InvalidCapacity);
+    }
+
+    let passphrase = passphrase.filter(|passphrase| !passphrase.is_empty());
+    let room = match room {
+        Some(room) => Room::from(&room, passphrase.as_deref()).map_err(SavedRoomError::InvalidRoomCode)?,
+        None => Room::create(passphrase.clone()),
+    };
+    if matches!(room.kind, RoomKind::Scaffolding { .. }) && passphrase.is_some() {
+        return Err(SavedRoomError::UnexpectedPassphrase);
+    }
+
+    let _lock = LOCK.lock();
+    let mut rooms = load();
+    if rooms.iter().any(|saved| saved.name == name) {
+        return Err(SavedRoomError::NameConflict);
+    }
+
+    let saved = SavedRoom {
+        name,
+        code: room.code,
+        seed: room.kind.seed().to_string(),
+        passphrase,
+        player,
+        public_nodes,
+        mods,
+        capacity,
+        last_used: 0,
+    };
+    rooms.push(saved.clone());
+    store(&rooms);
+
+    logging!("SavedRooms", "Saved room {:?}, code={}", saved.name, saved.code);
+    Ok(saved)
+}
+
+pub fn delete_saved_room(name: &str) -> Result<(), SavedRoomError> {
+    let _lock = LOCK.lock();
+    let mut rooms = load();
+    let Some(index) = rooms.iter().position(|saved| saved.name == name) else {
+        return Err(SavedRoomError::NotFound);
+    };
+
+    let saved = rooms.remove(index);
+    store(&rooms);
+
+    logging!("SavedRooms", "Deleted saved room {:?}, code={}", saved.name, saved.code);
+    Ok(())
+}
+
+/// Reopen a saved room, or the most recently used one if name is absent.
+pub fn open_saved_room(name: Option<&str>) -> Result<SavedRoom, SavedRoomError> {
+    let _lock = LOCK.lock();
+    let mut rooms = load();
+
+    let index = match name {
+        Some(name) => rooms.iter().position(|saved| saved.name == name),
+        None => rooms
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, saved)| saved.last_used)
+            .map(|(index, _)| index),
+    };
+    let Some(index) = index else {
+        return Err(SavedRoomError::NotFound);
+    };
+
+    let saved = &mut rooms[index];
+    if !set_scanning(
+        Some(saved.code.clone()),
+        saved.passphrase.clone(),
+        saved.player.clone(),
+        saved.public_nodes.clone(),
+        saved.mods.clone(),
+        saved.capacity,
+    ) {
+        return Err(SavedRoomError::IllegalState);
+    }
+    logging!("SavedRooms", "Reopening saved room {:?}, code={}", saved.name, saved.code);
+
+    saved.last_used = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let saved = saved.clone();
+    store(&rooms);
+
+    Ok(saved)
+}
@@ -1,19 +1,31 @@
 use std::fmt::{Debug, Formatter};
 use crate::easytier::EasyTier;
 use crate::mc::fakeserver::FakeServer;
+use crate::mc::mods::{ModDiff, ModEntry};
+use crate::mc::relay::Relay;
+use crate::mc::Edition;
+use crate::mc::status::ServerStatus;
 use crate::mc::scanning::MinecraftScanner;
 use std::mem;
+use std::net::SocketAddr;
 use std::panic::Location;
 use std::time::{Duration, SystemTime};
 use parking_lot::{Mutex, MutexGuard};
+use crate::controller::scaffolding::approval::ApprovalQueue;
+use crate::controller::scaffolding::chat::ChatLog;
 use crate::controller::{ConnectionDifficulty, Room};
+use crate::scaffolding::client::ClientSession;
 use crate::scaffolding::profile::Profile;
 
 pub enum AppState {
     Waiting,
 
     HostScanning {
+        /// Bedrock worlds are discovered through RakNet pings instead of scanner.
+        edition: Edition,
         scanner: MinecraftScanner,
+        /// The world chosen by host, which is required if several worlds are opened to LAN.
+        selected: Option<u16>,
     },
     HostStarting {
         room: Room,
@@ -21,9 +33,34 @@ pub enum AppState {
     },
     HostOk {
         room: Room,
+        edition: Edition,
+        /// The port exposed to guests, which is the port of a LAN world or a relay.
         port: u16,
+        /// Address of the server given by host, or None if it's a LAN world.
+        server: Option<SocketAddr>,
+        /// Relay to the server given by host, if it's on another machine.
+        relay: Option<Relay>,
+        /// Whether Minecraft server has stopped and host is waiting for the world to be reopened, possibly at another port.
+        reopening: bool,
+        /// Status of the shared server from the latest ping, or None if it hasn't answered yet or has stopped.
+        status: Option<ServerStatus>,
+        /// Worlds opened to LAN, among which host may switch the shared one.
+        scanner: MinecraftScanner,
         easytier: EasyTier,
         profiles: Vec<(SystemTime, Profile)>,
+        /// Whether guests must present an invite through c:player_invite before joining.
+        invite_only: bool,
+        /// Machine IDs which have presented a valid invite, or had joined before invite-only mode is enabled.
+        invited: Vec<String>,
+        /// Machine IDs removed by host, which are told to leave on their next c:player_ping.
+        kicked: Vec<(SystemTime, String)>,
+        /// Guests awaiting approval of host, if approve-joins mode is enabled.
+        approval: ApprovalQueue,
+        /// Maximum number of players in the room, including host.
+        capacity: Option<usize>,
+        chat: ChatLog,
+        /// Manifest of mods published to guests through c:mods_manifest, if host has chosen a mods directory.
+        mods: Option<Vec<ModEntry>>,
     },
 
     GuestConnecting {
@@ -32,14 +69,29 @@ pub enum AppState {
     GuestStarting {
         room: Room,
         easytier: EasyTier,
-        difficulty: ConnectionDifficulty
+        difficulty: ConnectionDifficulty,
+        /// Whether host approves joins and hasn't approved this guest yet.
+        awaiting_approval: bool,
     },
     GuestOk {
         room: Room,
+        edition: Edition,
         easytier: EasyTier,
         server: FakeServer,
+        /// Template of the MOTD advertised by server, which is rendered with room data.
+        motd: String,
+        /// Status of the server of host from the latest ping through the forwarded port.
+        status: Option<ServerStatus>,
 
         profiles: Vec<Profile>,
+        /// Protocols used by guests but not implemented by host, which is running an older Terracotta or other vendor.
+        unsupported: Vec<&'static str>,
+        /// Mirror of the chat history kept by host.
+        chat: ChatLog,
+        /// Differences from the mods of host, if both sides have chosen a mods directory.
+        mods: Option<ModDiff>,
+        /// Another handle of the scaffolding session, through which chat messages are sent outside the guest thread.
+        session: ClientSession,
     },
     Exception {
         kind: ExceptionType,
@@ -56,8 +108,11 @@ impl Debug for AppState {
             AppState::HostStarting { room, port } => {
                 write!(f, "AppState::HostStarting {{ code: {:?}, port: {} }}", room.code, port)
             }
-            AppState::HostOk { room, port, profiles, .. } => {
-                write!(f, "AppState::HostOk {{ code: {:?}, port: {}, easytier: .., profiles: {:?} }}", room.code, port, profiles)
+            AppState::HostOk { room, port, profiles, invite_only, .. } => {
+                write!(
+                    f, "AppState::HostOk {{ code: {:?}, port: {}, easytier: .., profiles: {:?}, invite_only: {} }}",
+                    room.code, port, profiles, invite_only
+                )
             }
             AppState::GuestConnecting { room } => {
                 write!(f, "AppState::GuestConnecting {{ code: {:?} }}", room.code)
@@ -65,10 +120,10 @@ impl Debug for AppState {
             AppState::GuestStarting { room, difficulty, .. } => {
                 write!(f, "AppState::GuestStarting {{ code: {:?}, difficulty: {:?}, easytier: .. }}", room.code, difficulty)
             }
-            AppState::GuestOk { room, server, profiles, .. } => {
+            AppState::GuestOk { room, server, profiles, unsupported, .. } => {
                 write!(
-                    f, "AppState::GuestOk {{ code: {:?}, server_port: {}, easytier: .., profiles: {:?} }}",
-                    room.code, server.port, profiles
+                    f, "AppState::GuestOk {{ code: {:?}, server_port: {}, easytier: .., profiles: {:?}, unsupported: {:?} }}",
+                    room.code, server.port, profiles, unsupported
                 )
             }
             AppState::Exception { kind } => {
@@ -86,6 +141,13 @@ pub enum ExceptionType {
     HostEasytierCrash,
     PingServerRst,
     ScaffoldingInvalidResponse,
+    InviteRequired,
+    InviteRejected,
+    RoomClosed,
+    Kicked,
+    Banned,
+    JoinDenied,
+    RoomFull,
 }
 
 pub struct AppStateContainer {
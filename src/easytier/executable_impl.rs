@@ -255,12 +255,20 @@ impl EasyTier {
     }
 
     pub fn add_port_forward(&mut self, forwards: &[PortForward]) -> bool {
+        self.port_forward("add", forwards)
+    }
+
+    pub fn remove_port_forward(&mut self, forwards: &[PortForward]) -> bool {
+        self.port_forward("remove", forwards)
+    }
+
+    fn port_forward(&mut self, command: &str, forwards: &[PortForward]) -> bool {
         let mut processes: Vec<(&PortForward, Option<Child>)> = forwards.iter().map(|forward| (forward, None)).collect();
 
         for time in 0..3 {
             for (PortForward { local, remote, proto }, process_holder) in processes.iter_mut() {
                 let mut process = match self.start_cli().args([
-                    "-p", &format!("127.0.0.1:{}", self.rpc), "port-forward", "add",
+                    "-p", &format!("127.0.0.1:{}", self.rpc), "port-forward", command,
                     proto.name(), &local.to_string(), &remote.to_string(),
                 ]).spawn() {
                     Ok(v) => v,
@@ -290,7 +298,7 @@ impl EasyTier {
         }
 
         if !processes.is_empty() {
-            let mut msg = "Cannot adding port-forward rules: ".to_string();
+            let mut msg = format!("Cannot {} port-forward rules: ", command);
             for (i, (PortForward { local, remote, proto }, _)) in processes.iter().enumerate() {
                 write!(&mut msg, "{} -> {} ({})", local, remote, proto.name()).unwrap();
                 if i != processes.len() - 1 {
@@ -303,6 +311,27 @@ impl EasyTier {
         true
     }
 
+    pub fn set_whitelist(&mut self, tcp: &[u16], udp: &[u16]) -> bool {
+        for (command, ports) in [("set-tcp", tcp), ("set-udp", udp)] {
+            let ports = ports.iter().map(u16::to_string).collect::<Vec<_>>().join(",");
+            match self.start_cli().args(["-p", &format!("127.0.0.1:{}", self.rpc), "whitelist", command, &ports]).output() {
+                Ok(output) if output.status.success() => {}
+                Ok(output) => {
+                    logging!(
+                        "EasyTier CLI", "Cannot set whitelist by {} {}: {}",
+                        command, ports, String::from_utf8_lossy(&output.stderr)
+                    );
+                    return false;
+                }
+                Err(e) => {
+                    logging!("EasyTier CLI", "Cannot spawn easytier cli instance: {:?}", e);
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
     fn start_cli(&self) -> Command {
         let mut command = Command::new(FACTORY.cli.as_path());
         command.current_dir(env::temp_dir())
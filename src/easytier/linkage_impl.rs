@@ -279,6 +279,21 @@ impl EasyTier {
     pub fn add_port_forward(
         &mut self,
         forwards: &[PortForward],
+    ) -> bool {
+        self.patch_port_forwards(forwards, ConfigPatchAction::Add)
+    }
+
+    pub fn remove_port_forward(
+        &mut self,
+        forwards: &[PortForward],
+    ) -> bool {
+        self.patch_port_forwards(forwards, ConfigPatchAction::Remove)
+    }
+
+    fn patch_port_forwards(
+        &mut self,
+        forwards: &[PortForward],
+        action: ConfigPatchAction,
     ) -> bool {
         if let Some(EasyTierHolder { instance, runtime, .. }) = self.0.as_ref() {
             let service = instance.get_api_service().unwrap();
@@ -286,7 +301,7 @@ impl EasyTier {
                 .patch_config(BaseController::default(), PatchConfigRequest {
                     patch: Some(InstanceConfigPatch {
                         port_forwards: forwards.iter().map(|forward| PortForwardPatch {
-                            action: ConfigPatchAction::Add as i32,
+                            action: action as i32,
                             cfg: Some(PortForwardConfigPb {
                                 bind_addr: Some(forward.local.into()),
                                 dst_addr: Some(forward.remote.into()),
@@ -304,13 +319,19 @@ impl EasyTier {
             return match runtime.block_on(task) {
                 Ok(_) => true,
                 Err(e) => {
-                    logging!("EasyTier", "Cannot adding port-forward rules: {:?}", e);
+                    logging!("EasyTier", "Cannot patch port-forward rules: {:?}", e);
                     false
                 }
             };
         }
         return false;
     }
+
+    // TODO: Patch whitelists through the config service once it's exposed by the linked EasyTier.
+    pub fn set_whitelist(&mut self, tcp: &[u16], udp: &[u16]) -> bool {
+        logging!("EasyTier", "Cannot set whitelist to tcp={:?}, udp={:?}: Unsupported on this platform.", tcp, udp);
+        false
+    }
 }
 
 impl Drop for EasyTier {
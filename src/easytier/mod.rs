@@ -77,4 +77,13 @@ impl EasyTier {
     pub fn add_port_forward(&mut self, forwards: &[PortForward]) -> bool {
         self.0.add_port_forward(forwards)
     }
+
+    pub fn remove_port_forward(&mut self, forwards: &[PortForward]) -> bool {
+        self.0.remove_port_forward(forwards)
+    }
+
+    /// Replace the TCP and UDP whitelists given by arguments when EasyTier was created.
+    pub fn set_whitelist(&mut self, tcp: &[u16], udp: &[u16]) -> bool {
+        self.0.set_whitelist(tcp, udp)
+    }
 }
\ No newline at end of file
@@ -36,15 +36,17 @@ macro_rules! try_jvm {
     }};
 }
 
-use crate::controller::{Room, RoomKind};
+use crate::controller::{GuestingError, PacketResponse, RegistryError, Room, RoomCodeError, RoomKind};
 use crate::once_cell::OnceCell;
+use crate::scaffolding::limits::ServerLimits;
 use chrono::{FixedOffset, TimeZone, Utc};
 use jni::signature::{Primitive, ReturnType};
 use jni::sys::JNI_VERSION_1_6;
-use jni::{objects::{JClass, JString}, sys::{jboolean, jint, jlong, jshort, jsize, jvalue, JNI_FALSE, JNI_TRUE}, JNIEnv, JavaVM, NativeMethod};
+use jni::{objects::{JByteArray, JClass, JObject, JString, JValue}, sys::{jboolean, jint, jlong, jshort, jsize, jvalue, JNI_FALSE, JNI_TRUE}, JNIEnv, JavaVM, NativeMethod};
 use libc::{c_char, c_int};
 use std::ffi::c_void;
 use std::fs::File;
+use std::io;
 use std::io::Write;
 use std::os::fd::FromRawFd;
 use std::path::PathBuf;
@@ -96,6 +98,9 @@ lazy_static::lazy_static! {
 }
 
 static MACHINE_ID_FILE: OnceCell<std::path::PathBuf> = OnceCell::new();
+static SAVED_ROOMS_FILE: OnceCell<std::path::PathBuf> = OnceCell::new();
+static INVITE_KEY_FILE: OnceCell<std::path::PathBuf> = OnceCell::new();
+static BANNED_PLAYERS_FILE: OnceCell<std::path::PathBuf> = OnceCell::new();
 static LOGGING_FD: Mutex<Option<std::fs::File>> = Mutex::new(None);
 static VPN_SERVICE_CFG: Mutex<Option<crate::easytier::EasyTierTunRequest>> = Mutex::new(None);
 
@@ -139,9 +144,22 @@ extern "system" fn JNI_OnLoad(vm: JavaVM, _: *mut c_void) -> jint {
             of!["start0", "(Ljava/lang/String;I)I", jni_start],
             of!["getState0", "()Ljava/lang/String;", jni_get_state],
             of!["setWaiting0", "()V", jni_set_waiting],
-            of!["setScanning0", "(Ljava/lang/String;Ljava/lang/String;)V", jni_set_scanning],
-            of!["setGuesting0", "(Ljava/lang/String;Ljava/lang/String;)Z", jni_set_guesting],
+            of!["setScanning0", "(Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;)V", jni_set_scanning],
+            of!["setScanningBedrock0", "(Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;)V", jni_set_scanning_bedrock],
+            of!["setHosting0", "(Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;)Z", jni_set_hosting],
+            of!["setGuesting0", "(Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;Ljava/lang/String;)Z", jni_set_guesting],
+            of!["createInvite0", "(J)Ljava/lang/String;", jni_create_invite],
+            of!["setInviteOnly0", "(Z)Z", jni_set_invite_only],
+            of!["setApproval0", "(Z)Z", jni_set_approval],
+            of!["approveJoin0", "(Ljava/lang/String;)Z", jni_approve_join],
+            of!["denyJoin0", "(Ljava/lang/String;)Z", jni_deny_join],
+            of!["kick0", "(Ljava/lang/String;)Z", jni_kick],
+            of!["ban0", "(Ljava/lang/String;)Z", jni_ban],
             of!["verifyRoomCode0", "(Ljava/lang/String;)I", jni_verify_room_code],
+            of!["checkRoomCode0", "(Ljava/lang/String;)Ljava/lang/String;", jni_check_room_code],
+            of!["registerHandler0", "(Ljava/lang/String;Ljava/lang/String;Ljava/lang/Object;)I", jni_register_handler],
+            of!["unregisterHandler0", "(Ljava/lang/String;Ljava/lang/String;)Z", jni_unregister_handler],
+            of!["setServerLimits0", "(Ljava/lang/String;)Z", jni_set_server_limits],
             of!["getMetadata0", "()Ljava/lang/String;", jni_get_metadata],
             of!["prepareExportLogs0", "()J", jni_prepare_export_logs],
             of!["finishExportLogs0", "(J)V", jni_finish_export_logs],
@@ -194,7 +212,10 @@ extern "system" fn jni_start<'l>(jenv: JNIEnv<'l>, clazz: JClass<'l>, dir: JStri
     let clazz = jenv.new_global_ref(clazz).unwrap();
 
     let dir: String = parse_jstring(&jenv, &dir).unwrap();
-    MACHINE_ID_FILE.set(PathBuf::from(dir).join("machine-id"));
+    MACHINE_ID_FILE.set(PathBuf::from(&dir).join("machine-id"));
+    SAVED_ROOMS_FILE.set(PathBuf::from(&dir).join("saved-rooms.json"));
+    INVITE_KEY_FILE.set(PathBuf::from(&dir).join("invite-key"));
+    BANNED_PLAYERS_FILE.set(PathBuf::from(&dir).join("banned-players.json"));
 
     thread::spawn(move || {
         let mut jenv = jvm.attach_current_thread_as_daemon().unwrap();
@@ -277,20 +298,79 @@ extern "system" fn jni_set_waiting<'l>(jenv: JNIEnv<'l>, _: JClass<'l>) {
     }
 }
 
-extern "system" fn jni_set_scanning<'l>(jenv: JNIEnv<'l>, _: JClass<'l>, room: JString<'l>, player: JString<'l>) {
+extern "system" fn jni_set_scanning<'l>(jenv: JNIEnv<'l>, _: JClass<'l>, room: JString<'l>, passphrase: JString<'l>, player: JString<'l>) {
     try_jvm! { |jenv|
         let room = parse_jstring(&jenv, &room);
+        let passphrase = parse_jstring(&jenv, &passphrase);
         let player = parse_jstring(&jenv, &player);
-        controller::set_scanning(room, player, vec![]);
+        controller::set_scanning(room, passphrase, player, vec![], None, None);
     }
 }
 
-extern "system" fn jni_set_guesting<'l>(jenv: JNIEnv<'l>, _: JClass<'l>, room: JString<'l>, player: JString<'l>) -> jboolean {
+extern "system" fn jni_set_scanning_bedrock<'l>(jenv: JNIEnv<'l>, _: JClass<'l>, room: JString<'l>, passphrase: JString<'l>, player: JString<'l>) {
+    try_jvm! { |jenv|
+        let room = parse_jstring(&jenv, &room);
+        let passphrase = parse_jstring(&jenv, &passphrase);
+        let player = parse_jstring(&jenv, &player);
+        controller::set_scanning_bedrock(room, passphrase, player, vec![], None);
+    }
+}
+
+extern "system" fn jni_set_hosting<'l>(
+    jenv: JNIEnv<'l>, _: JClass<'l>, server: JString<'l>, room: JString<'l>, passphrase: JString<'l>, player: JString<'l>,
+) -> jboolean {
+    try_jvm! { |jenv|
+        let server = parse_jstring(&jenv, &server).expect("'server' must not be NULL.");
+        let room = parse_jstring(&jenv, &room);
+        let passphrase = parse_jstring(&jenv, &passphrase);
+        let player = parse_jstring(&jenv, &player);
+
+        match server.parse() {
+            Ok(server) if controller::set_hosting(server, room, passphrase, player, vec![], None, None) => JNI_TRUE,
+            Ok(_) => JNI_FALSE,
+            Err(e) => {
+                logging!("Core", "Cannot share server at {:?}: {:?}", server, e);
+                JNI_FALSE
+            }
+        }
+    }
+}
+
+extern "system" fn jni_set_guesting<'l>(
+    jenv: JNIEnv<'l>, _: JClass<'l>, room: JString<'l>, passphrase: JString<'l>, invite: JString<'l>, player: JString<'l>,
+) -> jboolean {
     try_jvm! { |jenv|
         let room = parse_jstring(&jenv, &room).expect("'room' must not be NULL.");
+        let passphrase = parse_jstring(&jenv, &passphrase);
+        let invite = parse_jstring(&jenv, &invite);
         let player = parse_jstring(&jenv, &player);
 
-        if let Some(room) = Room::from(&room) && controller::set_guesting(room, player, vec![]) {
+        match controller::set_guesting(&room, passphrase.as_deref(), invite, player, vec![], None, None) {
+            Ok(()) => JNI_TRUE,
+            Err(e) => {
+                logging!("Core", "Cannot join room {:?}: {:?}", room, e);
+                JNI_FALSE
+            }
+        }
+    }
+}
+
+extern "system" fn jni_create_invite<'l>(jenv: JNIEnv<'l>, _: JClass<'l>, ttl: jlong) -> JString<'l> {
+    try_jvm! { |jenv|
+        match controller::create_invite(Duration::from_secs(ttl.max(0) as u64)) {
+            Some(invite) => jenv.new_string(serde_json::to_string(&serde_json::json!({
+                "invite": invite.invite,
+                "link": invite.link,
+                "expiry": invite.expiry_millis(),
+            })).unwrap()).unwrap(),
+            None => JString::from(JObject::null()),
+        }
+    }
+}
+
+extern "system" fn jni_set_invite_only<'l>(jenv: JNIEnv<'l>, _: JClass<'l>, enabled: jboolean) -> jboolean {
+    try_jvm! { |jenv|
+        if controller::set_invite_only(enabled != JNI_FALSE) {
             JNI_TRUE
         } else {
             JNI_FALSE
@@ -298,15 +378,183 @@ extern "system" fn jni_set_guesting<'l>(jenv: JNIEnv<'l>, _: JClass<'l>, room: J
     }
 }
 
+extern "system" fn jni_set_approval<'l>(jenv: JNIEnv<'l>, _: JClass<'l>, enabled: jboolean) -> jboolean {
+    try_jvm! { |jenv|
+        if controller::set_approval(enabled != JNI_FALSE) {
+            JNI_TRUE
+        } else {
+            JNI_FALSE
+        }
+    }
+}
+
+extern "system" fn jni_approve_join<'l>(jenv: JNIEnv<'l>, _: JClass<'l>, machine_id: JString<'l>) -> jboolean {
+    try_jvm! { |jenv|
+        let machine_id = parse_jstring(&jenv, &machine_id).expect("'machine_id' must not be NULL.");
+        if controller::approve_join(&machine_id) {
+            JNI_TRUE
+        } else {
+            JNI_FALSE
+        }
+    }
+}
+
+extern "system" fn jni_deny_join<'l>(jenv: JNIEnv<'l>, _: JClass<'l>, machine_id: JString<'l>) -> jboolean {
+    try_jvm! { |jenv|
+        let machine_id = parse_jstring(&jenv, &machine_id).expect("'machine_id' must not be NULL.");
+        if controller::deny_join(&machine_id) {
+            JNI_TRUE
+        } else {
+            JNI_FALSE
+        }
+    }
+}
+
+extern "system" fn jni_kick<'l>(jenv: JNIEnv<'l>, _: JClass<'l>, machine_id: JString<'l>) -> jboolean {
+    try_jvm! { |jenv|
+        let machine_id = parse_jstring(&jenv, &machine_id).expect("'machine_id' must not be NULL.");
+        match controller::kick(&machine_id) {
+            Ok(()) => JNI_TRUE,
+            Err(e) => {
+                logging!("Moderation", "Cannot kick {}: {:?}", machine_id, e);
+                JNI_FALSE
+            }
+        }
+    }
+}
+
+extern "system" fn jni_ban<'l>(jenv: JNIEnv<'l>, _: JClass<'l>, machine_id: JString<'l>) -> jboolean {
+    try_jvm! { |jenv|
+        let machine_id = parse_jstring(&jenv, &machine_id).expect("'machine_id' must not be NULL.");
+        match controller::ban(&machine_id) {
+            Ok(()) => JNI_TRUE,
+            Err(e) => {
+                logging!("Moderation", "Cannot ban {}: {:?}", machine_id, e);
+                JNI_FALSE
+            }
+        }
+    }
+}
+
 extern "system" fn jni_verify_room_code<'l>(jenv: JNIEnv<'l>, _: JClass<'l>, room: JString<'l>) -> jint {
     try_jvm! { |jenv|
         let room = parse_jstring(&jenv, &room).expect("'room' must not be NULL.");
 
-        match Room::from(&room) {
-            Some(Room { kind, .. }) => match kind {
-                RoomKind::Scaffolding { .. } => 3
+        match Room::from(&room, None) {
+            Ok(Room { kind, .. }) => match kind {
+                RoomKind::Scaffolding { .. } => 3,
+                RoomKind::Protected { .. } => unreachable!(),
             },
-            None => -1
+            Err(RoomCodeError::MissingPassphrase) => 4,
+            Err(_) => -1
+        }
+    }
+}
+
+extern "system" fn jni_check_room_code<'l>(jenv: JNIEnv<'l>, _: JClass<'l>, room: JString<'l>) -> JString<'l> {
+    try_jvm! { |jenv|
+        let room = parse_jstring(&jenv, &room).expect("'room' must not be NULL.");
+
+        match Room::from(&room, None) {
+            Ok(_) | Err(RoomCodeError::MissingPassphrase) => JString::from(JObject::null()),
+            Err(e) => jenv.new_string(serde_json::to_string(&GuestingError::InvalidRoomCode(e).to_json()).unwrap()).unwrap(),
+        }
+    }
+}
+
+extern "system" fn jni_register_handler<'l>(
+    jenv: JNIEnv<'l>, _: JClass<'l>, namespace: JString<'l>, path: JString<'l>, handler: JObject<'l>,
+) -> jint {
+    try_jvm! { |jenv|
+        let namespace = parse_jstring(&jenv, &namespace).expect("'namespace' must not be NULL.");
+        let path = parse_jstring(&jenv, &path).expect("'path' must not be NULL.");
+        let jvm = jenv.get_java_vm().unwrap();
+        let handler = jenv.new_global_ref(handler).unwrap();
+
+        match controller::HANDLERS.register(&namespace, &path, move |request, mut response| {
+            let mut jenv = jvm.attach_current_thread_as_daemon().map_err(io::Error::other)?;
+
+            // Scaffolding threads are attached permanently, so local references must be freed by a local frame.
+            match jenv.with_local_frame(8, |jenv| -> jni::errors::Result<Vec<u8>> {
+                let request = jenv.byte_array_from_slice(request)?;
+                let data = jenv.call_method(&handler, "handle", "([B)[B", &[JValue::Object(&request)])?.l()?;
+                if data.is_null() {
+                    Ok(vec![])
+                } else {
+                    jenv.convert_byte_array(JByteArray::from(data))
+                }
+            }) {
+                Ok(data) => {
+                    response.extend_from_slice(&data);
+                    PacketResponse::ok(response)
+                }
+                Err(jni::errors::Error::JavaException) => {
+                    let _ = jenv.exception_describe();
+                    let _ = jenv.exception_clear();
+                    response.extend_from_slice("An exception is thrown by handler.".as_bytes());
+                    PacketResponse::fail(255, response)
+                }
+                Err(e) => Err(io::Error::other(e)),
+            }
+        }) {
+            Ok(()) => 0,
+            Err(RegistryError::InvalidName) => 1,
+            Err(RegistryError::ReservedNamespace) => 2,
+            Err(RegistryError::Conflict) => 3,
+        }
+    }
+}
+
+extern "system" fn jni_unregister_handler<'l>(jenv: JNIEnv<'l>, _: JClass<'l>, namespace: JString<'l>, path: JString<'l>) -> jboolean {
+    try_jvm! { |jenv|
+        let namespace = parse_jstring(&jenv, &namespace).expect("'namespace' must not be NULL.");
+        let path = parse_jstring(&jenv, &path).expect("'path' must not be NULL.");
+
+        if controller::HANDLERS.unregister(&namespace, &path) {
+            JNI_TRUE
+        } else {
+            JNI_FALSE
+        }
+    }
+}
+
+// Limits are given as JSON, whose absent fields are left as default. It must be called before start0.
+extern "system" fn jni_set_server_limits<'l>(jenv: JNIEnv<'l>, _: JClass<'l>, limits: JString<'l>) -> jboolean {
+    try_jvm! { |jenv|
+        let limits = parse_jstring(&jenv, &limits).expect("'limits' must not be NULL.");
+        let Ok(value) = serde_json::from_str::<serde_json::Value>(&limits) else {
+            logging!("Core", "Cannot parse server limits: {}", limits);
+            return JNI_FALSE;
+        };
+        let field = |name: &str| value.get(name).and_then(serde_json::Value::as_u64);
+
+        let mut limits = ServerLimits::default();
+        if let Some(max_frame_size) = field("max_frame_size") {
+            limits.max_frame_size = max_frame_size as usize;
+        }
+        if let Some(max_connections) = field("max_connections") {
+            limits.max_connections = max_connections as usize;
+        }
+        if let Some(requests_per_second) = field("requests_per_second") {
+            limits.requests_per_second = requests_per_second.min(u32::MAX as u64) as u32;
+        }
+        if let Some(idle_timeout) = field("idle_timeout_millis") {
+            limits.idle_timeout = Duration::from_millis(idle_timeout);
+        }
+        if let Some(max_in_flight) = field("max_in_flight") {
+            limits.max_in_flight = max_in_flight as usize;
+        }
+        if let Some(max_workers) = field("max_workers") {
+            limits.max_workers = max_workers as usize;
+        }
+        if let Some(allow_unauthenticated) = value.get("allow_unauthenticated").and_then(serde_json::Value::as_bool) {
+            limits.allow_unauthenticated = allow_unauthenticated;
+        }
+
+        if controller::set_server_limits(limits) {
+            JNI_TRUE
+        } else {
+            JNI_FALSE
         }
     }
 }
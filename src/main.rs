@@ -116,6 +116,9 @@ lazy_static! {
         path
     };
     static ref MACHINE_ID_FILE: std::path::PathBuf = FILE_ROOT.join("machine-id");
+    static ref SAVED_ROOMS_FILE: std::path::PathBuf = FILE_ROOT.join("saved-rooms.json");
+    static ref INVITE_KEY_FILE: std::path::PathBuf = FILE_ROOT.join("invite-key");
+    static ref BANNED_PLAYERS_FILE: std::path::PathBuf = FILE_ROOT.join("banned-players.json");
     static ref WORKING_DIR: std::path::PathBuf = {
         use chrono::{Datelike, Timelike};
         let now = chrono::Local::now();
@@ -143,6 +146,9 @@ enum Mode {
     HMCL {
         file: String,
     },
+    Join {
+        link: controller::JoinLink,
+    },
 }
 
 #[rocket::main]
@@ -224,10 +230,33 @@ async fn main() {
         logging!("UI", "Unknown arguments: {}", arguments.join(", "));
     }
 
+    fn main_join(link: &str) -> Mode {
+        match controller::JoinLink::parse(link) {
+            Ok(link) => Mode::Join { link },
+            Err(e) => {
+                logging!("UI", "Cannot parse join link {:?}: {:?}", link, e);
+                Mode::General
+            }
+        }
+    }
+
     let arguments = env::args().skip(1).collect::<Vec<_>>();
+    if arguments.first().is_some_and(|argument| argument == "scaffolding") {
+        let success = match &arguments[1..] {
+            [command] if command == "conformance" => controller::scaffolding::conformance::run(),
+            arguments => scaffolding::debug::main(arguments),
+        };
+        if !success {
+            std::process::exit(1);
+        }
+        return;
+    }
+
     match arguments.len() {
         0 => main_general(Mode::General).await,
         1 => match arguments[0].as_str() {
+            // Deep-links opened by the operating system are passed without '--join'.
+            link if link.starts_with("terracotta://") => main_general(main_join(link)).await,
             #[cfg(target_os = "macos")]
             "--daemon" => main_daemon().await,
             "--help" => {
@@ -236,6 +265,8 @@ async fn main() {
                 println!("Options:");
                 println!("  --help: Print this help message");
                 println!("  --hmcl: [HMCL] For HMCL only.");
+                println!("  --join <LINK>: Join a room by a room code or a 'terracotta://join/' link.");
+                println!("  scaffolding <COMMAND>: Debug Scaffolding protocol. Run 'terracotta scaffolding' for details.");
                 #[cfg(target_os = "windows")]
                 println!("  --hmcl2: [INTERNAL] For HMCL only.");
                 #[cfg(target_os = "macos")]
@@ -265,6 +296,7 @@ async fn main() {
                     }
                 }
             }
+            "--join" => main_general(main_join(&arguments[1])).await,
             #[cfg(target_family = "windows")]
             "--hmcl2" => {
                 main_general(Mode::HMCL {
@@ -362,7 +394,7 @@ cfg_if::cfg_if! {
             }
 
             if let Some(error) = error {
-                if mode == Mode::General {
+                if matches!(mode, Mode::General | Mode::Join { .. }) {
                     let _ = native_dialog::DialogBuilder::message()
                         .set_level(native_dialog::MessageLevel::Error)
                         .set_title("Terracotta | 陶瓦联机")
@@ -447,6 +479,12 @@ async fn main_single(state: Option<Lock>, mode: Mode) {
                     let _ = open::that(format!("http://127.0.0.1:{}/", port));
                 }
                 Mode::HMCL { file } => output_port(port, file),
+                Mode::Join { link } => {
+                    if let Err(e) = controller::set_guesting(&link.room, link.passphrase.as_deref(), link.invite, link.player, vec![], None, None) {
+                        logging!("UI", "Cannot join room {}: {:?}", link.room, e);
+                    }
+                    let _ = open::that(format!("http://127.0.0.1:{}/", port));
+                }
             }
         }
     });
@@ -480,6 +518,40 @@ async fn main_secondary(port: u16, mode: Mode) {
         #[cfg(target_os = "macos")]
         Mode::Daemon => assert!(false),
         Mode::HMCL { file } => output_port(port, file),
+        Mode::Join { link } => {
+            let mut query = vec![("room", link.room.clone())];
+            if let Some(passphrase) = link.passphrase {
+                query.push(("passphrase", passphrase));
+            }
+            if let Some(invite) = link.invite {
+                query.push(("invite", invite));
+            }
+            if let Some(player) = link.player {
+                query.push(("player", player));
+            }
+
+            match reqwest::Client::new()
+                .get(format!("http://127.0.0.1:{}/state/guesting", port))
+                .query(&query)
+                .send().await
+            {
+                Ok(response) if response.status().is_success() => {}
+                Ok(response) => {
+                    logging!("UI", "Cannot join room {}: {}", link.room, response.text().await.unwrap_or_default());
+                }
+                Err(e) => {
+                    logging!("UI", "Cannot join room {}: {:?}", link.room, e);
+                }
+            }
+
+            cfg_if::cfg_if! {
+                if #[cfg(target_os = "macos")] {
+                    ui_macos::open(format!("http://127.0.0.1:{}/", port));
+                } else {
+                    let _ = open::that(format!("http://127.0.0.1:{}/", port));
+                }
+            }
+        }
     }
 }
 
@@ -571,7 +643,7 @@ fn cleanup() {
                         .path()
                         .file_name()
                         .and_then(|v| v.to_str())
-                        .is_none_or(|v| v != "terracotta.lock")
+                        .is_none_or(|v| !["terracotta.lock", "saved-rooms.json", "invite-key", "banned-players.json"].contains(&v))
                     && let Ok(metadata) = file.metadata()
                     && let Ok(file_type) = file.file_type()
                     && let Ok(time) = metadata.created()
@@ -594,3 +666,64 @@ fn cleanup() {
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    /// Map a Java parameter or return type of ffi/TerracottaAndroidAPI.java to a JNI type descriptor.
+    fn descriptor(java: &str) -> String {
+        match java {
+            "void" => "V".to_string(),
+            "boolean" => "Z".to_string(),
+            "int" => "I".to_string(),
+            "long" => "J".to_string(),
+            "String" => "Ljava/lang/String;".to_string(),
+            "Object" => "Ljava/lang/Object;".to_string(),
+            _ => panic!("Unsupported Java type in native declarations: {}", java),
+        }
+    }
+
+    /// Every native method registered in JNI_OnLoad must be declared by the Java API with the same signature,
+    /// otherwise RegisterNatives fails and Android cannot load the library.
+    #[test]
+    fn android_natives() {
+        let registered: Vec<(String, String)> = include_str!("lib.rs")
+            .split("of![\"")
+            .skip(1)
+            .map(|entry| {
+                let mut parts = entry.split('"');
+                let name = parts.next().unwrap().to_string();
+                let signature = parts.nth(1).unwrap().to_string();
+                (name, signature)
+            })
+            .collect();
+
+        let declared: Vec<(String, String)> = include_str!("../ffi/TerracottaAndroidAPI.java")
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("private static native "))
+            .map(|declaration| {
+                let (result, rest) = declaration.split_once(' ').unwrap();
+                let (name, parameters) = rest.trim_end_matches(");").split_once('(').unwrap();
+                let parameters: String = parameters
+                    .split(',')
+                    .filter(|parameter| !parameter.trim().is_empty())
+                    .map(|parameter| descriptor(parameter.split_whitespace().next().unwrap()))
+                    .collect();
+                (name.to_string(), format!("({}){}", parameters, descriptor(result)))
+            })
+            .collect();
+
+        assert!(!registered.is_empty());
+        for (name, signature) in &registered {
+            assert!(
+                declared.contains(&(name.clone(), signature.clone())),
+                "{}{} is registered, but isn't declared in TerracottaAndroidAPI.java.", name, signature
+            );
+        }
+        for (name, signature) in &declared {
+            assert!(
+                registered.contains(&(name.clone(), signature.clone())),
+                "{}{} is declared in TerracottaAndroidAPI.java, but isn't registered.", name, signature
+            );
+        }
+    }
+}
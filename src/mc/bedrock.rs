@@ -0,0 +1,134 @@
+use crate::mc::status::{self, ServerStatus};
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, UdpSocket};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Ports on which Bedrock worlds opened to LAN are reached, and to which clients broadcast RakNet pings.
+pub const PORT_V4: u16 = 19132;
+pub const PORT_V6: u16 = 19133;
+
+// Offline message ID of RakNet, which is carried by unconnected pings and pongs.
+const MAGIC: [u8; 16] = [0x00, 0xFF, 0xFF, 0x00, 0xFE, 0xFE, 0xFE, 0xFE, 0xFD, 0xFD, 0xFD, 0xFD, 0x12, 0x34, 0x56, 0x78];
+const UNCONNECTED_PING: u8 = 0x01;
+const UNCONNECTED_PING_OPEN_CONNECTIONS: u8 = 0x02;
+const UNCONNECTED_PONG: u8 = 0x1C;
+
+/// Server info answered by a Bedrock server to RakNet unconnected pings, which is shown in the Friends tab.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pong {
+    pub edition: String,
+    pub motd: String,
+    pub protocol: i32,
+    pub version: String,
+    pub online: u32,
+    pub max: u32,
+    pub guid: u64,
+    /// Name of the world, shown below MOTD.
+    pub level: String,
+    pub game_mode: String,
+    pub game_mode_id: String,
+    pub port_v4: Option<u16>,
+    pub port_v6: Option<u16>,
+}
+
+impl Pong {
+    fn parse(guid: u64, text: &str) -> Option<Pong> {
+        let fields: Vec<&str> = text.split(';').collect();
+        let field = |index: usize| fields.get(index).copied().unwrap_or_default().to_string();
+        let port = |index: usize| fields.get(index).and_then(|port| port.parse().ok());
+
+        Some(Pong {
+            edition: field(0),
+            motd: field(1),
+            protocol: fields.get(2)?.parse().ok()?,
+            version: field(3),
+            online: fields.get(4)?.parse().ok()?,
+            max: fields.get(5)?.parse().ok()?,
+            guid,
+            level: field(7),
+            game_mode: field(8),
+            game_mode_id: field(9),
+            port_v4: port(10),
+            port_v6: port(11),
+        })
+    }
+
+    fn encode(&self) -> String {
+        // Fields are separated by semicolons, which cannot be escaped.
+        let text = |value: &str| value.replace(';', " ");
+        let port = |port: Option<u16>| port.map(|port| port.to_string()).unwrap_or_default();
+
+        format!(
+            "{};{};{};{};{};{};{};{};{};{};{};{};",
+            text(&self.edition), text(&self.motd), self.protocol, text(&self.version), self.online, self.max, self.guid,
+            text(&self.level), text(&self.game_mode), text(&self.game_mode_id), port(self.port_v4), port(self.port_v6),
+        )
+    }
+
+    pub fn to_status(&self) -> ServerStatus {
+        ServerStatus {
+            version: self.version.clone(),
+            protocol: Some(self.protocol),
+            online: self.online,
+            max: self.max,
+            description: status::strip_formatting(&self.motd),
+            favicon: None,
+        }
+    }
+}
+
+/// Send a RakNet unconnected ping to a Bedrock server, and wait for its pong.
+pub fn ping(address: SocketAddr, timeout: Duration) -> io::Result<Pong> {
+    let socket = match address {
+        SocketAddr::V4(_) => UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?,
+        SocketAddr::V6(_) => UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0))?,
+    };
+    socket.connect(address)?;
+    socket.set_read_timeout(Some(timeout))?;
+
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0);
+    let mut request = vec![UNCONNECTED_PING];
+    request.extend_from_slice(&time.to_be_bytes());
+    request.extend_from_slice(&MAGIC);
+    // GUID of client, which isn't checked by servers.
+    request.extend_from_slice(&0u64.to_be_bytes());
+    socket.send(&request)?;
+
+    let mut buf = [0u8; 1500];
+    let length = socket.recv(&mut buf)?;
+    let data = &buf[..length];
+    if length < 35 || data[0] != UNCONNECTED_PONG || data[17..33] != MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected RakNet packet."));
+    }
+    let guid = u64::from_be_bytes(data[9..17].try_into().unwrap());
+    let size = u16::from_be_bytes([data[33], data[34]]) as usize;
+    data.get(35..35 + size)
+        .and_then(|text| Pong::parse(guid, &String::from_utf8_lossy(text)))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Invalid RakNet pong."))
+}
+
+/// Find a Bedrock world opened to LAN on this PC, which listens on the default ports unless they are occupied.
+pub fn discover() -> Option<Pong> {
+    [SocketAddrV4::new(Ipv4Addr::LOCALHOST, PORT_V4).into(), SocketAddrV6::new(Ipv6Addr::LOCALHOST, PORT_V6, 0, 0).into()]
+        .into_iter()
+        .find_map(|address| ping(address, Duration::from_millis(500)).ok())
+}
+
+/// Parse a RakNet unconnected ping, returning the time which must be echoed by the pong.
+pub fn parse_ping(data: &[u8]) -> Option<u64> {
+    if data.len() < 25 || !matches!(data[0], UNCONNECTED_PING | UNCONNECTED_PING_OPEN_CONNECTIONS) || data[9..25] != MAGIC {
+        return None;
+    }
+    Some(u64::from_be_bytes(data[1..9].try_into().unwrap()))
+}
+
+pub fn encode_pong(time: u64, pong: &Pong) -> Vec<u8> {
+    let text = pong.encode();
+    let mut response = vec![UNCONNECTED_PONG];
+    response.extend_from_slice(&time.to_be_bytes());
+    response.extend_from_slice(&pong.guid.to_be_bytes());
+    response.extend_from_slice(&MAGIC);
+    response.extend_from_slice(&(text.len() as u16).to_be_bytes());
+    response.extend_from_slice(text.as_bytes());
+    response
+}
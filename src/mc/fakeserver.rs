@@ -1,23 +1,48 @@
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use crate::mc::bedrock::{self, Pong};
+use crate::mc::Edition;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, UdpSocket};
 use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
 use std::{io, thread};
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
+
+// Formatting codes appended to every MOTD advertised by FakeServer, which are invisible in Minecraft.
+// Scanners of any Terracotta recognize them, so that rooms joined on this LAN are never shared again.
+const MARKER: &str = "§k§r";
 
 pub struct FakeServer {
     pub port: u16,
+    motd: Arc<Mutex<String>>,
     _holder: Sender<()>,
 }
 
 impl FakeServer {
-    pub fn create(port: u16, motd: &'static str) -> FakeServer {
+    /// Advertise the world at port to Minecraft on this PC. Java Edition discovers it through multicast, while Bedrock
+    /// Edition broadcasts RakNet pings, which are answered with the server info of the world.
+    pub fn create(edition: Edition, port: u16, motd: String) -> FakeServer {
         let (tx, rx) = mpsc::channel::<()>();
-        thread::spawn(move || run(port, motd, rx));
+        let motd = Arc::new(Mutex::new(motd));
+        let motd_cloned = Arc::clone(&motd);
+        match edition {
+            Edition::Java => thread::spawn(move || run(port, motd_cloned, rx)),
+            Edition::Bedrock => thread::spawn(move || run_bedrock(port, motd_cloned, rx)),
+        };
+
+        FakeServer { port, motd, _holder: tx }
+    }
 
-        FakeServer { port, _holder: tx }
+    /// Update the MOTD, which is advertised since the next broadcast or pong.
+    pub fn set_motd(&self, motd: String) {
+        *self.motd.lock().unwrap() = motd;
     }
 }
 
-fn run(port: u16, motd: &'static str, signal: Receiver<()>) {
+/// Whether the MOTD is advertised by FakeServer, including those of older Terracotta.
+pub fn is_fake(motd: &str) -> bool {
+    motd.ends_with(MARKER) || motd == crate::MOTD
+}
+
+fn run(port: u16, motd: Arc<Mutex<String>>, signal: Receiver<()>) {
     lazy_static::lazy_static! {
         static ref ADDR_V4: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(224, 0, 2, 60)), 4445);
         static ref ADDR_V6: SocketAddr = SocketAddr::new(IpAddr::V6(Ipv6Addr::new(0xFF75, 0x230, 0, 0, 0, 0, 0, 0x60)), 4445);
@@ -45,13 +70,13 @@ fn run(port: u16, motd: &'static str, signal: Receiver<()>) {
         .filter_map(|r| r.ok())
         .collect();
 
-    let message: String = format!("[MOTD]{}[/MOTD][AD]{}[/AD]", motd, port);
-
     loop {
         if let Err(mpsc::TryRecvError::Disconnected) = signal.try_recv() {
             return;
         }
 
+        let message: String = format!("[MOTD]{}{}[/MOTD][AD]{}[/AD]", motd.lock().unwrap(), MARKER, port);
+
         for (socket, address) in sockets.iter() {
             let _ = socket.send_to(message.as_bytes(), address);
         }
@@ -59,3 +84,64 @@ fn run(port: u16, motd: &'static str, signal: Receiver<()>) {
         thread::sleep(Duration::from_millis(1500));
     }
 }
+
+fn run_bedrock(port: u16, motd: Arc<Mutex<String>>, signal: Receiver<()>) {
+    let sockets: Vec<UdpSocket> = [
+        SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), bedrock::PORT_V4),
+        SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), bedrock::PORT_V6),
+    ]
+        .into_iter()
+        .filter_map(|address| {
+            let socket = UdpSocket::bind(address).and_then(|socket| {
+                socket.set_broadcast(true)?;
+                socket.set_read_timeout(Some(Duration::from_millis(250)))?;
+                Ok(socket)
+            });
+            socket.inspect_err(|e| {
+                logging!("FakeServer", "Cannot answer RakNet pings at {}: {:?}", address, e);
+            }).ok()
+        })
+        .collect();
+
+    // Server info of host is refreshed through the port-forward, while MOTD and ports are replaced by local ones.
+    let mut cached: Option<(SystemTime, Pong)> = None;
+    let mut buf = [0u8; 1500];
+    loop {
+        if let Err(mpsc::TryRecvError::Disconnected) = signal.try_recv() {
+            return;
+        }
+
+        for socket in sockets.iter() {
+            let Ok((length, client)) = socket.recv_from(&mut buf) else {
+                continue;
+            };
+            let Some(time) = bedrock::parse_ping(&buf[..length]) else {
+                continue;
+            };
+
+            if cached.as_ref().is_none_or(|(time, _)| time.elapsed().is_ok_and(|d| d >= Duration::from_secs(5))) {
+                match bedrock::ping(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port).into(), Duration::from_secs(1)) {
+                    Ok(pong) => cached = Some((SystemTime::now(), pong)),
+                    // Previous server info is kept, and retried after a while.
+                    Err(e) => {
+                        logging!("FakeServer", "Cannot ping Bedrock world of host: {:?}", e);
+                        if let Some((time, _)) = &mut cached {
+                            *time = SystemTime::now();
+                        }
+                    }
+                }
+            }
+            let Some((_, pong)) = &cached else {
+                continue;
+            };
+
+            let pong = Pong {
+                motd: motd.lock().unwrap().clone(),
+                port_v4: Some(port),
+                port_v6: Some(port),
+                ..pong.clone()
+            };
+            let _ = socket.send_to(&bedrock::encode_pong(time, &pong), client);
+        }
+    }
+}
\ No newline at end of file
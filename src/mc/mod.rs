@@ -1,2 +1,16 @@
+use serde::Serialize;
+
+pub mod bedrock;
 pub mod fakeserver;
-pub mod scanning;
\ No newline at end of file
+pub mod mods;
+pub mod relay;
+pub mod scanning;
+pub mod status;
+
+/// Edition of Minecraft, which decides how worlds are discovered and reached.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Edition {
+    Java,
+    Bedrock,
+}
\ No newline at end of file
@@ -0,0 +1,153 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek};
+use std::path::Path;
+use zip::ZipArchive;
+
+/// A mod found in a mods directory. Jars declaring several mods produce an entry for each of them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModEntry {
+    /// Mod ID declared by the jar, or the file name without '.jar' if the jar cannot be recognized.
+    pub id: String,
+    /// Declared version, or an empty string if it's unknown.
+    pub version: String,
+    pub file: String,
+    /// Hex-encoded SHA-256 of the jar.
+    pub sha256: String,
+}
+
+/// A mod installed both on host and guest, whose jars are different.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModMismatch {
+    pub id: String,
+    pub host: ModEntry,
+    pub local: ModEntry,
+}
+
+/// Differences between the mods of host and guest, each sorted by mod ID.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ModDiff {
+    /// Mods installed by host but not by guest.
+    pub missing: Vec<ModEntry>,
+    /// Mods installed by guest but not by host, which are usually fine if they are client-side.
+    pub extra: Vec<ModEntry>,
+    pub different: Vec<ModMismatch>,
+}
+
+/// Build the manifest of all jars directly under directory, sorted by mod ID. Disabled mods and subdirectories are skipped.
+pub fn scan(directory: &Path) -> io::Result<Vec<ModEntry>> {
+    let mut manifest = vec![];
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let Some(file) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if !path.is_file() || !file.to_ascii_lowercase().ends_with(".jar") {
+            continue;
+        }
+
+        match inspect(&path, file) {
+            Ok(entries) => manifest.extend(entries),
+            Err(e) => {
+                logging!("Mods", "Cannot inspect {}: {:?}", file, e);
+            }
+        }
+    }
+
+    manifest.sort_by(|a, b| a.id.cmp(&b.id).then_with(|| a.file.cmp(&b.file)));
+    Ok(manifest)
+}
+
+pub fn diff(host: &[ModEntry], local: &[ModEntry]) -> ModDiff {
+    let (hosts, locals) = (index(host), index(local));
+
+    let mut diff = ModDiff::default();
+    for (id, entry) in hosts.iter() {
+        match locals.get(id) {
+            None => diff.missing.push((*entry).clone()),
+            Some(local) if local.sha256 != entry.sha256 => diff.different.push(ModMismatch {
+                id: id.to_string(),
+                host: (*entry).clone(),
+                local: (*local).clone(),
+            }),
+            Some(_) => {}
+        }
+    }
+    diff.extra.extend(locals.iter().filter(|(id, _)| !hosts.contains_key(*id)).map(|(_, entry)| (*entry).clone()));
+
+    diff.missing.sort_by(|a, b| a.id.cmp(&b.id));
+    diff.extra.sort_by(|a, b| a.id.cmp(&b.id));
+    diff.different.sort_by(|a, b| a.id.cmp(&b.id));
+    diff
+}
+
+// Jars declaring a mod ID already seen are ignored, as only one of them can be loaded.
+fn index(manifest: &[ModEntry]) -> HashMap<&str, &ModEntry> {
+    let mut index = HashMap::new();
+    for entry in manifest {
+        index.entry(entry.id.as_str()).or_insert(entry);
+    }
+    index
+}
+
+fn inspect(path: &Path, file: &str) -> io::Result<Vec<ModEntry>> {
+    let mut jar = File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut jar, &mut hasher)?;
+    let sha256 = hex::encode(hasher.finalize());
+
+    jar.rewind()?;
+    let mut mods = ZipArchive::new(jar).ok().map(|mut archive| read_metadata(&mut archive)).unwrap_or_default();
+    if mods.is_empty() {
+        mods.push((file[..file.len() - ".jar".len()].to_string(), String::new()));
+    }
+
+    Ok(mods.into_iter().map(|(id, version)| ModEntry { id, version, file: file.to_string(), sha256: sha256.clone() }).collect())
+}
+
+/// Read (id, version) of mods declared by Fabric, Quilt, Forge and NeoForge metadata.
+fn read_metadata<R: Read + Seek>(archive: &mut ZipArchive<R>) -> Vec<(String, String)> {
+    let mut read = |name: &str| -> Option<String> {
+        let mut content = String::new();
+        archive.by_name(name).ok()?.read_to_string(&mut content).ok()?;
+        Some(content)
+    };
+
+    if let Some(value) = read("fabric.mod.json").and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+        && let Some(id) = value.get("id").and_then(|id| id.as_str())
+    {
+        return vec![(id.to_string(), value.get("version").and_then(|v| v.as_str()).unwrap_or_default().to_string())];
+    }
+    if let Some(value) = read("quilt.mod.json").and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+        && let Some(loader) = value.get("quilt_loader")
+        && let Some(id) = loader.get("id").and_then(|id| id.as_str())
+    {
+        return vec![(id.to_string(), loader.get("version").and_then(|v| v.as_str()).unwrap_or_default().to_string())];
+    }
+
+    for name in ["META-INF/neoforge.mods.toml", "META-INF/mods.toml"] {
+        let Some(value) = read(name).and_then(|content| toml::from_str::<toml::Table>(&content).ok()) else {
+            continue;
+        };
+        // Forge substitutes ${file.jarVersion} with Implementation-Version of the jar manifest.
+        let jar_version = read("META-INF/MANIFEST.MF").and_then(|manifest| {
+            manifest.lines().find_map(|line| line.strip_prefix("Implementation-Version:").map(|v| v.trim().to_string()))
+        });
+
+        let mods = value.get("mods").and_then(|mods| mods.as_array()).into_iter().flatten().filter_map(|item| {
+            let id = item.get("modId")?.as_str()?.to_string();
+            let version = match item.get("version").and_then(|v| v.as_str()) {
+                Some("${file.jarVersion}") => jar_version.clone().unwrap_or_default(),
+                Some(version) => version.to_string(),
+                None => String::new(),
+            };
+            Some((id, version))
+        }).collect::<Vec<_>>();
+        if !mods.is_empty() {
+            return mods;
+        }
+    }
+    vec![]
+}
@@ -0,0 +1,160 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+// UDP clients silent for this duration are forgotten, along with their upstream sockets.
+const UDP_IDLE: Duration = Duration::from_secs(60);
+
+/// Forwards TCP connections and UDP datagrams received on a local port to a Minecraft server elsewhere, so that it
+/// can be exposed through EasyTier like a LAN world. Stopped once dropped, while established connections are kept.
+pub struct Relay {
+    pub port: u16,
+    pub target: SocketAddr,
+    _holder: (Sender<()>, Sender<()>),
+}
+
+impl Relay {
+    pub fn create(target: SocketAddr) -> io::Result<Relay> {
+        let (listener, socket) = bind()?;
+        let port = listener.local_addr()?.port();
+        logging!("Relay", "Relaying port {} to {}", port, target);
+
+        let (tcp_tx, tcp_rx) = mpsc::channel::<()>();
+        let (udp_tx, udp_rx) = mpsc::channel::<()>();
+        thread::spawn(move || run_tcp(listener, target, tcp_rx));
+        thread::spawn(move || run_udp(socket, target, udp_rx));
+
+        Ok(Relay { port, target, _holder: (tcp_tx, udp_tx) })
+    }
+}
+
+/// Bind TCP and UDP on an identical port, as guests forward both protocols to the same one. Only loopback is bound, as
+/// guests reach the port through EasyTier, and the server shouldn't be exposed to the LAN of host by Terracotta.
+fn bind() -> io::Result<(TcpListener, UdpSocket)> {
+    let mut error = None;
+    for _ in 0..8 {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        match UdpSocket::bind((Ipv4Addr::LOCALHOST, listener.local_addr()?.port())) {
+            Ok(socket) => return Ok((listener, socket)),
+            Err(e) => error = Some(e),
+        }
+    }
+    Err(error.unwrap())
+}
+
+fn run_tcp(listener: TcpListener, target: SocketAddr, signal: Receiver<()>) {
+    if let Err(e) = listener.set_nonblocking(true) {
+        logging!("Relay", "Cannot relay TCP: {:?}", e);
+        return;
+    }
+
+    loop {
+        if let Err(mpsc::TryRecvError::Disconnected) = signal.try_recv() {
+            return;
+        }
+
+        match listener.accept() {
+            Ok((client, _)) => {
+                thread::spawn(move || {
+                    if let Err(e) = relay_tcp(client, target) {
+                        logging!("Relay", "Cannot relay TCP connection to {}: {:?}", target, e);
+                    }
+                });
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(50)),
+            Err(e) => {
+                logging!("Relay", "Cannot accept TCP connection: {:?}", e);
+                thread::sleep(Duration::from_millis(50));
+            }
+        }
+    }
+}
+
+fn relay_tcp(client: TcpStream, target: SocketAddr) -> io::Result<()> {
+    client.set_nonblocking(false)?;
+    let upstream = TcpStream::connect_timeout(&target, Duration::from_secs(10))?;
+    client.set_nodelay(true)?;
+    upstream.set_nodelay(true)?;
+
+    let (mut client_read, mut upstream_write) = (client.try_clone()?, upstream.try_clone()?);
+    let outbound = thread::spawn(move || {
+        let _ = io::copy(&mut client_read, &mut upstream_write);
+        let _ = upstream_write.shutdown(Shutdown::Write);
+    });
+
+    let (mut upstream_read, mut client_write) = (upstream, client);
+    let _ = io::copy(&mut upstream_read, &mut client_write);
+    let _ = client_write.shutdown(Shutdown::Write);
+
+    let _ = outbound.join();
+    Ok(())
+}
+
+fn run_udp(socket: UdpSocket, target: SocketAddr, signal: Receiver<()>) {
+    if let Err(e) = socket.set_read_timeout(Some(Duration::from_millis(500))) {
+        logging!("Relay", "Cannot relay UDP: {:?}", e);
+        return;
+    }
+
+    // Each client is given its own upstream socket, so that responses can be sent back to it.
+    let mut clients: HashMap<SocketAddr, (UdpSocket, SystemTime, JoinHandle<()>)> = HashMap::new();
+    let mut buf = [0u8; 65536];
+    loop {
+        if let Err(mpsc::TryRecvError::Disconnected) = signal.try_recv() {
+            return;
+        }
+
+        let now = SystemTime::now();
+        clients.retain(|_, (_, time, reader)| !reader.is_finished() && !now.duration_since(*time).is_ok_and(|d| d >= UDP_IDLE));
+
+        let (length, client) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => continue,
+            Err(e) => {
+                logging!("Relay", "Cannot receive UDP datagram: {:?}", e);
+                continue;
+            }
+        };
+
+        let upstream = match clients.get_mut(&client) {
+            Some((upstream, time, reader)) if !reader.is_finished() => {
+                *time = now;
+                upstream
+            }
+            _ => {
+                let Ok((upstream, reader)) = connect_udp(&socket, client, target).inspect_err(|e| {
+                    logging!("Relay", "Cannot relay UDP datagrams of {}: {:?}", client, e);
+                }) else {
+                    continue;
+                };
+                clients.insert(client, (upstream, now, reader));
+                &clients[&client].0
+            }
+        };
+        let _ = upstream.send(&buf[..length]);
+    }
+}
+
+/// Create the upstream socket of a client, whose responses are sent back until target is silent for UDP_IDLE.
+fn connect_udp(socket: &UdpSocket, client: SocketAddr, target: SocketAddr) -> io::Result<(UdpSocket, JoinHandle<()>)> {
+    let upstream = match target {
+        SocketAddr::V4(_) => UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?,
+        SocketAddr::V6(_) => UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0))?,
+    };
+    upstream.connect(target)?;
+    upstream.set_read_timeout(Some(UDP_IDLE))?;
+
+    let (reader, downstream) = (upstream.try_clone()?, socket.try_clone()?);
+    let reader = thread::spawn(move || {
+        let mut buf = [0u8; 65536];
+        while let Ok(length) = reader.recv(&mut buf) {
+            if downstream.send_to(&buf[..length], client).is_err() {
+                return;
+            }
+        }
+    });
+    Ok((upstream, reader))
+}
@@ -10,18 +10,26 @@ use std::time::{Duration, SystemTime};
 use std::{mem, thread};
 
 pub struct MinecraftScanner {
-    port: Arc<Mutex<Vec<u16>>>,
+    servers: Arc<Mutex<Vec<LanServer>>>,
     _holder: Sender<()>,
 }
 
+/// A world opened to LAN, advertised by Minecraft through multicast.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LanServer {
+    pub port: u16,
+    pub motd: String,
+    pub last_seen: SystemTime,
+}
+
 impl MinecraftScanner {
     pub fn create(filter: fn(&str) -> bool) -> MinecraftScanner {
         let (tx, rx) = mpsc::channel::<()>();
-        let port = Arc::new(Mutex::new(vec![]));
+        let servers = Arc::new(Mutex::new(vec![]));
 
-        let port_cloned = Arc::clone(&port);
+        let servers_cloned = Arc::clone(&servers);
         thread::spawn(move || {
-            let result = Self::run(rx, port_cloned, filter);
+            let result = Self::run(rx, servers_cloned, filter);
 
             match result {
                 Ok(_) => {}
@@ -31,10 +39,10 @@ impl MinecraftScanner {
             }
         });
 
-        return MinecraftScanner { _holder: tx, port };
+        return MinecraftScanner { _holder: tx, servers };
     }
 
-    fn run(signal: Receiver<()>, output: Arc<Mutex<Vec<u16>>>, filter: fn(&str) -> bool) -> Result<()> {
+    fn run(signal: Receiver<()>, output: Arc<Mutex<Vec<LanServer>>>, filter: fn(&str) -> bool) -> Result<()> {
         let sockets: Vec<(Socket, &IpAddr)> = crate::ADDRESSES
             .iter()
             .map(|address| match address {
@@ -66,23 +74,25 @@ impl MinecraftScanner {
 
         let mut buf: [MaybeUninit<u8>; _] = [MaybeUninit::uninit(); 8192];
 
-        let mut ports: Vec<(u16, SystemTime)> = vec![];
+        let mut servers: Vec<LanServer> = vec![];
 
         loop {
+            // Whether servers have been added or removed, and whether any of them has been seen.
             let mut dirty = false;
+            let mut seen = false;
 
             if let Err(mpsc::TryRecvError::Disconnected) = signal.try_recv() {
                 return Ok(());
             }
 
             let now = SystemTime::now();
-            for i in (0..ports.len()).rev() {
-                if match now.duration_since(ports[i].1) {
+            for i in (0..servers.len()).rev() {
+                if match now.duration_since(servers[i].last_seen) {
                     Ok(value) => value.as_millis() >= 5_000,
                     Err(_) => false,
                 } {
                     dirty = true;
-                    ports.remove(i);
+                    servers.remove(i);
                 }
             }
 
@@ -92,7 +102,7 @@ impl MinecraftScanner {
                     let buf = unsafe { mem::transmute::<&[MaybeUninit<u8>], &[u8]>(&buf[..length]) };
 
                     let data: Cow<'_, str> = String::from_utf8_lossy(buf);
-                    {
+                    let motd = {
                         let begin = data.find("[MOTD]");
                         let end = data.find("[/MOTD]");
                         if let Some(begin) = begin
@@ -100,10 +110,12 @@ impl MinecraftScanner {
                             && end - begin >= "[MOTD]".len() + 1
                             && let Some(motd) = data.as_ref().get((begin + "[MOTD]".len())..end)
                             && filter(motd)
-                        {} else {
+                        {
+                            motd
+                        } else {
                             continue;
                         }
-                    }
+                    };
 
                     {
                         let begin = data.find("[AD]");
@@ -114,34 +126,35 @@ impl MinecraftScanner {
                             && let Some(port) = data.as_ref().get((begin + "[AD]".len())..end)
                             && let Ok(port) = port.parse::<u16>()
                         {
-                            let mut existed = false;
-                            for i in 0..ports.len() {
-                                if ports[i].0 == port {
-                                    existed = true;
-                                    ports.remove(i);
-                                    break;
+                            // Servers are kept in the order of discovery, while MOTD may change as another world is opened.
+                            match servers.iter_mut().find(|server| server.port == port) {
+                                Some(server) => {
+                                    if server.motd != motd {
+                                        server.motd = motd.to_string();
+                                        dirty = true;
+                                    }
+                                    server.last_seen = SystemTime::now();
+                                }
+                                None => {
+                                    servers.push(LanServer { port, motd: motd.to_string(), last_seen: SystemTime::now() });
+                                    dirty = true;
                                 }
                             }
-
-                            ports.push((port, SystemTime::now()));
-                            if !existed {
-                                dirty = true;
-                            }
+                            seen = true;
                             break;
                         }
                     }
                 }
             }
 
+            if dirty || seen {
+                output.lock().unwrap().clone_from(&servers);
+            }
             if dirty {
-                let mut output = output.lock().unwrap();
-                output.clear();
-
                 let mut message = String::from("Updating server list to [");
-                for i in 0..ports.len() {
-                    output.push(ports[0].0);
-                    message += &ports[0].0.to_string();
-                    if i != ports.len() - 1 {
+                for (i, server) in servers.iter().enumerate() {
+                    message += &format!("{} ({:?})", server.port, server.motd);
+                    if i != servers.len() - 1 {
                         message.push_str(", ");
                     }
                 }
@@ -151,7 +164,12 @@ impl MinecraftScanner {
         }
     }
 
+    /// Worlds advertised within the last 5 seconds, in the order of discovery.
+    pub fn get_servers(&self) -> Vec<LanServer> {
+        return self.servers.lock().unwrap().clone();
+    }
+
     pub fn get_ports(&self) -> Vec<u16> {
-        return self.port.lock().unwrap().clone();
+        return self.servers.lock().unwrap().iter().map(|server| server.port).collect();
     }
 }
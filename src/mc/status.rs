@@ -0,0 +1,195 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::Duration;
+
+// Responses are rejected beyond this size, which is far more than any favicon.
+const MAX_RESPONSE: usize = 2 * 1024 * 1024;
+
+/// Status of a Minecraft server, as shown on the multiplayer screen.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ServerStatus {
+    pub version: String,
+    /// Protocol version, or None if the server is older than 1.4 and only tells its MOTD and player counts.
+    pub protocol: Option<i32>,
+    pub online: u32,
+    pub max: u32,
+    /// Description with formatting codes removed and chat components flattened into plain text.
+    pub description: String,
+    /// Favicon as a data URL, which is only sent by servers since 1.7.
+    pub favicon: Option<String>,
+}
+
+/// Query the status of a Minecraft server through Server List Ping. Servers older than 1.7 don't answer the status
+/// request, so the legacy ping is sent instead if the connection is closed or answered with unknown data.
+pub fn query(address: SocketAddr, timeout: Duration) -> io::Result<ServerStatus> {
+    match query_modern(address, timeout) {
+        Err(e) if matches!(
+            e.kind(),
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted
+        ) => query_legacy(address, timeout),
+        result => result,
+    }
+}
+
+fn connect(address: SocketAddr, timeout: Duration) -> io::Result<TcpStream> {
+    let stream = TcpStream::connect_timeout(&address, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+fn query_modern(address: SocketAddr, timeout: Duration) -> io::Result<ServerStatus> {
+    let mut stream = connect(address, timeout)?;
+
+    let mut handshake = vec![0x00];
+    // Protocol version -1 is conventionally used by clients which only query the status.
+    write_varint(&mut handshake, -1);
+    let host = address.ip().to_string();
+    write_varint(&mut handshake, host.len() as i32);
+    handshake.extend_from_slice(host.as_bytes());
+    handshake.extend_from_slice(&address.port().to_be_bytes());
+    write_varint(&mut handshake, 1);
+
+    let mut packets = vec![];
+    write_varint(&mut packets, handshake.len() as i32);
+    packets.extend_from_slice(&handshake);
+    // Status request, which carries nothing but the packet ID.
+    packets.extend_from_slice(&[0x01, 0x00]);
+    stream.write_all(&packets)?;
+
+    let length = read_length(&mut stream)?;
+    let mut packet = vec![0u8; length];
+    stream.read_exact(&mut packet)?;
+
+    let mut packet = packet.as_slice();
+    if read_varint(&mut packet)? != 0x00 {
+        return Err(invalid("Unexpected packet ID."));
+    }
+    let length = read_length(&mut packet)?;
+    let json = packet.get(..length).ok_or_else(|| invalid("Truncated status response."))?;
+    let value: Value = serde_json::from_slice(json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let number = |path: [&str; 2]| value.get(path[0]).and_then(|value| value.get(path[1])).and_then(Value::as_i64);
+    Ok(ServerStatus {
+        version: value.get("version").and_then(|version| version.get("name")).and_then(Value::as_str).unwrap_or_default().to_string(),
+        protocol: number(["version", "protocol"]).map(|protocol| protocol as i32),
+        online: number(["players", "online"]).unwrap_or(0).clamp(0, u32::MAX as i64) as u32,
+        max: number(["players", "max"]).unwrap_or(0).clamp(0, u32::MAX as i64) as u32,
+        description: value.get("description").map(|description| {
+            let mut text = String::new();
+            flatten(description, &mut text);
+            strip_formatting(&text)
+        }).unwrap_or_default(),
+        favicon: value.get("favicon").and_then(Value::as_str).map(str::to_string),
+    })
+}
+
+fn query_legacy(address: SocketAddr, timeout: Duration) -> io::Result<ServerStatus> {
+    let mut stream = connect(address, timeout)?;
+    // Servers before 1.4 ignore the trailing 0x01, while later ones answer with the protocol and version.
+    stream.write_all(&[0xFE, 0x01])?;
+
+    let mut header = [0u8; 3];
+    stream.read_exact(&mut header)?;
+    if header[0] != 0xFF {
+        return Err(invalid("Unexpected legacy ping response."));
+    }
+    let mut data = vec![0u8; u16::from_be_bytes([header[1], header[2]]) as usize * 2];
+    stream.read_exact(&mut data)?;
+    let text = String::from_utf16_lossy(&data.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect::<Vec<_>>());
+
+    let count = |value: Option<&str>| value.and_then(|value| value.parse::<u32>().ok()).unwrap_or(0);
+    if let Some(fields) = text.strip_prefix("§1\0") {
+        // 1.4 to 1.6: protocol, version, MOTD, online players and max players, separated by NUL.
+        let fields: Vec<&str> = fields.split('\0').collect();
+        Ok(ServerStatus {
+            version: fields.get(1).copied().unwrap_or_default().to_string(),
+            protocol: fields.first().and_then(|protocol| protocol.parse().ok()),
+            online: count(fields.get(3).copied()),
+            max: count(fields.get(4).copied()),
+            description: strip_formatting(fields.get(2).copied().unwrap_or_default()),
+            favicon: None,
+        })
+    } else {
+        // Beta 1.8 to 1.3: MOTD, online players and max players, separated by section signs.
+        let mut fields = text.rsplitn(3, '§');
+        let (max, online) = (count(fields.next()), count(fields.next()));
+        Ok(ServerStatus {
+            version: String::new(),
+            protocol: None,
+            online,
+            max,
+            description: fields.next().unwrap_or_default().to_string(),
+            favicon: None,
+        })
+    }
+}
+
+/// Description may be a plain string or a chat component, whose text is followed by that of its extra components.
+fn flatten(component: &Value, output: &mut String) {
+    match component {
+        Value::String(text) => output.push_str(text),
+        Value::Array(components) => components.iter().for_each(|component| flatten(component, output)),
+        Value::Object(component) => {
+            if let Some(text) = component.get("text").and_then(Value::as_str) {
+                output.push_str(text);
+            }
+            if let Some(extra) = component.get("extra") {
+                flatten(extra, output);
+            }
+        }
+        _ => {}
+    }
+}
+
+pub(crate) fn strip_formatting(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '§' {
+            chars.next();
+        } else {
+            output.push(c);
+        }
+    }
+    output
+}
+
+fn invalid(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_varint(output: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+    loop {
+        if value & !0x7F == 0 {
+            output.push(value as u8);
+            return;
+        }
+        output.push((value & 0x7F) as u8 | 0x80);
+        value >>= 7;
+    }
+}
+
+fn read_varint<R: Read>(input: &mut R) -> io::Result<i32> {
+    let mut value = 0u32;
+    for i in 0..5 {
+        let mut byte = [0u8; 1];
+        input.read_exact(&mut byte)?;
+        value |= ((byte[0] & 0x7F) as u32) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+    Err(invalid("VarInt is too long."))
+}
+
+fn read_length<R: Read>(input: &mut R) -> io::Result<usize> {
+    match read_varint(input)? {
+        length @ 0.. if (length as usize) <= MAX_RESPONSE => Ok(length as usize),
+        _ => Err(invalid("Invalid packet length.")),
+    }
+}
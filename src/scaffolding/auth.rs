@@ -0,0 +1,76 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Length of the MAC appended to every frame once a connection is authenticated.
+pub(crate) const MAC_LENGTH: usize = 16;
+pub(crate) const NONCE_LENGTH: usize = 16;
+pub(crate) const PROOF_LENGTH: usize = 32;
+
+/// Body of the first c:auth request, followed by the client nonce.
+pub(crate) const STEP_CHALLENGE: u8 = 1;
+/// Body of the second c:auth request, followed by the client proof.
+pub(crate) const STEP_RESPONSE: u8 = 2;
+
+pub(crate) const DIRECTION_REQUEST: u8 = 0;
+pub(crate) const DIRECTION_RESPONSE: u8 = 1;
+
+fn create_mac(secret: &[u8], label: &[u8], client_nonce: &[u8], server_nonce: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+    mac.update(label);
+    mac.update(client_nonce);
+    mac.update(server_nonce);
+    mac
+}
+
+/// Compute the proof that a side knows the room secret. Labels differ between both sides, so that proofs cannot be reflected.
+pub(crate) fn proof(secret: &[u8], server: bool, client_nonce: &[u8], server_nonce: &[u8]) -> [u8; PROOF_LENGTH] {
+    let label: &[u8] = if server { b"scaffolding-server" } else { b"scaffolding-client" };
+    create_mac(secret, label, client_nonce, server_nonce).finalize().into_bytes().into()
+}
+
+pub(crate) fn verify_proof(secret: &[u8], server: bool, client_nonce: &[u8], server_nonce: &[u8], proof: &[u8]) -> bool {
+    let label: &[u8] = if server { b"scaffolding-server" } else { b"scaffolding-client" };
+    create_mac(secret, label, client_nonce, server_nonce).verify_slice(proof).is_ok()
+}
+
+pub(crate) fn session_key(secret: &[u8], client_nonce: &[u8], server_nonce: &[u8]) -> [u8; 32] {
+    create_mac(secret, b"scaffolding-session", client_nonce, server_nonce).finalize().into_bytes().into()
+}
+
+/// MACs of requests and responses on an authenticated connection.
+pub(crate) type FrameMacs = (FrameMac, FrameMac);
+
+/// Signs or verifies frames in one direction. Sequence numbers are covered, so that frames cannot be replayed or reordered.
+pub(crate) struct FrameMac {
+    key: [u8; 32],
+    direction: u8,
+    sequence: u64,
+}
+
+impl FrameMac {
+    pub(crate) fn new(key: [u8; 32], direction: u8) -> FrameMac {
+        FrameMac { key, direction, sequence: 0 }
+    }
+
+    fn next(&mut self, parts: &[&[u8]]) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).unwrap();
+        mac.update(&[self.direction]);
+        mac.update(&self.sequence.to_be_bytes());
+        for part in parts {
+            mac.update(part);
+        }
+
+        self.sequence += 1;
+        mac
+    }
+
+    pub(crate) fn sign(&mut self, parts: &[&[u8]]) -> [u8; MAC_LENGTH] {
+        let mut tag = [0u8; MAC_LENGTH];
+        tag.copy_from_slice(&self.next(parts).finalize().into_bytes()[..MAC_LENGTH]);
+        tag
+    }
+
+    pub(crate) fn verify(&mut self, parts: &[&[u8]], tag: &[u8]) -> bool {
+        self.next(parts).verify_truncated_left(tag).is_ok()
+    }
+}
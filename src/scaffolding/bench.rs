@@ -0,0 +1,156 @@
+//! Compares request throughput and memory of the event-driven scaffolding server against the thread-per-connection
+//! design it replaced, which is kept here as a minimal reference implementation.
+//!
+//! It takes a while, so it's ignored unless requested: `cargo test --release bench -- --ignored --nocapture`.
+
+use crate::scaffolding::limits::ServerLimits;
+use crate::scaffolding::server::{self, HandlerRegistry};
+use crate::scaffolding::PacketResponse;
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, SocketAddrV4, TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+use std::{fs, io, thread};
+
+static HANDLERS: HandlerRegistry = HandlerRegistry::new(&[
+    ("c", "ping", |request, mut response| {
+        response.extend_from_slice(request);
+        PacketResponse::ok(response)
+    }),
+]);
+
+const CLIENT_THREADS: usize = 8;
+
+fn run(connections: usize, requests: usize) -> io::Result<()> {
+    println!("Scaffolding server benchmark: {} connections, {} requests.", connections, requests);
+
+    let limits = ServerLimits {
+        max_connections: connections + 1,
+        requests_per_second: u32::MAX,
+        idle_timeout: Duration::from_secs(600),
+        // Benchmark clients don't authenticate, as no room secret is available.
+        allow_unauthenticated: true,
+        ..ServerLimits::default()
+    };
+    let event_loop = measure(server::start(&HANDLERS, || None, limits, 0)?, connections, requests)?;
+    let threaded = measure(start_threaded()?, connections, requests)?;
+
+    println!("{:<24}{:>16}{:>16}", "", "event loop", "threaded");
+    println!("{:<24}{:>16.0}{:>16.0}", "Requests per second", event_loop.throughput, threaded.throughput);
+    println!("{:<24}{:>16}{:>16}", "Threads while idle", format_delta(event_loop.threads), format_delta(threaded.threads));
+    println!("{:<24}{:>16}{:>16}", "Memory while idle (KiB)", format_delta(event_loop.memory), format_delta(threaded.memory));
+    Ok(())
+}
+
+struct Report {
+    throughput: f64,
+    /// Threads and resident memory gained after opening idle connections, if available on current platform.
+    threads: Option<i64>,
+    memory: Option<i64>,
+}
+
+fn measure(port: u16, connections: usize, requests: usize) -> io::Result<Report> {
+    let (threads, memory) = (process_status("Threads:"), process_status("VmRSS:"));
+
+    let mut streams = (0..connections)
+        .map(|_| TcpStream::connect(SocketAddrV4::new(Ipv4Addr::LOCALHOST, port)))
+        .collect::<io::Result<Vec<_>>>()?;
+    for stream in streams.iter_mut() {
+        ping(stream)?;
+    }
+    // Let the server settle down before sampling.
+    thread::sleep(Duration::from_millis(500));
+    let threads = process_status("Threads:").zip(threads).map(|(after, before)| after - before);
+    let memory = process_status("VmRSS:").zip(memory).map(|(after, before)| after - before);
+
+    let start = Instant::now();
+    let chunk = connections.div_ceil(CLIENT_THREADS);
+    let mut clients = vec![];
+    while !streams.is_empty() {
+        let mut group = streams.split_off(streams.len().saturating_sub(chunk));
+        let count = requests * group.len() / connections;
+        clients.push(thread::spawn(move || -> io::Result<()> {
+            for index in 0..count {
+                let length = group.len();
+                ping(&mut group[index % length])?;
+            }
+            Ok(())
+        }));
+    }
+    for client in clients {
+        client.join().unwrap()?;
+    }
+    let throughput = requests as f64 / start.elapsed().as_secs_f64();
+
+    Ok(Report { throughput, threads, memory })
+}
+
+fn ping(stream: &mut TcpStream) -> io::Result<()> {
+    const KIND: &[u8] = b"c:ping";
+    const BODY: &[u8] = b"benchmark";
+
+    let mut request = Vec::with_capacity(1 + KIND.len() + 4 + BODY.len());
+    request.push(KIND.len() as u8);
+    request.extend_from_slice(KIND);
+    request.extend_from_slice(&(BODY.len() as u32).to_be_bytes());
+    request.extend_from_slice(BODY);
+    stream.write_all(&request)?;
+
+    let mut header = [0u8; 5];
+    stream.read_exact(&mut header)?;
+    let mut body = vec![0u8; u32::from_be_bytes(header[1..5].try_into().unwrap()) as usize];
+    stream.read_exact(&mut body)?;
+
+    if header[0] != 0 || body != BODY {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected response of c:ping."));
+    }
+    Ok(())
+}
+
+/// Serve requests in the serial format with blocking reads, one thread per connection.
+fn start_threaded() -> io::Result<u16> {
+    let listener = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0))?;
+    let port = listener.local_addr()?.port();
+
+    thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            thread::spawn(move || -> io::Result<()> {
+                loop {
+                    let mut kind_size = [0u8; 1];
+                    stream.read_exact(&mut kind_size)?;
+                    let mut kind = vec![0u8; kind_size[0] as usize];
+                    stream.read_exact(&mut kind)?;
+                    let mut body_size = [0u8; 4];
+                    stream.read_exact(&mut body_size)?;
+                    let mut body = vec![0u8; u32::from_be_bytes(body_size) as usize];
+                    stream.read_exact(&mut body)?;
+
+                    let kind = String::from_utf8(kind).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                    stream.write_all(&server::dispatch(&kind, &body, &HANDLERS))?;
+                }
+            });
+        }
+    });
+
+    Ok(port)
+}
+
+/// Read a numeric field from /proc/self/status, which is only available on Linux.
+fn process_status(key: &str) -> Option<i64> {
+    fs::read_to_string("/proc/self/status").ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix(key))?
+        .split_whitespace()
+        .next()?
+        .parse()
+        .ok()
+}
+
+fn format_delta(value: Option<i64>) -> String {
+    value.map_or_else(|| "n/a".to_string(), |value| format!("{:+}", value))
+}
+
+#[test]
+#[ignore]
+fn bench() {
+    run(512, 200_000).unwrap();
+}
@@ -1,19 +1,62 @@
-use crate::scaffolding::{PacketResponse, TIMEOUT};
+use crate::scaffolding::auth::{self, FrameMac, FrameMacs};
+use crate::scaffolding::{PacketResponse, AUTH, PIPELINE, TIMEOUT};
+use parking_lot::Mutex;
+use rand_core::{OsRng, TryRngCore};
 use socket2::{Domain, SockAddr, Socket, Type};
+use std::collections::HashMap;
 use std::io::{Read, Write};
-use std::net::{IpAddr, SocketAddr};
+use std::net::{IpAddr, Shutdown, SocketAddr};
 use std::sync::{mpsc, Arc, OnceLock};
 use std::{io, thread};
 use std::time::Duration;
 
+type Handle = Box<dyn FnOnce(Option<PacketResponse>) + Send + 'static>;
+
+/// How the session should be switched after a packet succeeds.
+enum Switch {
+    Pipeline,
+    Authenticate([u8; 32]),
+}
+
 struct Packet {
     data: Vec<u8>,
-    handle: Box<dyn FnOnce(Option<PacketResponse>) + Send + 'static>,
+    switch: Option<Switch>,
+    handle: Handle,
 }
 
+/// Clones share the same connection, so that requests can be sent from several threads.
+#[derive(Clone)]
 pub struct ClientSession {
     channel: mpsc::Sender<Packet>,
     alive: Arc<OnceLock<()>>,
+    /// Protocols implemented by host, or None if they haven't been negotiated.
+    protocols: Option<Vec<String>>,
+}
+
+/// A response which will be delivered later, returned by [ClientSession::send].
+pub struct PendingResponse {
+    kind: String,
+    receiver: mpsc::Receiver<Option<PacketResponse>>,
+    alive: Arc<OnceLock<()>>,
+}
+
+impl PendingResponse {
+    /// Block until the response arrives. None is returned only if the session has been closed.
+    pub fn wait(self) -> Option<PacketResponse> {
+        let response = loop {
+            match self.receiver.recv_timeout(Duration::from_millis(100)) {
+                Ok(response) => break response,
+                Err(mpsc::RecvTimeoutError::Disconnected) => break None,
+                Err(mpsc::RecvTimeoutError::Timeout) if self.alive.get().is_some() => break None,
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            }
+        };
+
+        if response.is_none() {
+            logging!("ScaffoldingClient", "API {} invocation failed: Session has been closed.", self.kind);
+        }
+        response
+    }
 }
 
 impl ClientSession {
@@ -39,37 +82,41 @@ impl ClientSession {
         let alive2 = alive.clone();
 
         thread::spawn(move || {
+            // MACs for requests and responses, available once the session is authenticated.
+            let mut macs: Option<FrameMacs> = None;
             let handle_packet =
-                &mut move |data: Vec<u8>| -> io::Result<PacketResponse> {
+                &mut |data: Vec<u8>, macs: &mut Option<FrameMacs>| -> io::Result<PacketResponse> {
                     socket.write_all(data.as_ref())?;
+                    if let Some((mac, _)) = macs {
+                        socket.write_all(&mac.sign(&[&data]))?;
+                    }
                     socket.flush()?;
 
                     drop(data);
 
-                    let (status, body_size) = {
-                        let mut buf = [0u8; 5];
-                        socket.read_exact(&mut buf)?;
-
-                        (
-                            buf[0],
-                            u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]) as usize,
-                        )
-                    };
-
-                    let mut data = vec![0u8; body_size];
-                    socket.read_exact(&mut data)?;
-
-                    Ok(if status == 0 {
-                        PacketResponse::Ok { data }
-                    } else {
-                        PacketResponse::Fail { status, data }
-                    })
+                    read_response(&mut socket, &[], macs.as_mut().map(|(_, mac)| mac))
                 };
 
             while let Ok(packet) = receiver.recv() {
-                let Packet { data, handle } = packet;
-                match handle_packet(data) {
-                    Ok(response) => handle(Some(response)),
+                let Packet { data, switch, handle } = packet;
+                match handle_packet(data, &mut macs) {
+                    Ok(response) => {
+                        let switch = switch.filter(|_| matches!(response, PacketResponse::Ok { .. }));
+                        handle(Some(response));
+
+                        match switch {
+                            Some(Switch::Pipeline) => {
+                                logging!("ScaffoldingClient", "Session has been switched to pipelined mode.");
+                                run_pipelined(socket, receiver, alive, macs);
+                                return;
+                            }
+                            Some(Switch::Authenticate(key)) => {
+                                logging!("ScaffoldingClient", "Session has been authenticated.");
+                                macs = Some((FrameMac::new(key, auth::DIRECTION_REQUEST), FrameMac::new(key, auth::DIRECTION_RESPONSE)));
+                            }
+                            None => {}
+                        }
+                    }
                     Err(e) => {
                         logging!("ScaffoldingClient", "Session is closed: {:?}", e);
                         alive.get_or_init(|| ());
@@ -84,6 +131,7 @@ impl ClientSession {
         Ok(ClientSession {
             channel: sender,
             alive: alive2,
+            protocols: None,
         })
     }
 
@@ -91,42 +139,117 @@ impl ClientSession {
         self.alive.get().is_none()
     }
 
+    /// Fetch protocols implemented by host through c:protocols, which are available from [ClientSession::protocols] later.
+    /// If host advertises c:auth, the session is authenticated with the room secret, and an error is returned if it fails.
+    /// The session is then switched to pipelined mode if host advertises it, so that a slow request no longer holds up the following ones.
+    /// Hosts only speaking the serial format are still served serially. Returns whether the session is pipelined.
+    pub fn negotiate(&mut self, secret: &str) -> io::Result<bool> {
+        let Some(PacketResponse::Ok { data }) = self.send_sync(("c", "protocols"), |_| {}) else {
+            return if self.is_alive() {
+                Ok(false)
+            } else {
+                Err(io::Error::from(io::ErrorKind::ConnectionAborted))
+            };
+        };
+        let protocols = data
+            .split(|byte| *byte == b'\0')
+            .filter(|protocol| !protocol.is_empty())
+            .map(|protocol| String::from_utf8_lossy(protocol).into_owned())
+            .collect::<Vec<_>>();
+        logging!("ScaffoldingClient", "Host supports protocols: {:?}", protocols);
+        self.protocols = Some(protocols);
+
+        if self.supports(AUTH) {
+            self.authenticate(secret)?;
+        } else {
+            logging!("ScaffoldingClient", "Host doesn't support authentication, frames are sent without MAC.");
+        }
+
+        if !self.supports(PIPELINE) {
+            logging!("ScaffoldingClient", "Host doesn't support pipelined mode, requests will be sent serially.");
+            return Ok(false);
+        }
+
+        let (namespace, path) = PIPELINE.split_once(':').unwrap();
+        Ok(matches!(self.enqueue((namespace, path), |_| {}, Some(Switch::Pipeline)).wait(), Some(PacketResponse::Ok { .. })))
+    }
+
+    // Both sides prove knowing the secret by signing nonces from each other, from which the key of frame MACs is derived.
+    fn authenticate(&mut self, secret: &str) -> io::Result<()> {
+        let (namespace, path) = AUTH.split_once(':').unwrap();
+        let denied = |message: &str| io::Error::new(io::ErrorKind::PermissionDenied, message);
+
+        let mut client_nonce = [0u8; auth::NONCE_LENGTH];
+        OsRng.try_fill_bytes(&mut client_nonce).unwrap();
+
+        let Some(PacketResponse::Ok { data }) = self.send_sync((namespace, path), |body| {
+            body.push(auth::STEP_CHALLENGE);
+            body.extend_from_slice(&client_nonce);
+        }) else {
+            return Err(denied("Host refused the authentication challenge."));
+        };
+        if data.len() != auth::NONCE_LENGTH + auth::PROOF_LENGTH {
+            return Err(denied("Invalid authentication challenge response."));
+        }
+        let (server_nonce, proof) = data.split_at(auth::NONCE_LENGTH);
+        if !auth::verify_proof(secret.as_bytes(), true, &client_nonce, server_nonce, proof) {
+            return Err(denied("Host cannot prove knowing the room secret."));
+        }
+
+        let key = auth::session_key(secret.as_bytes(), &client_nonce, server_nonce);
+        let proof = auth::proof(secret.as_bytes(), false, &client_nonce, server_nonce);
+        match self.enqueue((namespace, path), |body| {
+            body.push(auth::STEP_RESPONSE);
+            body.extend_from_slice(&proof);
+        }, Some(Switch::Authenticate(key))).wait() {
+            Some(PacketResponse::Ok { .. }) => Ok(()),
+            _ => Err(denied("Host rejected the authentication.")),
+        }
+    }
+
+    pub fn protocols(&self) -> Option<&[String]> {
+        self.protocols.as_deref()
+    }
+
+    /// Whether host has advertised the protocol, in 'namespace:path' format.
+    pub fn supports(&self, protocol: &str) -> bool {
+        self.protocols.as_ref().is_some_and(|protocols| protocols.iter().any(|p| p == protocol))
+    }
+
     pub fn send_sync<P>(&mut self, kind: (&str, &str), encoder: P) -> Option<PacketResponse>
     where
         P: FnOnce(&mut Vec<u8>),
     {
-        let (sender, receiver) = mpsc::channel();
-        self.send(kind, encoder, move |response| {
-            let _ = sender.send(response);
-        });
-
-        match loop {
-            match receiver.recv_timeout(Duration::from_millis(100)) {
-                Ok(response) => break response,
-                Err(mpsc::RecvTimeoutError::Disconnected) => unreachable!(),
-                Err(mpsc::RecvTimeoutError::Timeout) if !self.is_alive() => {
-                    logging!("ScaffoldingClient", "API {}:{} invocation failed: Session has been closed.", kind.0, kind.1);
-                    return None;
-                },
-                Err(mpsc::RecvTimeoutError::Timeout) => continue,
-            }
-        } {
-            Some(PacketResponse::Ok { data }) => Some(PacketResponse::Ok { data }),
-            Some(PacketResponse::Fail { status, data }) => {
+        match self.invoke(kind, encoder)? {
+            PacketResponse::Ok { data } => Some(PacketResponse::Ok { data }),
+            PacketResponse::Fail { status, data } => {
                 logging!("ScaffoldingClient", "API {}:{} invocation failed with status {}: {}", kind.0, kind.1, status, String::from_utf8_lossy(&data));
                 None
             },
-            None => {
-                logging!("ScaffoldingClient", "API {}:{} invocation failed: Session has been closed.", kind.0, kind.1);
-                None
-            }
         }
     }
 
-    fn send<P, R>(&mut self, kind: (&str, &str), encoder: P, receiver: R)
+    /// Like send_sync, but failure responses are returned to caller instead of being swallowed.
+    /// None is returned only if the session has been closed.
+    pub fn invoke<P>(&mut self, kind: (&str, &str), encoder: P) -> Option<PacketResponse>
+    where
+        P: FnOnce(&mut Vec<u8>),
+    {
+        self.send(kind, encoder).wait()
+    }
+
+    /// Send a request without waiting for its response.
+    /// In pipelined mode, several requests may be in flight and their responses may arrive in any order.
+    pub fn send<P>(&mut self, kind: (&str, &str), encoder: P) -> PendingResponse
+    where
+        P: FnOnce(&mut Vec<u8>),
+    {
+        self.enqueue(kind, encoder, None)
+    }
+
+    fn enqueue<P>(&mut self, kind: (&str, &str), encoder: P, switch: Option<Switch>) -> PendingResponse
     where
         P: FnOnce(&mut Vec<u8>),
-        R: FnOnce(Option<PacketResponse>) + Send + 'static,
     {
         let mut data: Vec<u8> = vec![];
 
@@ -149,11 +272,138 @@ impl ClientSession {
             data[pos..pos + size_of::<u32>()].copy_from_slice(&(length as u32).to_be_bytes());
         }
 
-        self.channel
-            .send(Packet {
-                data,
-                handle: Box::new(receiver),
-            })
-            .unwrap();
+        let (sender, receiver) = mpsc::channel();
+        // If the worker has exited, the packet is dropped and the pending response is resolved as None.
+        let _ = self.channel.send(Packet {
+            data,
+            switch,
+            handle: Box::new(move |response| {
+                let _ = sender.send(response);
+            }),
+        });
+
+        PendingResponse {
+            kind: format!("{}:{}", kind.0, kind.1),
+            receiver,
+            alive: self.alive.clone(),
+        }
+    }
+}
+
+fn read_response(socket: &mut Socket, prefix: &[u8], mac: Option<&mut FrameMac>) -> io::Result<PacketResponse> {
+    let mut header = [0u8; 5];
+    socket.read_exact(&mut header)?;
+    let (status, body_size) = (
+        header[0],
+        u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize,
+    );
+
+    let mut data = vec![0u8; body_size];
+    socket.read_exact(&mut data)?;
+
+    if let Some(mac) = mac {
+        let mut tag = [0u8; auth::MAC_LENGTH];
+        socket.read_exact(&mut tag)?;
+        if !mac.verify(&[prefix, &header, &data], &tag) {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "Invalid frame MAC."));
+        }
+    }
+
+    Ok(if status == 0 {
+        PacketResponse::Ok { data }
+    } else {
+        PacketResponse::Fail { status, data }
+    })
+}
+
+// In pipelined mode, every packet is prefixed with a 4-byte request ID, which is echoed by host in its response.
+// Packets are written by the worker thread, while responses are dispatched by a dedicated reader thread.
+fn run_pipelined(mut socket: Socket, receiver: mpsc::Receiver<Packet>, alive: Arc<OnceLock<()>>, macs: Option<FrameMacs>) {
+    let (mut request_mac, mut response_mac) = match macs {
+        Some((request, response)) => (Some(request), Some(response)),
+        None => (None, None),
+    };
+    let pending: Arc<Mutex<HashMap<u32, Handle>>> = Arc::new(Mutex::new(HashMap::new()));
+
+    let close = {
+        let pending = pending.clone();
+        let alive = alive.clone();
+        move |e: io::Error| {
+            if alive.set(()).is_ok() {
+                logging!("ScaffoldingClient", "Session is closed: {:?}", e);
+            }
+
+            let handles: Vec<Handle> = pending.lock().drain().map(|(_, handle)| handle).collect();
+            for handle in handles {
+                handle(None);
+            }
+        }
+    };
+
+    let mut reader = match socket.try_clone() {
+        Ok(reader) => reader,
+        Err(e) => {
+            close(e);
+            return;
+        }
+    };
+    {
+        let pending = pending.clone();
+        let close = close.clone();
+        thread::spawn(move || {
+            let e = loop {
+                let mut request_id = [0u8; 4];
+                // Idle sessions are kept, as long as no request is waiting for its response.
+                match reader.read(&mut request_id[..1]) {
+                    Ok(0) => break io::Error::from(io::ErrorKind::UnexpectedEof),
+                    Ok(_) => {}
+                    Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) && pending.lock().is_empty() => continue,
+                    Err(e) => break e,
+                }
+                if let Err(e) = reader.read_exact(&mut request_id[1..]) {
+                    break e;
+                }
+
+                let response = match read_response(&mut reader, &request_id, response_mac.as_mut()) {
+                    Ok(response) => response,
+                    Err(e) => break e,
+                };
+
+                let request_id = u32::from_be_bytes(request_id);
+                let handle = pending.lock().remove(&request_id);
+                match handle {
+                    Some(handle) => handle(Some(response)),
+                    None => {
+                        logging!("ScaffoldingClient", "Dropping response of unknown request {}.", request_id);
+                    }
+                }
+            };
+            close(e);
+        });
+    }
+
+    let mut next_id: u32 = 0;
+    while let Ok(packet) = receiver.recv() {
+        let Packet { data, handle, .. } = packet;
+        if alive.get().is_some() {
+            handle(None);
+            continue;
+        }
+
+        let request_id = next_id;
+        next_id = next_id.wrapping_add(1);
+        pending.lock().insert(request_id, handle);
+
+        let request_id = request_id.to_be_bytes();
+        let tag = request_mac.as_mut().map(|mac| mac.sign(&[&request_id, &data]));
+        if let Err(e) = socket.write_all(&request_id)
+            .and_then(|_| socket.write_all(&data))
+            .and_then(|_| tag.map_or(Ok(()), |tag| socket.write_all(&tag)))
+            .and_then(|_| socket.flush())
+        {
+            close(e);
+        }
     }
+
+    let _ = socket.shutdown(Shutdown::Both);
 }
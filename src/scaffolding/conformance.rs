@@ -0,0 +1,433 @@
+//! Conformance suite of the Scaffolding protocol, driving a host over TCP with hand-made frames.
+//!
+//! Checks only rely on the wire format and protocols advertised through c:protocols, so that other vendors can run
+//! the suite against their implementations with `terracotta scaffolding conformance <HOST:PORT>`.
+
+use crate::scaffolding::auth::{self, FrameMac, FrameMacs};
+use crate::scaffolding::{AUTH, PIPELINE, STATUS_UNAUTHENTICATED};
+use rand_core::{OsRng, TryRngCore};
+use serde_json::{json, Value};
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpStream};
+use std::time::Duration;
+
+/// Hosts are expected to answer every check within this duration.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+const UNKNOWN_PROTOCOL: &str = "conformance:unknown";
+
+pub type Check = Result<(), String>;
+
+fn ensure(condition: bool, message: impl FnOnce() -> String) -> Check {
+    if condition { Ok(()) } else { Err(message()) }
+}
+
+/// Collects results of checks, which are printed as they finish.
+#[derive(Default)]
+pub struct Suite {
+    passed: usize,
+    failed: usize,
+    skipped: usize,
+}
+
+impl Suite {
+    pub fn new() -> Suite {
+        Suite::default()
+    }
+
+    pub fn group(&self, name: &str) {
+        println!("\n{}", name);
+    }
+
+    pub fn check(&mut self, name: &str, check: impl FnOnce() -> Check) {
+        match check() {
+            Ok(_) => {
+                self.passed += 1;
+                println!("  PASS {}", name);
+            }
+            Err(e) => {
+                self.failed += 1;
+                println!("  FAIL {}: {}", name, e);
+            }
+        }
+    }
+
+    pub fn skip(&mut self, name: &str, reason: &str) {
+        self.skipped += 1;
+        println!("  SKIP {}: {}", name, reason);
+    }
+
+    /// Print the summary. Returns whether all checks have passed.
+    pub fn finish(self) -> bool {
+        println!("\n{} passed, {} failed, {} skipped.", self.passed, self.failed, self.skipped);
+        self.failed == 0
+    }
+}
+
+enum Reply {
+    Response { request_id: Option<[u8; 4]>, status: u8, data: Vec<u8> },
+    Closed,
+}
+
+/// A connection sending frames byte by byte as specified, without any help from [crate::scaffolding::client].
+struct Connection {
+    stream: TcpStream,
+    macs: Option<FrameMacs>,
+    pipelined: bool,
+}
+
+impl Connection {
+    fn open(address: SocketAddr) -> Result<Connection, String> {
+        let stream = TcpStream::connect_timeout(&address, READ_TIMEOUT).map_err(|e| format!("Cannot connect: {}", e))?;
+        stream.set_read_timeout(Some(READ_TIMEOUT)).unwrap();
+        stream.set_write_timeout(Some(READ_TIMEOUT)).unwrap();
+        Ok(Connection { stream, macs: None, pipelined: false })
+    }
+
+    /// Encode a request. Length of body is declared as given one, rather than the actual one, if available.
+    fn encode(&mut self, request_id: [u8; 4], kind: &[u8], declared: Option<u32>, body: &[u8]) -> Vec<u8> {
+        let mut frame = vec![];
+        if self.pipelined {
+            frame.extend_from_slice(&request_id);
+        }
+        frame.push(kind.len() as u8);
+        frame.extend_from_slice(kind);
+        frame.extend_from_slice(&declared.unwrap_or(body.len() as u32).to_be_bytes());
+        frame.extend_from_slice(body);
+        if let Some((mac, _)) = &mut self.macs {
+            let tag = mac.sign(&[&frame]);
+            frame.extend_from_slice(&tag);
+        }
+        frame
+    }
+
+    fn send(&mut self, request_id: [u8; 4], kind: &[u8], body: &[u8]) -> Check {
+        let frame = self.encode(request_id, kind, None, body);
+        self.stream.write_all(&frame).map_err(|e| format!("Cannot send request: {}", e))
+    }
+
+    fn receive(&mut self) -> Result<Reply, String> {
+        let mut frame = vec![0u8; if self.pipelined { 4 } else { 0 } + 5];
+        match self.stream.read_exact(&mut frame) {
+            Ok(_) => {}
+            Err(e) if matches!(e.kind(), io::ErrorKind::UnexpectedEof | io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted) => {
+                return Ok(Reply::Closed);
+            }
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                return Err(format!("No response in {}s.", READ_TIMEOUT.as_secs()));
+            }
+            Err(e) => return Err(format!("Cannot receive response: {}", e)),
+        }
+
+        let header = frame.len() - 5;
+        let length = u32::from_be_bytes(frame[header + 1..].try_into().unwrap()) as usize;
+        frame.resize(frame.len() + length, 0);
+        self.stream.read_exact(&mut frame[header + 5..]).map_err(|e| format!("Cannot receive response body: {}", e))?;
+
+        if let Some((_, mac)) = &mut self.macs {
+            let mut tag = [0u8; auth::MAC_LENGTH];
+            self.stream.read_exact(&mut tag).map_err(|e| format!("Cannot receive response MAC: {}", e))?;
+            if !mac.verify(&[&frame], &tag) {
+                return Err("Invalid response MAC.".to_string());
+            }
+        }
+
+        Ok(Reply::Response {
+            request_id: self.pipelined.then(|| frame[..4].try_into().unwrap()),
+            status: frame[header],
+            data: frame[header + 5..].to_vec(),
+        })
+    }
+
+    fn request(&mut self, kind: &str, body: &[u8]) -> Result<(u8, Vec<u8>), String> {
+        self.send([0; 4], kind.as_bytes(), body)?;
+        match self.receive()? {
+            Reply::Response { status, data, .. } => Ok((status, data)),
+            Reply::Closed => Err(format!("Connection is closed by host when requesting {}.", kind)),
+        }
+    }
+
+    fn authenticate(&mut self, secret: &str) -> Check {
+        let mut client_nonce = [0u8; auth::NONCE_LENGTH];
+        OsRng.try_fill_bytes(&mut client_nonce).unwrap();
+
+        let mut body = vec![auth::STEP_CHALLENGE];
+        body.extend_from_slice(&client_nonce);
+        let (status, data) = self.request(AUTH, &body)?;
+        ensure(status == 0, || format!("Challenge failed with status {}.", status))?;
+        ensure(data.len() == auth::NONCE_LENGTH + auth::PROOF_LENGTH, || format!("Challenge response has {} bytes.", data.len()))?;
+
+        let (server_nonce, proof) = data.split_at(auth::NONCE_LENGTH);
+        ensure(auth::verify_proof(secret.as_bytes(), true, &client_nonce, server_nonce, proof), || "Host cannot prove knowing the secret.".to_string())?;
+
+        let mut body = vec![auth::STEP_RESPONSE];
+        body.extend_from_slice(&auth::proof(secret.as_bytes(), false, &client_nonce, server_nonce));
+        let (status, _) = self.request(AUTH, &body)?;
+        ensure(status == 0, || format!("Host refused the proof with status {}.", status))?;
+
+        let key = auth::session_key(secret.as_bytes(), &client_nonce, server_nonce);
+        self.macs = Some((FrameMac::new(key, auth::DIRECTION_REQUEST), FrameMac::new(key, auth::DIRECTION_RESPONSE)));
+        Ok(())
+    }
+
+    /// Expect host to refuse the last request, either with a non-zero status or by closing the connection.
+    fn expect_refused(&mut self) -> Check {
+        match self.receive()? {
+            Reply::Response { status: 0, .. } => Err("Host has accepted the request.".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    OsRng.try_fill_bytes(&mut bytes).unwrap();
+    bytes
+}
+
+fn ping(address: SocketAddr) -> Check {
+    let body = random_bytes::<16>();
+    let (status, data) = Connection::open(address)?.request("c:ping", &body)?;
+    ensure(status == 0, || format!("Status is {}.", status))?;
+    ensure(data == body, || "Response doesn't match the request body.".to_string())
+}
+
+fn fetch_protocols(address: SocketAddr) -> Result<Vec<String>, String> {
+    let (status, data) = Connection::open(address)?.request("c:protocols", &[])?;
+    ensure(status == 0, || format!("Status is {}.", status))?;
+    let protocols = String::from_utf8(data).map_err(|_| "Protocols aren't in UTF-8.".to_string())?
+        .split('\0')
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+    for protocol in &protocols {
+        ensure(protocol.splitn(3, ':').count() == 2, || format!("Invalid protocol {:?}.", protocol))?;
+    }
+    for protocol in ["c:ping", "c:protocols"] {
+        ensure(protocols.iter().any(|p| p == protocol), || format!("{} isn't listed.", protocol))?;
+    }
+    Ok(protocols)
+}
+
+/// Run the suite against host at the address. Checks requiring authentication are skipped if no secret is given,
+/// and room protocols are only checked if the host is hosting a room.
+pub fn run(suite: &mut Suite, address: SocketAddr, secret: Option<&str>, room: bool) {
+    suite.group("Framing");
+    suite.check("c:ping echoes the request body", || ping(address));
+    suite.check("c:ping accepts an empty body", || {
+        let (status, data) = Connection::open(address)?.request("c:ping", &[])?;
+        ensure(status == 0 && data.is_empty(), || format!("Status is {}, with {} bytes.", status, data.len()))
+    });
+
+    let protocols = fetch_protocols(address);
+    suite.check("c:protocols lists protocols in 'namespace:path' format", || protocols.as_ref().map(|_| ()).map_err(Clone::clone));
+    let protocols = protocols.unwrap_or_default();
+    let supports = |protocol: &str| protocols.iter().any(|p| p == protocol);
+
+    suite.check("Serial requests are answered in order", || {
+        let mut connection = Connection::open(address)?;
+        let mut frames = vec![];
+        for index in 0..3u8 {
+            frames.extend(connection.encode([0; 4], b"c:ping", None, &[index]));
+        }
+        connection.stream.write_all(&frames).map_err(|e| e.to_string())?;
+        for index in 0..3u8 {
+            let Reply::Response { status: 0, data, .. } = connection.receive()? else {
+                return Err(format!("Request {} failed.", index));
+            };
+            ensure(data == [index], || format!("Response {} is out of order.", index))?;
+        }
+        Ok(())
+    });
+
+    for (name, kind) in [("Zero-length kind is refused", &b""[..]), ("Kind without ':' is refused", b"cping"), ("Kind with two ':' is refused", b"c:ping:ping")] {
+        suite.check(name, || {
+            let mut connection = Connection::open(address)?;
+            connection.send([0; 4], kind, b"conformance")?;
+            connection.expect_refused()?;
+            ping(address).map_err(|e| format!("Host stops serving: {}", e))
+        });
+    }
+
+    suite.check("Oversized length is refused without waiting for the body", || {
+        let mut connection = Connection::open(address)?;
+        let frame = connection.encode([0; 4], b"c:ping", Some(u32::MAX), &[]);
+        connection.stream.write_all(&frame).map_err(|e| e.to_string())?;
+        connection.expect_refused()
+    });
+
+    suite.check("Truncated body doesn't block other connections", || {
+        let mut connection = Connection::open(address)?;
+        let frame = connection.encode([0; 4], b"c:ping", Some(100), &[0; 10]);
+        connection.stream.write_all(&frame).map_err(|e| e.to_string())?;
+
+        ping(address)?;
+
+        connection.stream.shutdown(Shutdown::Write).map_err(|e| e.to_string())?;
+        connection.expect_refused()
+    });
+
+    let authenticated = |secret: Option<&str>| -> Result<Connection, String> {
+        let mut connection = Connection::open(address)?;
+        if supports(AUTH) {
+            connection.authenticate(secret.unwrap())?;
+        }
+        Ok(connection)
+    };
+    let requires_secret = supports(AUTH) && secret.is_none();
+
+    if requires_secret {
+        suite.skip("Unknown protocol fails with status 255", "Host requires authentication, but no secret is given.");
+    } else {
+        suite.check("Unknown protocol fails with status 255", || {
+            let (status, _) = authenticated(secret)?.request(UNKNOWN_PROTOCOL, &[])?;
+            ensure(status == 255, || format!("Status is {}.", status))
+        });
+    }
+
+    suite.group("Authentication");
+    if !supports(AUTH) {
+        suite.skip("Authentication", "Host doesn't advertise c:auth.");
+    } else {
+        suite.check("Unauthenticated requests are refused with status 66 before closing", || {
+            let mut connection = Connection::open(address)?;
+            let (status, _) = connection.request(UNKNOWN_PROTOCOL, &[])?;
+            ensure(status == STATUS_UNAUTHENTICATED, || format!("Status is {}.", status))?;
+            connection.send([0; 4], b"c:ping", &[])?;
+            ensure(matches!(connection.receive()?, Reply::Closed), || "Connection is still open.".to_string())
+        });
+
+        suite.check("Wrong proof is refused", || {
+            let mut connection = Connection::open(address)?;
+            let mut body = vec![auth::STEP_CHALLENGE];
+            body.extend_from_slice(&random_bytes::<{ auth::NONCE_LENGTH }>());
+            let (status, data) = connection.request(AUTH, &body)?;
+            ensure(status == 0, || format!("Challenge failed with status {}.", status))?;
+            ensure(data.len() == auth::NONCE_LENGTH + auth::PROOF_LENGTH, || format!("Challenge response has {} bytes.", data.len()))?;
+
+            let mut body = vec![auth::STEP_RESPONSE];
+            body.extend_from_slice(&[0; auth::PROOF_LENGTH]);
+            connection.send([0; 4], AUTH.as_bytes(), &body)?;
+            connection.expect_refused()
+        });
+
+        match secret {
+            None => suite.skip("Host proves knowing the secret, and frames are signed", "No secret is given."),
+            Some(secret) => {
+                suite.check("Host proves knowing the secret, and frames are signed", || {
+                    let mut connection = authenticated(Some(secret))?;
+                    let (status, data) = connection.request("c:ping", b"signed")?;
+                    ensure(status == 0 && data == b"signed", || format!("Status is {}.", status))
+                });
+
+                suite.check("Frames with invalid MAC are refused", || {
+                    let mut connection = authenticated(Some(secret))?;
+                    let mut frame = connection.encode([0; 4], b"c:ping", None, &[]);
+                    *frame.last_mut().unwrap() ^= 0xFF;
+                    connection.stream.write_all(&frame).map_err(|e| e.to_string())?;
+                    match connection.receive() {
+                        Ok(Reply::Response { status: 0, .. }) => Err("Host has accepted the request.".to_string()),
+                        _ => Ok(()),
+                    }
+                });
+            }
+        }
+    }
+
+    suite.group("Pipelining");
+    if !supports(PIPELINE) {
+        suite.skip("Pipelining", "Host doesn't advertise c:pipeline.");
+    } else if requires_secret {
+        suite.skip("Pipelining", "Host requires authentication, but no secret is given.");
+    } else {
+        suite.check("Pipelined responses carry request IDs", || {
+            let mut connection = authenticated(secret)?;
+            let (status, _) = connection.request(PIPELINE, &[])?;
+            ensure(status == 0, || format!("c:pipeline failed with status {}.", status))?;
+            connection.pipelined = true;
+
+            let requests = [[0x01, 0x02, 0x03, 0x04], [0, 0, 0, 7], [0xFF; 4]];
+            for request_id in requests {
+                connection.send(request_id, b"c:ping", &request_id)?;
+            }
+            let mut answered = vec![];
+            for _ in requests {
+                let Reply::Response { request_id: Some(request_id), status: 0, data } = connection.receive()? else {
+                    return Err("Request failed.".to_string());
+                };
+                ensure(data == request_id, || format!("Response of {:?} doesn't match its request.", request_id))?;
+                answered.push(request_id);
+            }
+            let mut requests = requests.to_vec();
+            answered.sort();
+            requests.sort();
+            ensure(answered == requests, || format!("Responses are {:?}.", answered))
+        });
+    }
+
+    suite.group("Room protocols");
+    if !room {
+        suite.skip("Room protocols", "Host isn't hosting a room.");
+        return;
+    } else if requires_secret {
+        suite.skip("Room protocols", "Host requires authentication, but no secret is given.");
+        return;
+    }
+
+    let machine_id = hex::encode(random_bytes::<16>());
+    let mut check = |protocol: &str, body: Value, verify: &dyn Fn(u8, &[u8]) -> Check| {
+        let name = format!("{} responds as specified", protocol);
+        if !supports(protocol) {
+            suite.skip(&name, "Host doesn't advertise it.");
+            return;
+        }
+        suite.check(&name, || {
+            let body = if body.is_null() { vec![] } else { serde_json::to_vec(&body).unwrap() };
+            let (status, data) = authenticated(secret)?.request(protocol, &body)?;
+            verify(status, &data)
+        });
+    };
+
+    check("c:server_port", Value::Null, &|status, data| {
+        ensure(status == 0 && data.len() == 2, || format!("Status is {}, with {} bytes.", status, data.len()))
+    });
+    check("c:server_edition", Value::Null, &|status, data| {
+        ensure(status == 0, || format!("Status is {}.", status))?;
+        let value = serde_json::from_slice::<Value>(data).map_err(|e| e.to_string())?;
+        ensure(
+            matches!(value.get("edition").and_then(Value::as_str), Some("java" | "bedrock")),
+            || format!("Edition is invalid: {}", value),
+        )
+    });
+    check("c:player_ping", json!({ "machine_id": machine_id, "name": "Conformance", "vendor": "Terracotta Conformance" }), &|status, _| {
+        ensure(status == 0, || format!("Status is {}.", status))
+    });
+    check("c:player_profiles_list", Value::Null, &|status, data| {
+        ensure(status == 0, || format!("Status is {}.", status))?;
+        let value = serde_json::from_slice::<Value>(data).map_err(|e| e.to_string())?;
+        let profiles = value.as_array().ok_or("Response isn't an array.")?;
+        let mut hosts = 0;
+        for profile in profiles {
+            for field in ["name", "machine_id", "vendor"] {
+                ensure(profile.get(field).is_some_and(Value::is_string), || format!("Profile {} lacks {}.", profile, field))?;
+            }
+            match profile.get("kind").and_then(Value::as_str) {
+                Some("HOST") => hosts += 1,
+                Some("GUEST") => {}
+                _ => return Err(format!("Profile {} has an invalid kind.", profile)),
+            }
+        }
+        ensure(hosts == 1, || format!("{} hosts are listed.", hosts))?;
+        ensure(
+            profiles.iter().any(|profile| profile.get("machine_id").and_then(Value::as_str) == Some(&machine_id)),
+            || "Player from c:player_ping isn't listed.".to_string(),
+        )
+    });
+    check("c:subscribe", json!({ "machine_id": machine_id, "cursor": null }), &|status, data| {
+        ensure(status == 0, || format!("Status is {}.", status))?;
+        let value = serde_json::from_slice::<Value>(data).map_err(|e| e.to_string())?;
+        ensure(
+            value.get("cursor").is_some_and(Value::is_u64) && value.get("events").is_some_and(Value::is_array)
+                && value.get("resync").is_some_and(Value::is_boolean),
+            || format!("Invalid response {}.", value),
+        )
+    });
+}
@@ -0,0 +1,273 @@
+//! `terracotta scaffolding` subcommand, debugging interoperability with other Scaffolding implementations.
+
+use crate::scaffolding::client::ClientSession;
+use crate::scaffolding::conformance::{self, Suite};
+use crate::scaffolding::limits::ServerLimits;
+use crate::scaffolding::server::{self, HandlerRegistry};
+use crate::scaffolding::{
+    PacketResponse, STATUS_FRAME_TOO_LARGE, STATUS_RATE_LIMITED, STATUS_TOO_MANY_CONNECTIONS, STATUS_UNAUTHENTICATED,
+};
+use parking_lot::Mutex;
+use rand_core::{OsRng, TryRngCore};
+use serde_json::{json, Value};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::OnceLock;
+use std::time::Instant;
+use std::{io, thread};
+
+const USAGE: &str = "\
+Usage: terracotta scaffolding <COMMAND>
+Commands:
+  call <HOST:PORT> <NAMESPACE:PATH> [--hex <BODY> | --json <BODY> | --raw <BODY>] [--secret <SECRET>]
+      Invoke a protocol and print the status and decoded response.
+  probe <HOST:PORT> [--secret <SECRET>]
+      Fetch c:protocols, verify c:ping with a random fingerprint, and negotiate the session if a secret is given.
+  serve-mock [PORT] [--secret <SECRET>]
+      Run a scaffolding server with canned responses.
+  conformance [HOST:PORT] [--secret <SECRET>]
+      Run the conformance suite against a host which is hosting a room.
+      Without an address, Terracotta's own implementation is checked in-process.";
+
+/// Run the subcommand with arguments following 'scaffolding'. Returns false if it fails.
+pub fn main(arguments: &[String]) -> bool {
+    let result = match arguments.first().map(String::as_str) {
+        Some("call") => call(&arguments[1..]),
+        Some("probe") => probe(&arguments[1..]),
+        Some("serve-mock") => serve_mock(&arguments[1..]),
+        Some("conformance") => return conformance_suite(&arguments[1..]),
+        _ => Err(invalid("Unknown command.")),
+    };
+
+    match result {
+        Ok(_) => true,
+        Err(e) if e.kind() == io::ErrorKind::InvalidInput => {
+            println!("{}\n{}", e, USAGE);
+            false
+        }
+        Err(e) => {
+            println!("Error: {}", e);
+            false
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message.to_string())
+}
+
+/// Positional arguments, and '--name value' options.
+type Arguments<'a> = (Vec<&'a str>, Vec<(&'a str, &'a str)>);
+
+fn parse_arguments(arguments: &[String]) -> io::Result<Arguments<'_>> {
+    let (mut positionals, mut options) = (vec![], vec![]);
+    let mut iterator = arguments.iter();
+    while let Some(argument) = iterator.next() {
+        if let Some(name) = argument.strip_prefix("--") {
+            let value = iterator.next().ok_or_else(|| invalid(&format!("Missing value of --{}.", name)))?;
+            options.push((name, value.as_str()));
+        } else {
+            positionals.push(argument.as_str());
+        }
+    }
+    Ok((positionals, options))
+}
+
+fn option<'a>(options: &[(&str, &'a str)], name: &str) -> Option<&'a str> {
+    options.iter().find(|(n, _)| *n == name).map(|(_, value)| *value)
+}
+
+fn connect(address: &str, secret: Option<&str>) -> io::Result<ClientSession> {
+    let address: SocketAddr = address.to_socket_addrs()?
+        .next()
+        .ok_or_else(|| invalid(&format!("Cannot resolve {}.", address)))?;
+    let mut session = ClientSession::open(address.ip(), address.port())?;
+
+    if let Some(secret) = secret {
+        let pipelined = session.negotiate(secret)?;
+        println!("Negotiated: authenticated={}, pipelined={}", session.supports("c:auth"), pipelined);
+    }
+    Ok(session)
+}
+
+fn call(arguments: &[String]) -> io::Result<()> {
+    let (positionals, options) = parse_arguments(arguments)?;
+    let [address, kind] = positionals[..] else {
+        return Err(invalid("Expecting <HOST:PORT> and <NAMESPACE:PATH>."));
+    };
+    let Some((namespace, path)) = kind.split_once(':') else {
+        return Err(invalid("Protocol must be in 'namespace:path' format."));
+    };
+
+    let body = match options.iter().find(|(name, _)| matches!(*name, "hex" | "json" | "raw")) {
+        Some(("hex", body)) => hex::decode(body.replace(char::is_whitespace, "")).map_err(|e| invalid(&format!("Invalid hex body: {}", e)))?,
+        Some(("json", body)) => serde_json::to_vec(&serde_json::from_str::<Value>(body).map_err(|e| invalid(&format!("Invalid JSON body: {}", e)))?)?,
+        Some((_, body)) => body.as_bytes().to_vec(),
+        None => vec![],
+    };
+
+    let mut session = connect(address, option(&options, "secret"))?;
+    let start = Instant::now();
+    let response = session.invoke((namespace, path), |request| request.extend_from_slice(&body))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::ConnectionAborted, "Session has been closed."))?;
+    println!("Time: {}ms", start.elapsed().as_millis());
+
+    print_response(&response);
+    Ok(())
+}
+
+fn probe(arguments: &[String]) -> io::Result<()> {
+    let (positionals, options) = parse_arguments(arguments)?;
+    let [address] = positionals[..] else {
+        return Err(invalid("Expecting <HOST:PORT>."));
+    };
+
+    let mut session = connect(address, None)?;
+    let closed = || io::Error::new(io::ErrorKind::ConnectionAborted, "Session has been closed.");
+
+    println!("> c:protocols");
+    print_response(&session.invoke(("c", "protocols"), |_| {}).ok_or_else(closed)?);
+
+    let mut fingerprint = [0u8; 16];
+    OsRng.try_fill_bytes(&mut fingerprint).unwrap();
+    println!("> c:ping {}", hex::encode(fingerprint));
+    let start = Instant::now();
+    let response = session.invoke(("c", "ping"), |request| request.extend_from_slice(&fingerprint)).ok_or_else(closed)?;
+    let elapsed = start.elapsed();
+    print_response(&response);
+    match response {
+        PacketResponse::Ok { data } if data == fingerprint => println!("Fingerprint verified in {}ms.", elapsed.as_millis()),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "Host didn't echo the fingerprint.")),
+    }
+
+    if let Some(secret) = option(&options, "secret") {
+        drop(session);
+        connect(address, Some(secret))?;
+    }
+    Ok(())
+}
+
+fn conformance_suite(arguments: &[String]) -> bool {
+    let address = parse_arguments(arguments).and_then(|(positionals, options)| {
+        let [address] = positionals[..] else {
+            return Err(invalid("Expecting <HOST:PORT>."));
+        };
+        let address: SocketAddr = address.to_socket_addrs()?
+            .next()
+            .ok_or_else(|| invalid(&format!("Cannot resolve {}.", address)))?;
+        Ok((address, option(&options, "secret")))
+    });
+
+    match address {
+        Ok((address, secret)) => {
+            let mut suite = Suite::new();
+            conformance::run(&mut suite, address, secret, true);
+            suite.finish()
+        }
+        Err(e) => {
+            println!("{}\n{}", e, USAGE);
+            false
+        }
+    }
+}
+
+fn print_response(response: &PacketResponse) {
+    let (status, data) = match response {
+        PacketResponse::Ok { data } => (0, data),
+        PacketResponse::Fail { status, data } => (*status, data),
+    };
+    let name = match status {
+        0 => "OK",
+        STATUS_UNAUTHENTICATED => "UNAUTHENTICATED",
+        STATUS_FRAME_TOO_LARGE => "FRAME_TOO_LARGE",
+        STATUS_TOO_MANY_CONNECTIONS => "TOO_MANY_CONNECTIONS",
+        STATUS_RATE_LIMITED => "RATE_LIMITED",
+        255 => "ERROR",
+        _ => "UNKNOWN",
+    };
+    println!("Status: {} ({}), {} bytes", status, name, data.len());
+
+    if data.is_empty() {
+        return;
+    }
+    if let Ok(value) = serde_json::from_slice::<Value>(data) {
+        println!("{}", serde_json::to_string_pretty(&value).unwrap());
+    } else if let Ok(text) = str::from_utf8(data)
+        && !text.chars().any(|c| c.is_control() && !matches!(c, '\0' | '\n' | '\t'))
+    {
+        // Lists, such as c:protocols, are separated by '\0'.
+        for line in text.split('\0') {
+            println!("{}", line);
+        }
+    } else {
+        for line in data.chunks(16) {
+            println!("{}", line.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>().join(" "));
+        }
+    }
+}
+
+const MOCK_SERVER_PORT: u16 = 25565;
+static MOCK_SECRET: OnceLock<String> = OnceLock::new();
+/// Players which have sent c:player_ping, as (machine_id, name, vendor). They never expire.
+static MOCK_PLAYERS: Mutex<Vec<(String, String, String)>> = Mutex::new(vec![]);
+
+static MOCK_HANDLERS: HandlerRegistry = HandlerRegistry::new(&[
+    ("c", "ping", |request, mut response| {
+        response.extend_from_slice(request);
+        PacketResponse::ok(response)
+    }),
+    ("c", "protocols", |_, mut response| {
+        response.extend_from_slice(MOCK_HANDLERS.protocols().join("\0").as_bytes());
+        PacketResponse::ok(response)
+    }),
+    ("c", "auth", |_, response| PacketResponse::ok(response)),
+    ("c", "pipeline", |_, response| PacketResponse::ok(response)),
+    ("c", "server_port", |_, mut response| {
+        response.extend_from_slice(&MOCK_SERVER_PORT.to_be_bytes());
+        PacketResponse::ok(response)
+    }),
+    ("c", "player_ping", |request, response| {
+        logging!("ScaffoldingMock", "c:player_ping {}", String::from_utf8_lossy(request));
+        let value: Value = serde_json::from_slice(request)?;
+        let field = |name: &str| value.get(name).and_then(Value::as_str).map(str::to_string).ok_or(io::Error::from(io::ErrorKind::InvalidInput));
+        let (machine_id, name, vendor) = (field("machine_id")?, field("name")?, field("vendor")?);
+
+        let mut players = MOCK_PLAYERS.lock();
+        players.retain(|(m, _, _)| *m != machine_id);
+        players.push((machine_id, name, vendor));
+        PacketResponse::ok(response)
+    }),
+    ("c", "player_profiles_list", |_, mut response| {
+        let mut profiles = vec![json!({
+            "name": "Mock Host",
+            "machine_id": "00000000000000000000000000000000",
+            "vendor": "Terracotta Mock",
+            "kind": "HOST",
+        })];
+        profiles.extend(MOCK_PLAYERS.lock().iter().map(|(machine_id, name, vendor)| json!({
+            "name": name,
+            "machine_id": machine_id,
+            "vendor": vendor,
+            "kind": "GUEST",
+        })));
+        serde_json::to_writer(&mut response, &profiles)?;
+        PacketResponse::ok(response)
+    }),
+]);
+
+fn serve_mock(arguments: &[String]) -> io::Result<()> {
+    let (positionals, options) = parse_arguments(arguments)?;
+    let port = match positionals[..] {
+        [] => 0,
+        [port] => port.parse().map_err(|_| invalid("Invalid port."))?,
+        _ => return Err(invalid("Expecting at most one port.")),
+    };
+    let secret = MOCK_SECRET.get_or_init(|| option(&options, "secret").unwrap_or("MOCK").to_string());
+
+    let port = server::start(&MOCK_HANDLERS, || MOCK_SECRET.get().cloned(), ServerLimits::default(), port)?;
+    println!("Mock scaffolding server is listening on port {}, secret={}.", port, secret);
+    println!("Protocols: {}", MOCK_HANDLERS.protocols().join(", "));
+
+    loop {
+        thread::park();
+    }
+}
@@ -0,0 +1,112 @@
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Limits protecting scaffolding server from misbehaving peers.
+#[derive(Debug, Clone)]
+pub struct ServerLimits {
+    /// Maximum size of request bodies in bytes.
+    pub max_frame_size: usize,
+    /// Connections beyond this are refused with STATUS_TOO_MANY_CONNECTIONS.
+    pub max_connections: usize,
+    /// Requests allowed per second from each peer. Bursts up to twice as many are allowed.
+    pub requests_per_second: u32,
+    /// Connections without any request for this duration are closed.
+    pub idle_timeout: Duration,
+    /// Requests handled concurrently on each pipelined connection. Further requests aren't read until some of them finish.
+    pub max_in_flight: usize,
+    /// Threads handling requests concurrently. Further requests wait until one of them is free.
+    pub max_workers: usize,
+    /// Serve connections which never request c:auth without MAC, for older Terracotta and clients of other vendors.
+    /// It's off by default, as any peer reaching the port would be served without knowing the room secret.
+    pub allow_unauthenticated: bool,
+}
+
+impl Default for ServerLimits {
+    fn default() -> Self {
+        ServerLimits {
+            max_frame_size: 256 * 1024,
+            max_connections: 64,
+            requests_per_second: 20,
+            idle_timeout: Duration::from_secs(30),
+            max_in_flight: 16,
+            max_workers: 16,
+            allow_unauthenticated: false,
+        }
+    }
+}
+
+/// Peers are told apart by their addresses, except those connecting from loopback. EasyTier runs without TUN, so that
+/// it connects to scaffolding server from loopback on behalf of every guest. Each of these connections is limited on
+/// its own instead, rather than all guests sharing a single rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum Peer {
+    Address(IpAddr),
+    Connection(usize),
+}
+
+impl Peer {
+    pub(crate) fn of(address: IpAddr, connection: usize) -> Peer {
+        if address.is_loopback() {
+            Peer::Connection(connection)
+        } else {
+            Peer::Address(address)
+        }
+    }
+}
+
+pub(crate) struct Limiter {
+    pub(crate) limits: ServerLimits,
+    connections: AtomicUsize,
+    /// Remaining tokens and the last time they were refilled, for each peer.
+    buckets: Mutex<HashMap<Peer, (f64, Instant)>>,
+}
+
+/// Occupies a connection slot until dropped.
+pub(crate) struct ConnectionGuard(Arc<Limiter>);
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.0.connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Limiter {
+    pub(crate) fn new(limits: ServerLimits) -> Arc<Limiter> {
+        Arc::new(Limiter { limits, connections: AtomicUsize::new(0), buckets: Mutex::new(HashMap::new()) })
+    }
+
+    pub(crate) fn connect(self: &Arc<Self>) -> Option<ConnectionGuard> {
+        self.connections
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| (count < self.limits.max_connections).then_some(count + 1))
+            .ok()
+            .map(|_| ConnectionGuard(self.clone()))
+    }
+
+    /// Take a token for a request from the peer, returning false if the peer has exceeded its rate.
+    pub(crate) fn acquire(&self, peer: Peer) -> bool {
+        let rate = self.limits.requests_per_second as f64;
+        let capacity = rate * 2.0;
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock();
+        // Forget peers which have been quiet long enough to refill their buckets.
+        if buckets.len() >= self.limits.max_connections * 4 {
+            buckets.retain(|_, (tokens, time)| *tokens + now.duration_since(*time).as_secs_f64() * rate < capacity);
+        }
+
+        let (tokens, time) = buckets.entry(peer).or_insert((capacity, now));
+        *tokens = (*tokens + now.duration_since(*time).as_secs_f64() * rate).min(capacity);
+        *time = now;
+
+        if *tokens >= 1.0 {
+            *tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -4,8 +4,33 @@ use std::time::Duration;
 pub mod client;
 pub mod server;
 pub mod profile;
+pub mod limits;
+#[cfg(test)]
+mod bench;
+#[cfg(not(target_os = "android"))]
+pub mod debug;
+#[cfg(not(target_os = "android"))]
+pub mod conformance;
+mod auth;
 
 pub(crate) static TIMEOUT: Duration = Duration::from_secs(64);
+/// Requesting this protocol switches the connection to the pipelined format, if host supports it.
+pub(crate) static PIPELINE: &str = "c:pipeline";
+/// Handshake proving both sides know the room secret. Once it succeeds, every frame is followed by a MAC.
+pub(crate) static AUTH: &str = "c:auth";
+/// Protocols which can be requested before authentication, as they are required for negotiation.
+pub(crate) static PUBLIC_PROTOCOLS: [&str; 3] = ["c:ping", "c:protocols", "c:auth"];
+
+/// Returned when a protocol other than PUBLIC_PROTOCOLS is requested before the c:auth handshake has finished, which
+/// closes the connection then. Connections which never request c:auth are only served if host opts in through
+/// ServerLimits::allow_unauthenticated, so that older Terracotta and clients of other vendors keep working.
+pub const STATUS_UNAUTHENTICATED: u8 = 66;
+/// Returned when a request body exceeds the maximum frame size, before the connection is closed.
+pub const STATUS_FRAME_TOO_LARGE: u8 = 67;
+/// Returned when host has reached the maximum number of connections, before the connection is closed.
+pub const STATUS_TOO_MANY_CONNECTIONS: u8 = 68;
+/// Returned instead of handling the request when the peer sends requests too fast.
+pub const STATUS_RATE_LIMITED: u8 = 69;
 
 pub enum PacketResponse {
     Ok { data: Vec<u8> },
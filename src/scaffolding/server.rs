@@ -1,71 +1,734 @@
-use crate::scaffolding::{PacketResponse, TIMEOUT};
+use crate::scaffolding::auth::{self, FrameMac, FrameMacs};
+use crate::scaffolding::limits::{ConnectionGuard, Limiter, Peer, ServerLimits};
+use crate::scaffolding::{
+    PacketResponse, AUTH, PIPELINE, PUBLIC_PROTOCOLS, STATUS_FRAME_TOO_LARGE, STATUS_RATE_LIMITED, STATUS_TOO_MANY_CONNECTIONS,
+    STATUS_UNAUTHENTICATED, TIMEOUT,
+};
+use mio::net::{TcpListener, TcpStream};
+use mio::{Events, Interest, Poll, Token, Waker};
+use parking_lot::{Condvar, Mutex, RwLock};
+use rand_core::{OsRng, TryRngCore};
 use socket2::{Domain, SockAddr, Socket, Type};
+use std::collections::{HashMap, VecDeque};
 use std::io::{Read, Write};
-use std::net::{Ipv4Addr, SocketAddrV4, TcpListener, TcpStream};
+use std::net::{IpAddr, Ipv4Addr, SocketAddrV4};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
 use std::{io, thread};
 
 pub type HandleFunction = fn(&[u8], Vec<u8>) -> io::Result<PacketResponse>;
 pub type Handlers = &'static [(&'static str, &'static str, HandleFunction)];
+/// Handlers registered at runtime, which may capture state.
+pub type HandleClosure = Arc<dyn Fn(&[u8], Vec<u8>) -> io::Result<PacketResponse> + Send + Sync>;
+/// Handlers of long polls, which are answered once something happens. Instead of blocking a worker, they return None
+/// to park the request on the event loop, which invokes them again whenever HandlerRegistry::notify is called. Once the
+/// timeout of the long poll elapses, they are invoked with expired set and must answer.
+pub type PollFunction = fn(&[u8], Vec<u8>, bool) -> io::Result<Option<PacketResponse>>;
+pub type Polls = &'static [(&'static str, &'static str, PollFunction, Duration)];
+/// Provides the secret of current room, or None if no room is available.
+pub type SecretFunction = fn() -> Option<String>;
 
-pub fn start(handlers: Handlers, port: u16) -> io::Result<u16> {
+/// Built-in handlers, together with handlers registered at runtime by embedders for their own namespaces.
+pub struct HandlerRegistry {
+    builtin: Handlers,
+    polls: Polls,
+    registered: RwLock<Vec<(String, String, HandleClosure)>>,
+    /// Number of notifications, which lets event loops tell whether parked long polls should be invoked again.
+    notifications: AtomicU64,
+    /// Wakers of event loops serving these handlers.
+    wakers: Mutex<Vec<Arc<Waker>>>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RegistryError {
+    /// Namespace or path is empty, contains ':' or '\0', or the protocol name exceeds 255 bytes.
+    InvalidName,
+    /// Namespace is used by built-in handlers.
+    ReservedNamespace,
+    Conflict,
+}
+
+enum Handle {
+    Builtin(HandleFunction),
+    Poll(PollFunction),
+    Registered(HandleClosure),
+}
+
+impl HandlerRegistry {
+    pub const fn new(builtin: Handlers) -> HandlerRegistry {
+        HandlerRegistry::with_polls(builtin, &[])
+    }
+
+    pub const fn with_polls(builtin: Handlers, polls: Polls) -> HandlerRegistry {
+        HandlerRegistry {
+            builtin,
+            polls,
+            registered: RwLock::new(vec![]),
+            notifications: AtomicU64::new(0),
+            wakers: Mutex::new(vec![]),
+        }
+    }
+
+    pub fn register<F>(&self, namespace: &str, path: &str, handle: F) -> Result<(), RegistryError>
+    where
+        F: Fn(&[u8], Vec<u8>) -> io::Result<PacketResponse> + Send + Sync + 'static,
+    {
+        let valid = |name: &str| !name.is_empty() && !name.contains([':', '\0']);
+        if !valid(namespace) || !valid(path) || namespace.len() + path.len() + 1 > u8::MAX as usize {
+            return Err(RegistryError::InvalidName);
+        }
+        if self.builtin.iter().any(|(n, _, _)| *n == namespace) || self.polls.iter().any(|(n, _, _, _)| *n == namespace) {
+            return Err(RegistryError::ReservedNamespace);
+        }
+
+        let mut registered = self.registered.write();
+        if registered.iter().any(|(n, p, _)| n == namespace && p == path) {
+            return Err(RegistryError::Conflict);
+        }
+        registered.push((namespace.to_string(), path.to_string(), Arc::new(handle)));
+
+        logging!("ScaffoldingServer", "Registered handler {}:{}.", namespace, path);
+        Ok(())
+    }
+
+    /// Remove a handler registered at runtime. Returns false if it doesn't exist.
+    pub fn unregister(&self, namespace: &str, path: &str) -> bool {
+        let mut registered = self.registered.write();
+        let length = registered.len();
+        registered.retain(|(n, p, _)| n != namespace || p != path);
+        registered.len() != length
+    }
+
+    /// All protocols in 'namespace:path' format, which are advertised through c:protocols.
+    pub fn protocols(&self) -> Vec<String> {
+        let mut protocols = self.builtin.iter()
+            .map(|(namespace, path, _)| format!("{}:{}", namespace, path))
+            .chain(self.polls.iter().map(|(namespace, path, _, _)| format!("{}:{}", namespace, path)))
+            .collect::<Vec<_>>();
+        protocols.extend(self.registered.read().iter().map(|(namespace, path, _)| format!("{}:{}", namespace, path)));
+        protocols
+    }
+
+    /// Invoke parked long polls again, as what they are waiting for may have happened.
+    pub fn notify(&self) {
+        self.notifications.fetch_add(1, Ordering::SeqCst);
+        for waker in self.wakers.lock().iter() {
+            let _ = waker.wake();
+        }
+    }
+
+    fn find(&self, namespace: &str, path: &str) -> Option<Handle> {
+        if let Some((_, _, handle)) = self.builtin.iter().find(|(n, p, _)| namespace == *n && path == *p) {
+            return Some(Handle::Builtin(*handle));
+        }
+        if let Some((_, _, handle, _)) = self.polls.iter().find(|(n, p, _, _)| namespace == *n && path == *p) {
+            return Some(Handle::Poll(*handle));
+        }
+
+        self.registered.read().iter()
+            .find(|(n, p, _)| namespace == n && path == p)
+            .map(|(_, _, handle)| Handle::Registered(handle.clone()))
+    }
+
+    fn poll_timeout(&self, kind: &str) -> Option<Duration> {
+        let (namespace, path) = kind.split_once(':')?;
+        self.polls.iter().find(|(n, p, _, _)| namespace == *n && path == *p).map(|(_, _, _, timeout)| *timeout)
+    }
+}
+
+/// Start scaffolding server on a single event loop thread.
+///
+/// Connections are multiplexed on the event loop, so idle guests don't occupy any thread. As handlers are blocking,
+/// requests are handed over to a pool of worker threads, which grows with requests being handled up to
+/// ServerLimits::max_workers and shrinks once idle. Long polls are parked on the event loop without occupying workers.
+pub fn start(handlers: &'static HandlerRegistry, secret: SecretFunction, limits: ServerLimits, port: u16) -> io::Result<u16> {
     let socket = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
     socket.bind(&SockAddr::from(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port)))?;
-    socket.set_read_timeout(Some(TIMEOUT)).unwrap();
-    socket.set_write_timeout(Some(TIMEOUT)).unwrap();
     socket.listen(128)?;
+    socket.set_nonblocking(true)?;
 
     let port = socket.local_addr().unwrap().as_socket().unwrap().port();
 
-    thread::spawn(move || {
-        let listener: TcpListener = socket.into();
-        for mut stream in listener.incoming().flatten() {
-            thread::spawn(move || {
-                loop {
-                    if let Err(e) = handle_connection(&mut stream, handlers) {
-                        logging!("ScaffoldingServer", "Connection closed: {:?}", e);
-                        return;
-                    }
+    let poll = Poll::new()?;
+    let mut listener = TcpListener::from_std(socket.into());
+    poll.registry().register(&mut listener, LISTENER, Interest::READABLE)?;
+    let waker = Arc::new(Waker::new(poll.registry(), WAKER)?);
+    handlers.wakers.lock().push(waker.clone());
+    let (sender, receiver) = mpsc::channel();
+
+    let mut server = EventLoop {
+        poll,
+        listener,
+        connections: HashMap::new(),
+        next_token: FIRST_CONNECTION,
+        handlers,
+        secret,
+        workers: Workers::new(limits.max_workers),
+        limiter: Limiter::new(limits),
+        completions: (sender, receiver),
+        waker,
+        parked: vec![],
+        notifications: handlers.notifications.load(Ordering::SeqCst),
+    };
+    thread::spawn(move || server.run());
+
+    Ok(port)
+}
+
+const LISTENER: Token = Token(0);
+const WAKER: Token = Token(1);
+const FIRST_CONNECTION: usize = 2;
+/// Interval of checking idle connections and stalled writes.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A request handed over to worker threads, or parked on the event loop until its long poll can be answered.
+struct Request {
+    token: Token,
+    kind: String,
+    request_id: Option<[u8; 4]>,
+    body: Vec<u8>,
+    /// When the long poll must be answered, which is set once the request is parked.
+    deadline: Option<Instant>,
+}
+
+/// A request handled by a worker thread, sent back to the event loop.
+struct Completion {
+    request: Request,
+    /// Encoded response, or None if the request is a long poll to be parked.
+    response: Option<Vec<u8>>,
+    /// Number of notifications of handlers when the request was handled.
+    notifications: u64,
+}
+
+struct EventLoop {
+    poll: Poll,
+    listener: TcpListener,
+    connections: HashMap<Token, Connection>,
+    /// Tokens aren't reused, so that completions of closed connections can't be delivered to new ones.
+    next_token: usize,
+    handlers: &'static HandlerRegistry,
+    secret: SecretFunction,
+    limiter: Arc<Limiter>,
+    workers: Arc<Workers>,
+    completions: (mpsc::Sender<Completion>, mpsc::Receiver<Completion>),
+    waker: Arc<Waker>,
+    /// Long polls waiting for notifications of handlers. They are counted as in flight on their connections.
+    parked: Vec<Request>,
+    /// Number of notifications of handlers the parked requests have been invoked after.
+    notifications: u64,
+}
+
+impl EventLoop {
+    fn run(&mut self) {
+        let mut events = Events::with_capacity(256);
+        let mut last_sweep = Instant::now();
+        loop {
+            let timeout = self.parked.iter()
+                .filter_map(|request| request.deadline)
+                .min()
+                .map_or(SWEEP_INTERVAL, |deadline| deadline.saturating_duration_since(Instant::now()).min(SWEEP_INTERVAL));
+            if let Err(e) = self.poll.poll(&mut events, Some(timeout)) {
+                if e.kind() != io::ErrorKind::Interrupted {
+                    logging!("ScaffoldingServer", "Cannot poll events: {:?}", e);
+                }
+                continue;
+            }
+
+            for event in events.iter() {
+                match event.token() {
+                    LISTENER => self.accept(),
+                    WAKER => {}
+                    token => self.drive(token),
+                }
+            }
+
+            while let Ok(completion) = self.completions.1.try_recv() {
+                self.complete(completion);
+            }
+            self.resume();
+
+            if last_sweep.elapsed() >= SWEEP_INTERVAL {
+                last_sweep = Instant::now();
+                self.sweep();
+            }
+        }
+    }
+
+    fn accept(&mut self) {
+        loop {
+            let (mut stream, address) = match self.listener.accept() {
+                Ok(accepted) => accepted,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return,
+                Err(e) => {
+                    logging!("ScaffoldingServer", "Cannot accept connection: {:?}", e);
+                    return;
                 }
+            };
+
+            let Some(guard) = self.limiter.connect() else {
+                logging!("ScaffoldingServer", "Rejecting connection from {}: Too many connections.", address);
+                // Socket buffer of a new connection is empty, so the whole response can be written without blocking.
+                let _ = stream.write_all(&encode_response(PacketResponse::fail(STATUS_TOO_MANY_CONNECTIONS, reserved(b"Too many connections."))));
+                continue;
+            };
+
+            let token = Token(self.next_token);
+            self.next_token += 1;
+            if let Err(e) = self.poll.registry().register(&mut stream, token, Interest::READABLE | Interest::WRITABLE) {
+                logging!("ScaffoldingServer", "Cannot register connection from {}: {:?}", address, e);
+                continue;
+            }
+
+            self.connections.insert(token, Connection {
+                stream,
+                peer: address.ip(),
+                _guard: guard,
+                input: vec![],
+                output: vec![],
+                unread: false,
+                pipelined: false,
+                in_flight: 0,
+                closing: false,
+                last_active: Instant::now(),
+                last_written: Instant::now(),
+                challenge: None,
+                macs: None,
             });
         }
-    });
+    }
 
-    Ok(port)
+    /// Read requests, dispatch them and flush responses of a connection, as far as it can go without blocking.
+    fn drive(&mut self, token: Token) {
+        if !self.connections.contains_key(&token) {
+            return;
+        }
+
+        let result = self.pump(token);
+        let connection = &self.connections[&token];
+        match result {
+            Err(e) => self.close(token, e),
+            Ok(_) if connection.closing && connection.output.is_empty() => {
+                self.close(token, io::Error::new(io::ErrorKind::ConnectionAborted, "Connection is closed by server."))
+            }
+            Ok(_) => {}
+        }
+    }
+
+    fn pump(&mut self, token: Token) -> io::Result<()> {
+        let max_frame_size = self.limiter.limits.max_frame_size;
+        loop {
+            self.connections.get_mut(&token).unwrap().read(max_frame_size)?;
+            if !self.process(token)? {
+                break;
+            }
+        }
+        self.connections.get_mut(&token).unwrap().flush()
+    }
+
+    /// Handle requests available in the input buffer. Returns true if more input should be read.
+    fn process(&mut self, token: Token) -> io::Result<bool> {
+        let limits = &self.limiter.limits;
+        let connection = self.connections.get_mut(&token).unwrap();
+        let capacity = if connection.pipelined { limits.max_in_flight } else { 1 };
+
+        let mut requests = vec![];
+        while !connection.closing && connection.in_flight < capacity {
+            let request = match connection.parse_request(limits.max_frame_size)? {
+                Frame::Incomplete => break,
+                Frame::TooLarge(request_id) => {
+                    connection.respond(request_id, &encode_response(PacketResponse::fail(STATUS_FRAME_TOO_LARGE, reserved(b"Request is too large."))));
+                    connection.closing = true;
+                    break;
+                }
+                Frame::Request(request) => request,
+            };
+            let (request_id, kind, body) = request;
+
+            if kind == AUTH && !connection.pipelined {
+                let (response, authenticated) = connection.authenticate(self.secret, &body)?;
+                connection.respond(request_id, &response);
+
+                // Response of the last handshake step is sent without MAC, as client cannot verify it until then.
+                if let Some(macs) = authenticated {
+                    connection.macs = Some(macs);
+                }
+            } else if connection.macs.is_none()
+                && (connection.challenge.is_some() || !limits.allow_unauthenticated)
+                && !PUBLIC_PROTOCOLS.contains(&kind.as_str())
+            {
+                logging!("ScaffoldingServer", "Rejecting unauthenticated request {} from {}.", kind, connection.peer);
+                connection.respond(request_id, &encode_response(PacketResponse::fail(STATUS_UNAUTHENTICATED, reserved(b"Authentication is required."))));
+                connection.closing = true;
+            } else if !self.limiter.acquire(Peer::of(connection.peer, token.0)) {
+                connection.respond(request_id, &encode_response(PacketResponse::fail(STATUS_RATE_LIMITED, reserved(b"Too many requests."))));
+            } else {
+                connection.in_flight += 1;
+                requests.push(Request { token, kind, request_id, body, deadline: None });
+            }
+        }
+
+        let more = connection.unread && !connection.closing && connection.input.len() < connection.input_capacity(limits.max_frame_size);
+        for request in requests {
+            self.submit(request, false);
+        }
+        Ok(more)
+    }
+
+    fn submit(&self, request: Request, expired: bool) {
+        let (handlers, sender, waker) = (self.handlers, self.completions.0.clone(), self.waker.clone());
+        self.workers.submit(Box::new(move || {
+            let notifications = handlers.notifications.load(Ordering::SeqCst);
+            let response = invoke(&request.kind, &request.body, handlers, expired);
+            if sender.send(Completion { request, response, notifications }).is_ok() {
+                let _ = waker.wake();
+            }
+        }));
+    }
+
+    fn complete(&mut self, completion: Completion) {
+        let Completion { mut request, response, notifications } = completion;
+        if !self.connections.contains_key(&request.token) {
+            return;
+        }
+        let Some(response) = response else {
+            let timeout = self.handlers.poll_timeout(&request.kind).unwrap_or_default();
+            request.deadline.get_or_insert_with(|| Instant::now() + timeout);
+            // Notifications may have arrived while the long poll was being handled, which it may have missed.
+            if notifications != self.handlers.notifications.load(Ordering::SeqCst) {
+                self.submit(request, false);
+            } else {
+                self.parked.push(request);
+            }
+            return;
+        };
+
+        let Request { token, kind, request_id, .. } = request;
+        let connection = self.connections.get_mut(&token).unwrap();
+
+        connection.in_flight -= 1;
+        connection.last_active = Instant::now();
+        connection.respond(request_id, &response);
+        if !connection.pipelined && kind == PIPELINE && response[0] == 0 {
+            connection.pipelined = true;
+        }
+
+        // Requests left in the input buffer or socket may be waiting for this one to finish.
+        self.drive(token);
+    }
+
+    /// Invoke parked long polls again if handlers have been notified, or answer them if they have expired.
+    fn resume(&mut self) {
+        let notifications = self.handlers.notifications.load(Ordering::SeqCst);
+        let notified = std::mem::replace(&mut self.notifications, notifications) != notifications;
+        let now = Instant::now();
+
+        for request in std::mem::take(&mut self.parked) {
+            if !self.connections.contains_key(&request.token) {
+                continue;
+            }
+            let expired = request.deadline.is_some_and(|deadline| deadline <= now);
+            if notified || expired {
+                self.submit(request, expired);
+            } else {
+                self.parked.push(request);
+            }
+        }
+    }
+
+    fn sweep(&mut self) {
+        let idle_timeout = self.limiter.limits.idle_timeout;
+        let expired = self.connections.iter()
+            .filter_map(|(token, connection)| {
+                if !connection.output.is_empty() && connection.last_written.elapsed() >= TIMEOUT {
+                    Some((*token, "Response cannot be sent in time."))
+                } else if connection.in_flight == 0 && connection.output.is_empty() && connection.last_active.elapsed() >= idle_timeout {
+                    Some((*token, "Connection is idle."))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        for (token, reason) in expired {
+            self.close(token, io::Error::new(io::ErrorKind::TimedOut, reason));
+        }
+    }
+
+    fn close(&mut self, token: Token, error: io::Error) {
+        if let Some(mut connection) = self.connections.remove(&token) {
+            let _ = self.poll.registry().deregister(&mut connection.stream);
+            logging!("ScaffoldingServer", "Connection closed: {:?}", error);
+        }
+    }
+}
+
+enum Frame {
+    Incomplete,
+    /// Body of the request exceeds max_frame_size. Request ID is available in the pipelined format.
+    TooLarge(Option<[u8; 4]>),
+    Request((Option<[u8; 4]>, String, Vec<u8>)),
+}
+
+struct Connection {
+    stream: TcpStream,
+    peer: IpAddr,
+    _guard: ConnectionGuard,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    /// Whether reading stopped before the socket is drained, as input buffer is full.
+    unread: bool,
+    // In the pipelined format, every request and response is prefixed with a 4-byte request ID chosen by client.
+    // Requests are handled concurrently, so responses may be sent in a different order.
+    pipelined: bool,
+    /// Requests being handled by workers or parked. Requests beyond ServerLimits::max_in_flight, or any request in the serial
+    /// format, aren't parsed until some of them finish, which eventually throttles the peer through TCP.
+    in_flight: usize,
+    /// Whether connection should be closed once pending responses are sent.
+    closing: bool,
+    last_active: Instant,
+    last_written: Instant,
+    /// Nonces and secret of an ongoing c:auth handshake.
+    challenge: Option<([u8; auth::NONCE_LENGTH], [u8; auth::NONCE_LENGTH], String)>,
+    /// MACs for requests and responses, available once the connection is authenticated.
+    macs: Option<FrameMacs>,
+}
+
+impl Connection {
+    /// Input buffer is large enough to hold a request with the maximum frame size.
+    fn input_capacity(&self, max_frame_size: usize) -> usize {
+        4 + 1 + u8::MAX as usize + 4 + max_frame_size + auth::MAC_LENGTH
+    }
+
+    fn read(&mut self, max_frame_size: usize) -> io::Result<()> {
+        let capacity = self.input_capacity(max_frame_size);
+        let mut buffer = [0u8; 4096];
+
+        self.unread = true;
+        while self.input.len() < capacity {
+            let length = buffer.len().min(capacity - self.input.len());
+            match self.stream.read(&mut buffer[..length]) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Connection is closed by peer.")),
+                Ok(length) => {
+                    self.input.extend_from_slice(&buffer[..length]);
+                    self.last_active = Instant::now();
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    self.unread = false;
+                    break;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_request(&mut self, max_frame_size: usize) -> io::Result<Frame> {
+        let prefix = if self.pipelined { 4 } else { 0 };
+        let input = &self.input;
+        let request_id = (self.pipelined && input.len() >= 4).then(|| input[..4].try_into().unwrap());
+
+        let Some(&kind_size) = input.get(prefix) else {
+            return Ok(Frame::Incomplete);
+        };
+        let kind_end = prefix + 1 + kind_size as usize;
+        let Some(body_size) = input.get(kind_end..kind_end + 4) else {
+            return Ok(Frame::Incomplete);
+        };
+        let size = u32::from_be_bytes(body_size.try_into().unwrap()) as usize;
+        if size > max_frame_size {
+            return Ok(Frame::TooLarge(request_id));
+        }
+
+        let body_end = kind_end + 4 + size;
+        let frame_end = body_end + if self.macs.is_some() { auth::MAC_LENGTH } else { 0 };
+        if input.len() < frame_end {
+            return Ok(Frame::Incomplete);
+        }
+
+        if let Some((mac, _)) = &mut self.macs
+            && !mac.verify(&[&input[..body_end]], &input[body_end..frame_end])
+        {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "Invalid frame MAC."));
+        }
+
+        let kind = String::from_utf8(input[prefix + 1..kind_end].to_vec()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if kind.splitn(3, ':').count() != 2 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid request kind."));
+        }
+        let body = input[kind_end + 4..body_end].to_vec();
+
+        self.input.drain(..frame_end);
+        Ok(Frame::Request((request_id, kind, body)))
+    }
+
+    /// Queue a response, which is sent in the order of queueing.
+    fn respond(&mut self, request_id: Option<[u8; 4]>, response: &[u8]) {
+        let prefix: &[u8] = match &request_id {
+            Some(request_id) => request_id,
+            None => &[],
+        };
+
+        if self.output.is_empty() {
+            self.last_written = Instant::now();
+        }
+        self.output.extend_from_slice(prefix);
+        self.output.extend_from_slice(response);
+        if let Some((_, mac)) = &mut self.macs {
+            self.output.extend_from_slice(&mac.sign(&[prefix, response]));
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        while !self.output.is_empty() {
+            match self.stream.write(&self.output) {
+                Ok(0) => return Err(io::Error::new(io::ErrorKind::WriteZero, "Connection is closed by peer.")),
+                Ok(length) => {
+                    self.output.drain(..length);
+                    self.last_written = Instant::now();
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn authenticate(&mut self, secret: SecretFunction, body: &[u8]) -> io::Result<(Vec<u8>, Option<FrameMacs>)> {
+        if self.macs.is_some() {
+            return Ok((encode_response(PacketResponse::fail(255, reserved(b"Connection has been authenticated."))), None));
+        }
+
+        match body.split_first() {
+            Some((&auth::STEP_CHALLENGE, client_nonce)) if client_nonce.len() == auth::NONCE_LENGTH => {
+                let Some(secret) = secret() else {
+                    return Err(io::Error::new(io::ErrorKind::PermissionDenied, "No room is available."));
+                };
+
+                let mut server_nonce = [0u8; auth::NONCE_LENGTH];
+                OsRng.try_fill_bytes(&mut server_nonce).unwrap();
+
+                let mut response = reserved(&server_nonce);
+                response.extend_from_slice(&auth::proof(secret.as_bytes(), true, client_nonce, &server_nonce));
+
+                self.challenge = Some((client_nonce.try_into().unwrap(), server_nonce, secret));
+                Ok((encode_response(PacketResponse::ok(response)), None))
+            }
+            Some((&auth::STEP_RESPONSE, proof)) if let Some((client_nonce, server_nonce, secret)) = self.challenge.take()
+                && auth::verify_proof(secret.as_bytes(), false, &client_nonce, &server_nonce, proof) =>
+            {
+                let key = auth::session_key(secret.as_bytes(), &client_nonce, &server_nonce);
+                Ok((
+                    encode_response(PacketResponse::ok(reserved(&[]))),
+                    Some((FrameMac::new(key, auth::DIRECTION_REQUEST), FrameMac::new(key, auth::DIRECTION_RESPONSE))),
+                ))
+            }
+            _ => Err(io::Error::new(io::ErrorKind::PermissionDenied, "Authentication failed.")),
+        }
+    }
 }
 
-fn handle_connection(stream: &mut TcpStream, handlers: Handlers) -> io::Result<()> {
-    let mut kind_size = [0u8; 1];
-    stream.read_exact(&mut kind_size)?;
-    let kind_size = kind_size[0] as usize;
+type Job = Box<dyn FnOnce() + Send>;
 
-    let mut kind = vec![0u8; kind_size];
-    stream.read_exact(&mut kind)?;
-    let kind = String::from_utf8(kind).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-    let kinds = kind.splitn(3, ':').collect::<Vec<_>>();
-    if kinds.len() != 2 {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid request kind."));
+/// Threads running blocking handlers. Threads are spawned when no one is idle until there are max of them, beyond which
+/// jobs wait in the queue. Threads exit after idling for KEEP_ALIVE.
+struct Workers {
+    state: Mutex<WorkerState>,
+    signal: Condvar,
+    max: usize,
+}
+
+struct WorkerState {
+    jobs: VecDeque<Job>,
+    idle: usize,
+    threads: usize,
+}
+
+impl Workers {
+    const KEEP_ALIVE: Duration = Duration::from_secs(10);
+
+    fn new(max: usize) -> Arc<Workers> {
+        Arc::new(Workers {
+            state: Mutex::new(WorkerState { jobs: VecDeque::new(), idle: 0, threads: 0 }),
+            signal: Condvar::new(),
+            max: max.max(1),
+        })
     }
 
-    let mut body_size = [0u8; 4];
-    stream.read_exact(&mut body_size)?;
-    let body_size = u32::from_be_bytes(body_size) as usize;
+    fn submit(self: &Arc<Self>, job: Job) {
+        let mut state = self.state.lock();
+        state.jobs.push_back(job);
+        if state.idle >= state.jobs.len() {
+            self.signal.notify_one();
+        } else if state.threads < self.max {
+            state.threads += 1;
+            let workers = self.clone();
+            thread::spawn(move || workers.work());
+        }
+    }
+
+    fn work(&self) {
+        let mut state = self.state.lock();
+        loop {
+            if let Some(job) = state.jobs.pop_front() {
+                drop(state);
+                job();
+                state = self.state.lock();
+                continue;
+            }
+
+            state.idle += 1;
+            let timeout = self.signal.wait_for(&mut state, Self::KEEP_ALIVE).timed_out();
+            state.idle -= 1;
+            if timeout && state.jobs.is_empty() {
+                state.threads -= 1;
+                return;
+            }
+        }
+    }
+}
 
-    let mut body = vec![0u8; body_size];
-    stream.read_exact(&mut body)?;
+/// Handle a request directly. Long polls are answered at once, as if they have expired.
+pub(crate) fn dispatch(kind: &str, body: &[u8], handlers: &HandlerRegistry) -> Vec<u8> {
+    invoke(kind, body, handlers, true).unwrap()
+}
 
+/// Handle a request, returning the encoded response, or None if it's a long poll to be parked unless it has expired.
+fn invoke(kind: &str, body: &[u8], handlers: &HandlerRegistry, expired: bool) -> Option<Vec<u8>> {
     static DEFAULT_HANDLE: HandleFunction = |_: &[u8], mut response: Vec<u8>| -> io::Result<PacketResponse> {
         response.extend_from_slice("Requested protocol hasn't been implemented.".as_bytes());
         PacketResponse::fail(255, response)
     };
-    let handle = handlers.iter()
-        .find(|(namespace, path, _)| kinds[0] == *namespace && kinds[1] == *path)
-        .map(|(_, _, handle)| handle)
-        .unwrap_or(&DEFAULT_HANDLE);
+    let (namespace, path) = kind.split_once(':').unwrap();
 
     let mut response = Vec::with_capacity(64);
     response.resize(5, 0u8);
 
-    let (code, mut response) = match handle(&body, response) {
+    Some(encode_response(match handlers.find(namespace, path) {
+        Some(Handle::Builtin(handle)) => handle(body, response),
+        Some(Handle::Poll(handle)) => match handle(body, response, expired) {
+            Ok(Some(response)) => Ok(response),
+            Ok(None) if expired => Err(io::Error::new(io::ErrorKind::TimedOut, "Long poll isn't answered before timeout.")),
+            Ok(None) => return None,
+            Err(e) => Err(e),
+        },
+        Some(Handle::Registered(handle)) => handle(body, response),
+        None => DEFAULT_HANDLE(body, response),
+    }))
+}
+
+pub(super) fn reserved(data: &[u8]) -> Vec<u8> {
+    let mut response = Vec::with_capacity(5 + data.len());
+    response.resize(5, 0u8);
+    response.extend_from_slice(data);
+    response
+}
+
+/// Encode a response, whose data must be prefixed by 5 bytes reserved for status and length.
+pub(super) fn encode_response(response: io::Result<PacketResponse>) -> Vec<u8> {
+    let (code, mut response) = match response {
         Ok(PacketResponse::Ok { data }) => (0, data),
         Ok(PacketResponse::Fail { status, data}) => (status, data),
         Err(e) => {
@@ -82,8 +745,5 @@ fn handle_connection(stream: &mut TcpStream, handlers: Handlers) -> io::Result<(
     response[0] = code;
     let response_size = (response.len() - 5) as u32;
     response[1..5].copy_from_slice(&response_size.to_be_bytes());
-
-    stream.write_all(&response)?;
-    stream.flush()?;
-    Ok(())
-}
\ No newline at end of file
+    response
+}
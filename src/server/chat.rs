@@ -0,0 +1,39 @@
+use rocket::http::Status;
+use rocket::response::status::BadRequest;
+use rocket::serde::json::Json;
+use serde_json::{json, Value};
+
+use crate::controller;
+
+#[get("/?<cursor>")]
+fn fetch_chat(cursor: Option<u64>) -> Result<Json<Value>, Status> {
+    let Some((cursor, messages)) = controller::fetch_chat(cursor.unwrap_or(0)) else {
+        return Err(Status::BadRequest);
+    };
+
+    Ok(Json(json!({
+        "cursor": cursor,
+        "messages": messages,
+    })))
+}
+
+#[get("/send?<text>")]
+fn send_chat(text: &str) -> Result<Status, BadRequest<Json<Value>>> {
+    match controller::send_chat(text) {
+        Ok(()) => Ok(Status::Ok),
+        Err(e) => {
+            logging!("Core", "Cannot send chat message: {:?}", e);
+            Err(BadRequest(Json(e.to_json())))
+        }
+    }
+}
+
+pub fn configure(rocket: rocket::Rocket<rocket::Build>) -> rocket::Rocket<rocket::Build> {
+    rocket.mount(
+        "/chat",
+        routes![
+            fetch_chat,
+            send_chat,
+        ],
+    )
+}
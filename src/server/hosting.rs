@@ -0,0 +1,105 @@
+use rocket::http::Status;
+use rocket::response::status::BadRequest;
+use rocket::serde::json::Json;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+use crate::controller;
+
+#[get("/invite?<ttl>")]
+fn create_invite(ttl: Option<u64>) -> Result<Json<Value>, Status> {
+    let Some(invite) = controller::create_invite(Duration::from_secs(ttl.unwrap_or(60 * 60))) else {
+        return Err(Status::BadRequest);
+    };
+
+    Ok(Json(json!({
+        "invite": invite.invite,
+        "link": invite.link,
+        "expiry": invite.expiry_millis(),
+    })))
+}
+
+#[get("/invite_only?<enabled>")]
+fn set_invite_only(enabled: bool) -> Status {
+    if controller::set_invite_only(enabled) {
+        Status::Ok
+    } else {
+        Status::BadRequest
+    }
+}
+
+#[get("/approval?<enabled>")]
+fn set_approval(enabled: bool) -> Status {
+    if controller::set_approval(enabled) {
+        Status::Ok
+    } else {
+        Status::BadRequest
+    }
+}
+
+#[get("/approve?<machine_id>")]
+fn approve_join(machine_id: &str) -> Status {
+    if controller::approve_join(machine_id) {
+        Status::Ok
+    } else {
+        Status::BadRequest
+    }
+}
+
+#[get("/deny?<machine_id>")]
+fn deny_join(machine_id: &str) -> Status {
+    if controller::deny_join(machine_id) {
+        Status::Ok
+    } else {
+        Status::BadRequest
+    }
+}
+
+#[get("/world?<port>")]
+fn select_world(port: u16) -> Status {
+    if controller::select_world(port) {
+        Status::Ok
+    } else {
+        Status::BadRequest
+    }
+}
+
+#[get("/kick?<machine_id>")]
+fn kick(machine_id: &str) -> Result<Status, BadRequest<Json<Value>>> {
+    controller::kick(machine_id).map(|_| Status::Ok).map_err(|e| BadRequest(Json(e.to_json())))
+}
+
+// Bans are per machine ID, which is told by guests themselves. Hence a ban can be bypassed with another machine ID,
+// unless the room is invite-only or approves joins.
+#[get("/ban?<machine_id>")]
+fn ban(machine_id: &str) -> Result<Status, BadRequest<Json<Value>>> {
+    controller::ban(machine_id).map(|_| Status::Ok).map_err(|e| BadRequest(Json(e.to_json())))
+}
+
+#[get("/unban?<machine_id>")]
+fn unban(machine_id: &str) -> Result<Status, BadRequest<Json<Value>>> {
+    controller::unban(machine_id).map(|_| Status::Ok).map_err(|e| BadRequest(Json(e.to_json())))
+}
+
+#[get("/bans")]
+fn get_bans() -> Json<Value> {
+    Json(json!(controller::get_bans()))
+}
+
+pub fn configure(rocket: rocket::Rocket<rocket::Build>) -> rocket::Rocket<rocket::Build> {
+    rocket.mount(
+        "/room",
+        routes![
+            create_invite,
+            set_invite_only,
+            set_approval,
+            approve_join,
+            deny_join,
+            select_world,
+            kick,
+            ban,
+            unban,
+            get_bans,
+        ],
+    )
+}
@@ -5,6 +5,9 @@ use serde_json::{Value, json};
 
 use crate::{LOGGING_FILE, controller};
 
+mod chat;
+mod hosting;
+mod rooms;
 mod states;
 mod statics;
 
@@ -79,6 +82,9 @@ pub async fn server_main(port_callback: mpsc::Sender<u16>) {
     });
 
     let rocket = states::configure(rocket);
+    let rocket = rooms::configure(rocket);
+    let rocket = hosting::configure(rocket);
+    let rocket = chat::configure(rocket);
     let rocket = statics::configure(rocket);
 
     rocket
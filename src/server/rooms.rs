@@ -0,0 +1,58 @@
+use rocket::http::Status;
+use rocket::response::status::{BadRequest, NotFound};
+use rocket::serde::json::Json;
+use serde_json::Value;
+use std::path::PathBuf;
+
+use crate::controller;
+use crate::controller::SavedRoomError;
+
+#[get("/")]
+fn list_rooms() -> Json<Value> {
+    Json(Value::Array(controller::list_saved_rooms().iter().map(|saved| saved.to_json()).collect()))
+}
+
+#[get("/create?<name>&<room>&<passphrase>&<player>&<public_nodes>&<mods>&<capacity>")]
+fn create_room(
+    name: String,
+    room: Option<String>,
+    passphrase: Option<String>,
+    player: Option<String>,
+    public_nodes: Vec<String>,
+    mods: Option<String>,
+    capacity: Option<usize>,
+) -> Result<Json<Value>, BadRequest<Json<Value>>> {
+    match controller::create_saved_room(name, room, passphrase, player, public_nodes, mods.map(PathBuf::from), capacity) {
+        Ok(saved) => Ok(Json(saved.to_json())),
+        Err(e) => Err(BadRequest(Json(e.to_json()))),
+    }
+}
+
+#[get("/delete?<name>")]
+fn delete_room(name: &str) -> Result<Status, NotFound<Json<Value>>> {
+    match controller::delete_saved_room(name) {
+        Ok(()) => Ok(Status::Ok),
+        Err(e) => Err(NotFound(Json(e.to_json()))),
+    }
+}
+
+#[get("/open?<name>")]
+fn open_room(name: Option<&str>) -> Result<Json<Value>, (Status, Json<Value>)> {
+    match controller::open_saved_room(name) {
+        Ok(saved) => Ok(Json(saved.to_json())),
+        Err(e @ SavedRoomError::NotFound) => Err((Status::NotFound, Json(e.to_json()))),
+        Err(e) => Err((Status::BadRequest, Json(e.to_json()))),
+    }
+}
+
+pub fn configure(rocket: rocket::Rocket<rocket::Build>) -> rocket::Rocket<rocket::Build> {
+    rocket.mount(
+        "/rooms",
+        routes![
+            list_rooms,
+            create_room,
+            delete_room,
+            open_room,
+        ],
+    )
+}
@@ -1,9 +1,11 @@
 use rocket::http::Status;
+use rocket::response::status::BadRequest;
 use rocket::serde::json::Json;
 use serde_json::Value;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 
 use crate::controller;
-use crate::controller::Room;
 
 #[get("/")]
 fn get_state() -> Json<Value> {
@@ -16,20 +18,70 @@ fn set_state_ide() -> Status {
     Status::Ok
 }
 
-#[get("/scanning?<room>&<player>&<public_nodes>")]
-fn set_state_scanning(room: Option<String>, player: Option<String>, public_nodes: Vec<String>) -> Status {
-    controller::set_scanning(room, player, public_nodes);
-    Status::Ok
+#[get("/scanning?<room>&<passphrase>&<player>&<public_nodes>&<mods>&<capacity>&<edition>")]
+fn set_state_scanning(
+    room: Option<String>,
+    passphrase: Option<String>,
+    player: Option<String>,
+    public_nodes: Vec<String>,
+    mods: Option<String>,
+    capacity: Option<usize>,
+    edition: Option<&str>,
+) -> Status {
+    // A room must hold host and at least one guest.
+    if capacity.is_some_and(|capacity| capacity < 2) {
+        return Status::BadRequest;
+    }
+    let started = match edition {
+        None | Some("java") => {
+            controller::set_scanning(room, passphrase, player, public_nodes, mods.map(PathBuf::from), capacity)
+        }
+        Some("bedrock") => controller::set_scanning_bedrock(room, passphrase, player, public_nodes, capacity),
+        Some(_) => return Status::BadRequest,
+    };
+    if started { Status::Ok } else { Status::BadRequest }
 }
 
-#[get("/guesting?<room>&<player>&<public_nodes>")]
-fn set_state_guesting(room: &str, player: Option<String>, public_nodes: Vec<String>) -> Status {
-    if let Some(room) = Room::from(room) && controller::set_guesting(room, player, public_nodes)
-    {
-        return Status::Ok;
+#[get("/hosting?<server>&<room>&<passphrase>&<player>&<public_nodes>&<mods>&<capacity>")]
+fn set_state_hosting(
+    server: &str,
+    room: Option<String>,
+    passphrase: Option<String>,
+    player: Option<String>,
+    public_nodes: Vec<String>,
+    mods: Option<String>,
+    capacity: Option<usize>,
+) -> Status {
+    let Ok(server) = server.parse::<SocketAddr>() else {
+        return Status::BadRequest;
+    };
+    if capacity.is_some_and(|capacity| capacity < 2) {
+        return Status::BadRequest;
+    }
+    if controller::set_hosting(server, room, passphrase, player, public_nodes, mods.map(PathBuf::from), capacity) {
+        Status::Ok
+    } else {
+        Status::BadRequest
     }
+}
 
-    Status::BadRequest
+#[get("/guesting?<room>&<passphrase>&<invite>&<player>&<public_nodes>&<mods>&<motd>")]
+fn set_state_guesting(
+    room: &str,
+    passphrase: Option<&str>,
+    invite: Option<String>,
+    player: Option<String>,
+    public_nodes: Vec<String>,
+    mods: Option<String>,
+    motd: Option<String>,
+) -> Result<Status, BadRequest<Json<Value>>> {
+    match controller::set_guesting(room, passphrase, invite, player, public_nodes, mods.map(PathBuf::from), motd) {
+        Ok(()) => Ok(Status::Ok),
+        Err(e) => {
+            logging!("Core", "Cannot join room {:?}: {:?}", room, e);
+            Err(BadRequest(Json(e.to_json())))
+        }
+    }
 }
 
 pub fn configure(rocket: rocket::Rocket<rocket::Build>) -> rocket::Rocket<rocket::Build> {
@@ -39,6 +91,7 @@ pub fn configure(rocket: rocket::Rocket<rocket::Build>) -> rocket::Rocket<rocket
             get_state,
             set_state_ide,
             set_state_scanning,
+            set_state_hosting,
             set_state_guesting,
         ],
     )